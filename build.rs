@@ -0,0 +1,4 @@
+fn main() {
+    // New migration directories are embedded at compile time
+    println!("cargo:rerun-if-changed=migrations");
+}
@@ -0,0 +1,2 @@
+DROP INDEX IF EXISTS action_runs_user_id_started_on;
+DROP TABLE IF EXISTS action_runs;
\ No newline at end of file
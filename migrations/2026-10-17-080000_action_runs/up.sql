@@ -0,0 +1,17 @@
+CREATE TABLE action_runs (
+    id INTEGER NOT NULL PRIMARY KEY,
+    user_id INT NOT NULL,
+    action_id INT,
+    request_id INT,
+    trigger_source VARCHAR NOT NULL,
+    trigger_detail VARCHAR,
+    started_on TIMESTAMP NOT NULL,
+    finished_on TIMESTAMP NOT NULL,
+    outcome VARCHAR NOT NULL,
+    status_code INT,
+    response TEXT,
+    error TEXT,
+    FOREIGN KEY (user_id) REFERENCES user_users (id)
+);
+
+CREATE INDEX action_runs_user_id_started_on ON action_runs (user_id, started_on);
@@ -0,0 +1,7 @@
+CREATE TABLE api_tokens (
+    id INTEGER NOT NULL PRIMARY KEY,
+    name TEXT NOT NULL,
+    token_hash TEXT NOT NULL UNIQUE,
+    created_on TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    last_used_on TIMESTAMP
+);
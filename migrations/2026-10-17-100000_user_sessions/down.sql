@@ -0,0 +1,2 @@
+DROP TABLE IF EXISTS rfid_tags;
+ALTER TABLE user_users DROP COLUMN pin_hash;
\ No newline at end of file
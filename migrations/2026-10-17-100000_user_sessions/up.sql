@@ -0,0 +1,9 @@
+ALTER TABLE user_users ADD COLUMN pin_hash TEXT;
+
+CREATE TABLE rfid_tags (
+    id INTEGER NOT NULL PRIMARY KEY,
+    user_id INT NOT NULL,
+    rfid_uid TEXT NOT NULL UNIQUE,
+    created_on TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    FOREIGN KEY (user_id) REFERENCES user_users (id)
+);
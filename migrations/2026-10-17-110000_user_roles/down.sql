@@ -0,0 +1 @@
+ALTER TABLE user_users DROP COLUMN role;
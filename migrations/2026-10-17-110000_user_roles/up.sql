@@ -0,0 +1,4 @@
+ALTER TABLE user_users ADD COLUMN role TEXT DEFAULT 'member' NOT NULL;
+
+-- Keep existing installations manageable by promoting the oldest profile
+UPDATE user_users SET role = 'admin' WHERE id = (SELECT MIN(id) FROM user_users);
@@ -0,0 +1,67 @@
+CREATE TABLE old_action_runs (
+    id INTEGER NOT NULL PRIMARY KEY,
+    user_id INT NOT NULL,
+    action_id INT,
+    request_id INT,
+    trigger_source VARCHAR NOT NULL,
+    trigger_detail VARCHAR,
+    started_on TIMESTAMP NOT NULL,
+    finished_on TIMESTAMP NOT NULL,
+    outcome VARCHAR NOT NULL,
+    status_code INT,
+    response TEXT,
+    error TEXT,
+    FOREIGN KEY (user_id) REFERENCES user_users (id)
+);
+INSERT INTO old_action_runs SELECT * FROM action_runs;
+DROP TABLE action_runs;
+ALTER TABLE old_action_runs RENAME TO action_runs;
+CREATE INDEX action_runs_user_id_started_on ON action_runs (user_id, started_on);
+
+CREATE TABLE old_rfid_tags (
+    id INTEGER NOT NULL PRIMARY KEY,
+    user_id INT NOT NULL,
+    rfid_uid TEXT NOT NULL UNIQUE,
+    created_on TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    FOREIGN KEY (user_id) REFERENCES user_users (id)
+);
+INSERT INTO old_rfid_tags SELECT * FROM rfid_tags;
+DROP TABLE rfid_tags;
+ALTER TABLE old_rfid_tags RENAME TO rfid_tags;
+
+CREATE TABLE old_constants (
+    id INTEGER NOT NULL PRIMARY KEY,
+    name VARCHAR(128) NOT NULL,
+    user_id INT NOT NULL,
+    value TEXT NOT NULL,
+    FOREIGN KEY (user_id) REFERENCES user_users (id)
+);
+INSERT INTO old_constants SELECT * FROM constants;
+DROP TABLE constants;
+ALTER TABLE old_constants RENAME TO constants;
+
+CREATE TABLE old_user_requests (
+    id INTEGER NOT NULL PRIMARY KEY,
+    user_id INT NOT NULL,
+    name VARCHAR NOT NULL,
+    endpoint VARCHAR NOT NULL,
+    parameters TEXT NOT NULL,
+    created_on TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    FOREIGN KEY (user_id) REFERENCES user_users (id)
+);
+INSERT INTO old_user_requests SELECT * FROM user_requests;
+DROP TABLE user_requests;
+ALTER TABLE old_user_requests RENAME TO user_requests;
+
+CREATE TABLE old_user_actions (
+    id INTEGER NOT NULL PRIMARY KEY,
+    user_id INT NOT NULL,
+    rfid_uid VARCHAR NOT NULL,
+    type_name VARCHAR NOT NULL,
+    details TEXT NOT NULL,
+    created_on TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    FOREIGN KEY (user_id) REFERENCES user_users (id)
+);
+INSERT INTO old_user_actions SELECT * FROM user_actions;
+DROP TABLE user_actions;
+ALTER TABLE old_user_actions RENAME TO user_actions;
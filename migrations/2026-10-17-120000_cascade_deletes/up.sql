@@ -0,0 +1,81 @@
+-- SQLite can't alter foreign keys, so every table referencing users is rebuilt.
+-- Rows left behind by users deleted before foreign keys were enforced are dropped.
+
+CREATE TABLE new_user_actions (
+    id INTEGER NOT NULL PRIMARY KEY,
+    user_id INT NOT NULL,
+    rfid_uid VARCHAR NOT NULL,
+    type_name VARCHAR NOT NULL,
+    details TEXT NOT NULL,
+    created_on TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    FOREIGN KEY (user_id) REFERENCES user_users (id) ON DELETE CASCADE
+);
+INSERT INTO new_user_actions SELECT * FROM user_actions WHERE user_id IN (SELECT id FROM user_users);
+DROP TABLE user_actions;
+ALTER TABLE new_user_actions RENAME TO user_actions;
+CREATE INDEX user_actions_user_id ON user_actions (user_id);
+
+CREATE TABLE new_user_requests (
+    id INTEGER NOT NULL PRIMARY KEY,
+    user_id INT NOT NULL,
+    name VARCHAR NOT NULL,
+    endpoint VARCHAR NOT NULL,
+    parameters TEXT NOT NULL,
+    created_on TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    FOREIGN KEY (user_id) REFERENCES user_users (id) ON DELETE CASCADE
+);
+INSERT INTO new_user_requests SELECT * FROM user_requests WHERE user_id IN (SELECT id FROM user_users);
+DROP TABLE user_requests;
+ALTER TABLE new_user_requests RENAME TO user_requests;
+CREATE INDEX user_requests_user_id ON user_requests (user_id);
+
+CREATE TABLE new_constants (
+    id INTEGER NOT NULL PRIMARY KEY,
+    name VARCHAR(128) NOT NULL,
+    user_id INT NOT NULL,
+    value TEXT NOT NULL,
+    FOREIGN KEY (user_id) REFERENCES user_users (id) ON DELETE CASCADE
+);
+INSERT INTO new_constants SELECT * FROM constants WHERE user_id IN (SELECT id FROM user_users);
+DROP TABLE constants;
+ALTER TABLE new_constants RENAME TO constants;
+CREATE INDEX constants_user_id ON constants (user_id);
+
+CREATE TABLE new_rfid_tags (
+    id INTEGER NOT NULL PRIMARY KEY,
+    user_id INT NOT NULL,
+    rfid_uid TEXT NOT NULL UNIQUE,
+    created_on TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    FOREIGN KEY (user_id) REFERENCES user_users (id) ON DELETE CASCADE
+);
+INSERT INTO new_rfid_tags SELECT * FROM rfid_tags WHERE user_id IN (SELECT id FROM user_users);
+DROP TABLE rfid_tags;
+ALTER TABLE new_rfid_tags RENAME TO rfid_tags;
+
+-- Rebuilt last, dropping the old actions and requests must not touch the new history
+CREATE TABLE new_action_runs (
+    id INTEGER NOT NULL PRIMARY KEY,
+    user_id INT NOT NULL,
+    action_id INT,
+    request_id INT,
+    trigger_source VARCHAR NOT NULL,
+    trigger_detail VARCHAR,
+    started_on TIMESTAMP NOT NULL,
+    finished_on TIMESTAMP NOT NULL,
+    outcome VARCHAR NOT NULL,
+    status_code INT,
+    response TEXT,
+    error TEXT,
+    FOREIGN KEY (user_id) REFERENCES user_users (id) ON DELETE CASCADE,
+    FOREIGN KEY (action_id) REFERENCES user_actions (id) ON DELETE SET NULL,
+    FOREIGN KEY (request_id) REFERENCES user_requests (id) ON DELETE SET NULL
+);
+INSERT INTO new_action_runs
+SELECT id, user_id,
+       CASE WHEN action_id IN (SELECT id FROM user_actions) THEN action_id END,
+       CASE WHEN request_id IN (SELECT id FROM user_requests) THEN request_id END,
+       trigger_source, trigger_detail, started_on, finished_on, outcome, status_code, response, error
+FROM action_runs WHERE user_id IN (SELECT id FROM user_users);
+DROP TABLE action_runs;
+ALTER TABLE new_action_runs RENAME TO action_runs;
+CREATE INDEX action_runs_user_id_started_on ON action_runs (user_id, started_on);
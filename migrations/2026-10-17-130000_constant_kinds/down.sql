@@ -0,0 +1 @@
+ALTER TABLE constants DROP COLUMN kind;
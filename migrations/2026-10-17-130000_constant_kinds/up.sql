@@ -0,0 +1 @@
+ALTER TABLE constants ADD COLUMN kind TEXT DEFAULT 'string' NOT NULL;
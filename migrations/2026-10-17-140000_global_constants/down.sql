@@ -0,0 +1,12 @@
+CREATE TABLE new_constants (
+    id INTEGER NOT NULL PRIMARY KEY,
+    name VARCHAR(128) NOT NULL,
+    user_id INT NOT NULL,
+    value TEXT NOT NULL,
+    kind TEXT DEFAULT 'string' NOT NULL,
+    FOREIGN KEY (user_id) REFERENCES user_users (id) ON DELETE CASCADE
+);
+INSERT INTO new_constants SELECT id, name, user_id, value, kind FROM constants WHERE user_id IS NOT NULL;
+DROP TABLE constants;
+ALTER TABLE new_constants RENAME TO constants;
+CREATE INDEX constants_user_id ON constants (user_id);
@@ -0,0 +1,15 @@
+-- Constants without an owner are shared by every user, a user's constant with the same name overrides them
+
+CREATE TABLE new_constants (
+    id INTEGER NOT NULL PRIMARY KEY,
+    name VARCHAR(128) NOT NULL,
+    user_id INT,
+    value TEXT NOT NULL,
+    kind TEXT DEFAULT 'string' NOT NULL,
+    FOREIGN KEY (user_id) REFERENCES user_users (id) ON DELETE CASCADE
+);
+INSERT INTO new_constants SELECT id, name, user_id, value, kind FROM constants;
+DROP TABLE constants;
+ALTER TABLE new_constants RENAME TO constants;
+CREATE INDEX constants_user_id ON constants (user_id);
+CREATE UNIQUE INDEX constants_global_name ON constants (name) WHERE user_id IS NULL;
@@ -1,84 +1,125 @@
 use std::sync::Arc;
 
-use axum::extract::{Json, Path, State};
+use axum::extract::{Path, Query, State};
 use axum::http::StatusCode;
+use axum::response::Response;
 use diesel::r2d2::{ConnectionManager, PooledConnection};
 use diesel::SqliteConnection;
+use validator::Validate;
 
-use crate::api::{AppState, ErrorMessage, internal_error};
+use crate::api::{AppState, internal_error};
+use crate::api::error::ApiError;
+use crate::api::extract::Json;
+use crate::api::listing::{list_response, ListQuery};
+use crate::api::permissions::{authorize, Permission};
 use crate::models::user_actions::{NewUserAction, UserAction, UserActionChangeset};
 
+#[utoipa::path(
+    get,
+    path = "/actions/{user_id}",
+    tag = "actions",
+    params(ListQuery),
+    responses(
+        (status = 200, description = "OK", body = [UserAction], headers(
+            ("x-total-count" = i64, description = "Number of matching items"),
+            ("x-next-cursor" = String, description = "Offset of the next page as an opaque cursor, missing on the last page"),
+        )),
+    ),
+)]
 pub async fn get_user_actions_by_user_id(
     Path(user_id): Path<i32>,
+    Query(query): Query<ListQuery>,
     State(state): State<Arc<AppState>>,
-) -> Result<Json<Vec<UserAction>>, (StatusCode, Json<ErrorMessage>)> {
+) -> Result<Response, ApiError> {
+    let listing = query.listing(&["id", "rfid_uid", "type_name", "created_on"])?;
     let mut conn = state.db_pool.get().map_err(internal_error)?;
 
-    let user_actions_result = UserAction::get_all_by_user_id(user_id, &mut conn);
+    let user_actions_result = UserAction::list_by_user_id(user_id, &listing, &mut conn);
 
     match user_actions_result {
-        Ok(user_actions) => Ok(Json(user_actions)),
-        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorMessage { message: "Failed to load user actions".to_string() }))),
+        Ok((user_actions, total)) => Ok(list_response(user_actions, total, &listing)),
+        Err(_) => Err(ApiError::Internal("Failed to load user actions".to_string())),
     }
 }
 
+#[utoipa::path(
+    post,
+    path = "/actions",
+    tag = "actions",
+    responses((status = 201, description = "Created")),
+)]
 pub async fn post_user_action(
     State(state): State<Arc<AppState>>,
     Json(new_user_action): Json<NewUserAction>,
-) -> Result<StatusCode, (StatusCode, Json<ErrorMessage>)> {
+) -> Result<StatusCode, ApiError> {
+    authorize(&state, Permission::UserData(new_user_action.user_id))?;
+    new_user_action.validate()?;
     let mut conn = state.db_pool.get().map_err(internal_error)?;
 
-    if user_action_exists(&new_user_action.rfid_uid, &mut conn) {
-        return Err((StatusCode::FORBIDDEN, Json(ErrorMessage { message: "Action with the same rfid already exists".to_string() })));
+    if user_action_exists(&new_user_action.rfid_uid, None, &mut conn) {
+        return Err(ApiError::Conflict("Action with the same rfid already exists".to_string()));
     }
 
-    let create_result = UserAction::create(new_user_action, &mut conn);
-
-    match create_result {
-        Ok(_) => Ok(StatusCode::CREATED),
-        Err(_) => Err((StatusCode::BAD_REQUEST, Json(ErrorMessage { message: "Failed to create user action".to_string() }))),
-    }
+    UserAction::create(new_user_action, &mut conn).map_err(ApiError::database("User action"))?;
+    Ok(StatusCode::CREATED)
 }
 
+#[utoipa::path(
+    delete,
+    path = "/actions/{action_id}",
+    tag = "actions",
+    responses((status = 204, description = "Done")),
+)]
 pub async fn delete_user_action_by_id(
     Path(action_id): Path<i32>,
     State(state): State<Arc<AppState>>,
-) -> Result<StatusCode, (StatusCode, Json<ErrorMessage>)> {
+) -> Result<StatusCode, ApiError> {
+    authorize_action(&state, action_id)?;
     let mut conn = state.db_pool.get().map_err(internal_error)?;
 
-    let delete_result = UserAction::delete_by_id(action_id, &mut conn);
-
-    match delete_result {
-        Ok(_) => Ok(StatusCode::NO_CONTENT),
-        Err(_) => Err((StatusCode::BAD_REQUEST, Json(ErrorMessage { message: "Failed to delete user action".to_string() }))),
-    }
+    UserAction::delete_by_id(action_id, &mut conn).map_err(ApiError::database("User action"))?;
+    Ok(StatusCode::NO_CONTENT)
 }
 
+#[utoipa::path(
+    put,
+    path = "/actions/{action_id}",
+    tag = "actions",
+    responses((status = 204, description = "Done")),
+)]
 pub async fn put_user_action(
     Path(action_id): Path<i32>,
     State(state): State<Arc<AppState>>,
     Json(changes): Json<UserActionChangeset>,
-) -> Result<StatusCode, (StatusCode, Json<ErrorMessage>)> {
+) -> Result<StatusCode, ApiError> {
+    // Moving an action to another user needs access to both
+    authorize_action(&state, action_id)?;
+    authorize(&state, Permission::UserData(changes.user_id))?;
+    changes.validate()?;
     let mut conn = state.db_pool.get().map_err(internal_error)?;
 
-    if user_action_exists(&changes.rfid_uid, &mut conn) {
-        return Err((StatusCode::FORBIDDEN, Json(ErrorMessage { message: "Action with the same rfid already exists".to_string() })));
+    if user_action_exists(&changes.rfid_uid, Some(action_id), &mut conn) {
+        return Err(ApiError::Conflict("Action with the same rfid already exists".to_string()));
     }
 
-    let update_result = UserAction::update(action_id, changes, &mut conn);
+    UserAction::update(action_id, changes, &mut conn).map_err(ApiError::database("User action"))?;
+    Ok(StatusCode::NO_CONTENT)
+}
 
-    match update_result {
-        Ok(_) => Ok(StatusCode::NO_CONTENT),
-        Err(_) => Err((StatusCode::BAD_REQUEST, Json(ErrorMessage { message: "Failed to update user action".to_string() }))),
-    }
+fn authorize_action(state: &AppState, action_id: i32) -> Result<(), ApiError> {
+    let owner = {
+        let mut conn = state.db_pool.get().map_err(internal_error)?;
+        UserAction::get_by_id(action_id, &mut conn).map_err(ApiError::database("User action"))?.user_id
+    };
+
+    authorize(state, Permission::UserData(owner)).map(|_| ())
 }
 
-fn user_action_exists(rfid_uid: &String, conn: &mut PooledConnection<ConnectionManager<SqliteConnection>>) -> bool {
+/// Whether another action than `except` already uses the uid
+fn user_action_exists(rfid_uid: &String, except: Option<i32>, conn: &mut PooledConnection<ConnectionManager<SqliteConnection>>) -> bool {
     let actions_result = UserAction::get_all_by_rfid_id(rfid_uid, conn);
     if let Ok(actions) = actions_result {
-        if !actions.is_empty() {
-            return true;
-        }
+        return actions.iter().any(|action| Some(action.id) != except);
     }
     false
 }
@@ -0,0 +1,399 @@
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use axum::extract::{ConnectInfo, Path, Query, Request, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::http::header::AUTHORIZATION;
+use axum::middleware::Next;
+use axum::response::Response;
+use diesel::result::Error as DieselError;
+use log::{info, warn};
+use rand::Rng;
+use serde_derive::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use utoipa::ToSchema;
+
+use crate::api::{AppState, internal_error};
+use crate::api::error::ApiError;
+use crate::api::extract::Json;
+use crate::api::permissions::{authorize, Permission};
+use crate::models::api_tokens::{ApiToken, NewApiToken};
+use crate::models::events::{PairingCompleted, PairingRequested};
+use crate::models::websocket::{OpCode, WebSocketMessage};
+
+const PAIRING_TTL: Duration = Duration::from_secs(120);
+/// Pairings waiting at the same time, each address may only have one of them
+const MAX_PENDING_PAIRINGS: usize = 3;
+/// Wrong codes per address within `PAIRING_FAILURE_WINDOW`
+const MAX_PAIRING_ATTEMPTS: usize = 5;
+/// Wrong codes from all addresses within `PAIRING_FAILURE_WINDOW`, so more addresses don't mean more guesses
+const MAX_PAIRING_FAILURES: usize = 20;
+const PAIRING_FAILURE_WINDOW: Duration = Duration::from_secs(600);
+
+/// Pairing requests waiting for the code shown on the kiosk
+#[derive(Default)]
+pub struct Pairings {
+    state: Mutex<PairingState>,
+}
+
+#[derive(Default)]
+struct PairingState {
+    pending: HashMap<String, PendingPairing>,
+    /// Wrong codes within `PAIRING_FAILURE_WINDOW`, by the address that sent them
+    failures: Vec<(IpAddr, Instant)>,
+}
+
+struct PendingPairing {
+    name: String,
+    code: String,
+    address: IpAddr,
+    expires: Instant,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct PairingRequest {
+    pub name: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct PairingResponse {
+    pub pairing_id: String,
+    pub expires_in: u64,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct PairingConfirmation {
+    pub code: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct IssuedToken {
+    pub id: i32,
+    pub name: String,
+    /// Only returned once, the backend stores a hash
+    pub token: String,
+}
+
+#[derive(Deserialize)]
+struct TokenQuery {
+    token: Option<String>,
+}
+
+impl Pairings {
+    /// Returns the id and code of a new pairing
+    fn start(&self, address: IpAddr, name: &str) -> Result<(String, String), ApiError> {
+        let mut state = self.lock();
+        state.check_failures(address)?;
+
+        if state.pending.values().any(|pairing| pairing.address == address) {
+            return Err(ApiError::TooManyRequests("A pairing from this address is already waiting".to_string()));
+        }
+        if state.pending.len() >= MAX_PENDING_PAIRINGS {
+            return Err(ApiError::TooManyRequests("Too many pairings are waiting, try again later".to_string()));
+        }
+
+        let pairing_id = random_hex(16);
+        let code = format!("{:06}", rand::thread_rng().gen_range(0..1_000_000));
+
+        state.pending.insert(pairing_id.clone(), PendingPairing {
+            name: name.to_string(),
+            code: code.clone(),
+            address,
+            expires: Instant::now() + PAIRING_TTL,
+        });
+
+        Ok((pairing_id, code))
+    }
+
+    /// Returns the device name once the code matches
+    fn confirm(&self, address: IpAddr, pairing_id: &str, code: &str) -> Result<String, ApiError> {
+        let mut state = self.lock();
+        state.check_failures(address)?;
+
+        let Some(pairing) = state.pending.get(pairing_id) else {
+            return Err(ApiError::NotFound("Pairing request not found or expired".to_string()));
+        };
+
+        if pairing.code != code.trim() {
+            warn!("Wrong pairing code from {}", address);
+            state.failures.push((address, Instant::now()));
+            return Err(ApiError::Forbidden("Invalid pairing code".to_string()));
+        }
+
+        Ok(state.pending.remove(pairing_id).map(|pairing| pairing.name).unwrap_or_default())
+    }
+
+    fn lock(&self) -> MutexGuard<'_, PairingState> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let now = Instant::now();
+        state.pending.retain(|_, pairing| pairing.expires > now);
+        state.failures.retain(|(_, at)| now.duration_since(*at) < PAIRING_FAILURE_WINDOW);
+        state
+    }
+}
+
+impl PairingState {
+    fn check_failures(&self, address: IpAddr) -> Result<(), ApiError> {
+        let from_address = self.failures.iter().filter(|(failed, _)| *failed == address).count();
+
+        if from_address >= MAX_PAIRING_ATTEMPTS || self.failures.len() >= MAX_PAIRING_FAILURES {
+            return Err(ApiError::TooManyRequests("Too many invalid pairing codes, try again later".to_string()));
+        }
+        Ok(())
+    }
+}
+
+/// Starts pairing a new device. The code is only shown on the kiosk, so whoever
+/// confirms it must be standing in front of the display.
+#[utoipa::path(
+    post,
+    path = "/auth/pair",
+    tag = "auth",
+    security(()),
+    responses(
+        (status = 202, description = "Pairing started, the code is shown on the kiosk", body = PairingResponse),
+    ),
+)]
+pub async fn post_pairing(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(address): ConnectInfo<SocketAddr>,
+    Json(request): Json<PairingRequest>,
+) -> Result<(StatusCode, Json<PairingResponse>), ApiError> {
+    let name = request.name.trim().to_string();
+    if name.is_empty() {
+        return Err(ApiError::field("name", "Device name cannot be empty"));
+    }
+
+    let (pairing_id, code) = state.pairings.start(address.ip().to_canonical(), &name)?;
+
+    state.tx.send(WebSocketMessage {
+        t: Some("PAIRING_REQUESTED".to_string()),
+        op: OpCode::System,
+        d: Some(json!(PairingRequested {
+            pairing_id: pairing_id.clone(),
+            name,
+            code,
+            expires_in: PAIRING_TTL.as_secs(),
+        })),
+    });
+
+    Ok((StatusCode::ACCEPTED, Json(PairingResponse { pairing_id, expires_in: PAIRING_TTL.as_secs() })))
+}
+
+#[utoipa::path(
+    post,
+    path = "/auth/pair/{pairing_id}",
+    tag = "auth",
+    security(()),
+    responses((status = 201, description = "Created", body = IssuedToken)),
+)]
+pub async fn post_pairing_confirmation(
+    Path(pairing_id): Path<String>,
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(address): ConnectInfo<SocketAddr>,
+    Json(confirmation): Json<PairingConfirmation>,
+) -> Result<(StatusCode, Json<IssuedToken>), ApiError> {
+    let name = state.pairings.confirm(address.ip().to_canonical(), &pairing_id, &confirmation.code)?;
+
+    let token = random_hex(32);
+    let mut conn = state.db_pool.get().map_err(internal_error)?;
+    let api_token = ApiToken::create(NewApiToken { name, token_hash: hash_token(&token) }, &mut conn).map_err(internal_error)?;
+
+    info!("Paired device {} ({})", api_token.name, api_token.id);
+
+    state.tx.send(WebSocketMessage {
+        t: Some("PAIRING_COMPLETED".to_string()),
+        op: OpCode::System,
+        d: Some(json!(PairingCompleted { pairing_id, name: api_token.name.clone() })),
+    });
+
+    Ok((StatusCode::CREATED, Json(IssuedToken { id: api_token.id, name: api_token.name, token })))
+}
+
+#[utoipa::path(
+    get,
+    path = "/auth/tokens",
+    tag = "auth",
+    responses((status = 200, description = "OK", body = [ApiToken])),
+)]
+pub async fn get_tokens(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<Vec<ApiToken>>, ApiError> {
+    authorize(&state, Permission::ManageSystem)?;
+    let mut conn = state.db_pool.get().map_err(internal_error)?;
+
+    match ApiToken::all(&mut conn) {
+        Ok(tokens) => Ok(Json(tokens)),
+        Err(_) => Err(ApiError::Internal("Failed to load tokens".to_string())),
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/auth/tokens/{id}",
+    tag = "auth",
+    responses((status = 204, description = "Done")),
+)]
+pub async fn delete_token(
+    Path(id): Path<i32>,
+    State(state): State<Arc<AppState>>,
+) -> Result<StatusCode, ApiError> {
+    authorize(&state, Permission::ManageSystem)?;
+    let mut conn = state.db_pool.get().map_err(internal_error)?;
+
+    match ApiToken::delete(id, &mut conn) {
+        Ok(0) => Err(ApiError::NotFound("Token not found".to_string())),
+        Ok(_) => Ok(StatusCode::NO_CONTENT),
+        Err(_) => Err(ApiError::Internal("Failed to revoke token".to_string())),
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/auth/tokens",
+    tag = "auth",
+    responses((status = 204, description = "Done")),
+)]
+pub async fn delete_tokens(
+    State(state): State<Arc<AppState>>,
+) -> Result<StatusCode, ApiError> {
+    authorize(&state, Permission::ManageSystem)?;
+    let mut conn = state.db_pool.get().map_err(internal_error)?;
+
+    match ApiToken::delete_all(&mut conn) {
+        Ok(_) => Ok(StatusCode::NO_CONTENT),
+        Err(_) => Err(ApiError::Internal("Failed to revoke tokens".to_string())),
+    }
+}
+
+/// Rejects requests without a valid API token. In kiosk mode requests from the
+/// device itself don't need one.
+pub async fn require_token(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(address): ConnectInfo<SocketAddr>,
+    request: Request,
+    next: Next,
+) -> Result<Response, ApiError> {
+    let kiosk_mode = state.config.borrow().auth.kiosk_mode;
+    if kiosk_mode && address.ip().to_canonical().is_loopback() {
+        return Ok(next.run(request).await);
+    }
+
+    // Browsers can't set headers on the WebSocket upgrade, so it may pass the token in the query
+    let token = bearer_token(request.headers()).or_else(|| {
+        if request.uri().path() == "/ws" {
+            Query::<TokenQuery>::try_from_uri(request.uri()).ok().and_then(|query| query.0.token)
+        } else {
+            None
+        }
+    });
+
+    let Some(token) = token else {
+        return Err(ApiError::Unauthorized("Missing API token".to_string()));
+    };
+
+    {
+        let mut conn = state.db_pool.get().map_err(internal_error)?;
+
+        match ApiToken::get_by_hash(&hash_token(&token), &mut conn) {
+            Ok(api_token) => {
+                if let Err(e) = ApiToken::mark_used(api_token.id, &mut conn) {
+                    warn!("Failed to update token usage: {}", e);
+                }
+            }
+            Err(DieselError::NotFound) => {
+                warn!("Rejected invalid API token from {}", address);
+                return Err(ApiError::Unauthorized("Invalid API token".to_string()));
+            }
+            Err(e) => return Err(internal_error(e)),
+        }
+    }
+
+    Ok(next.run(request).await)
+}
+
+fn bearer_token(headers: &HeaderMap) -> Option<String> {
+    headers.get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(|token| token.trim().to_string())
+}
+
+fn hash_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+fn random_hex(bytes: usize) -> String {
+    let mut rng = rand::thread_rng();
+    (0..bytes).map(|_| format!("{:02x}", rng.gen::<u8>())).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn address(last: u8) -> IpAddr {
+        IpAddr::from([192, 168, 1, last])
+    }
+
+    fn wrong_code(code: &str) -> String {
+        format!("{:06}", (code.parse::<u32>().unwrap() + 1) % 1_000_000)
+    }
+
+    fn is_too_many(result: Result<impl Sized, ApiError>) -> bool {
+        matches!(result, Err(ApiError::TooManyRequests(_)))
+    }
+
+    #[test]
+    fn confirms_the_right_code() {
+        let pairings = Pairings::default();
+        let (id, code) = pairings.start(address(1), "Phone").unwrap();
+
+        assert_eq!(pairings.confirm(address(1), &id, &code).unwrap(), "Phone");
+        assert!(matches!(pairings.confirm(address(1), &id, &code), Err(ApiError::NotFound(_))));
+    }
+
+    #[test]
+    fn limits_pending_pairings() {
+        let pairings = Pairings::default();
+
+        pairings.start(address(1), "Phone").unwrap();
+        assert!(is_too_many(pairings.start(address(1), "Phone")));
+
+        for last in 2..=MAX_PENDING_PAIRINGS as u8 {
+            pairings.start(address(last), "Phone").unwrap();
+        }
+        assert!(is_too_many(pairings.start(address(100), "Phone")));
+    }
+
+    #[test]
+    fn limits_attempts_per_address_across_pairings() {
+        let pairings = Pairings::default();
+        let (id, code) = pairings.start(address(1), "Phone").unwrap();
+
+        for _ in 0..MAX_PAIRING_ATTEMPTS {
+            assert!(matches!(pairings.confirm(address(2), &id, &wrong_code(&code)), Err(ApiError::Forbidden(_))));
+        }
+
+        // Blocked even with the right code or a new pairing, other addresses may go on
+        assert!(is_too_many(pairings.confirm(address(2), &id, &code)));
+        assert!(is_too_many(pairings.start(address(2), "Phone")));
+        assert_eq!(pairings.confirm(address(1), &id, &code).unwrap(), "Phone");
+    }
+
+    #[test]
+    fn limits_attempts_from_all_addresses() {
+        let pairings = Pairings::default();
+        let (id, code) = pairings.start(address(1), "Phone").unwrap();
+
+        for failure in 0..MAX_PAIRING_FAILURES {
+            let from = address(10 + (failure / (MAX_PAIRING_ATTEMPTS - 1)) as u8);
+            assert!(matches!(pairings.confirm(from, &id, &wrong_code(&code)), Err(ApiError::Forbidden(_))));
+        }
+
+        assert!(is_too_many(pairings.confirm(address(200), &id, &code)));
+    }
+}
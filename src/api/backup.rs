@@ -0,0 +1,97 @@
+use std::fs;
+use std::sync::Arc;
+
+use axum::body::Bytes;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::http::header::{CONTENT_DISPOSITION, CONTENT_TYPE};
+use axum::response::{IntoResponse, Response};
+use log::{info, warn};
+use serde_json::json;
+
+use crate::api::AppState;
+use crate::api::error::ApiError;
+use crate::api::permissions::{authorize, Permission};
+use crate::handlers::backup_handler::{self, BackupError};
+use crate::models::events::DatabaseRestored;
+use crate::models::websocket::{OpCode, WebSocketMessage};
+
+/// Uploaded databases larger than this are rejected
+pub const MAX_RESTORE_SIZE: usize = 64 * 1024 * 1024;
+
+/// Returns a consistent copy of the database for download
+#[utoipa::path(
+    post,
+    path = "/system/backup",
+    tag = "system",
+    responses(
+        (status = 200, description = "SQLite database file", content_type = "application/vnd.sqlite3", body = Vec<u8>),
+    ),
+)]
+pub async fn post_backup(
+    State(state): State<Arc<AppState>>,
+) -> Result<Response, ApiError> {
+    authorize(&state, Permission::ManageSystem)?;
+
+    let pool = state.db_pool.clone();
+    let data = tokio::task::spawn_blocking(move || {
+        let path = backup_handler::temp_path("backup");
+        let data = backup_handler::backup_to(&pool, &path).and_then(|_| Ok(fs::read(&path)?));
+
+        if let Err(e) = fs::remove_file(&path) {
+            warn!("Failed to remove temporary backup {}: {}", path.display(), e);
+        }
+        data
+    }).await.map_err(|e| backup_error(BackupError::Io(e.into())))?.map_err(backup_error)?;
+
+    let file_name = format!("smarthub-{}.db", chrono::Local::now().format("%Y%m%d-%H%M%S"));
+
+    Ok((
+        [
+            (CONTENT_TYPE, "application/vnd.sqlite3".to_string()),
+            (CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", file_name)),
+        ],
+        data,
+    ).into_response())
+}
+
+/// Replaces all data with an uploaded backup, sent as the raw request body
+#[utoipa::path(
+    post,
+    path = "/system/restore",
+    tag = "system",
+    request_body(content = Vec<u8>, description = "SQLite database file", content_type = "application/octet-stream"),
+    responses((status = 204, description = "Done")),
+)]
+pub async fn post_restore(
+    State(state): State<Arc<AppState>>,
+    body: Bytes,
+) -> Result<StatusCode, ApiError> {
+    authorize(&state, Permission::ManageSystem)?;
+
+    let pool = state.db_pool.clone();
+    let conf = state.backup.clone();
+    tokio::task::spawn_blocking(move || backup_handler::restore(&pool, &conf, &body))
+        .await
+        .map_err(|e| backup_error(BackupError::Io(e.into())))?
+        .map_err(backup_error)?;
+
+    info!("Restored database from backup");
+
+    // The logged in user may not exist anymore, and every client has to reload its data
+    state.session.logout(&state.tx, "restore");
+    state.tx.send(WebSocketMessage {
+        t: Some("DATABASE_RESTORED".to_string()),
+        op: OpCode::System,
+        d: Some(json!(DatabaseRestored {})),
+    });
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+fn backup_error(error: BackupError) -> ApiError {
+    match error {
+        BackupError::Invalid(_) => ApiError::Unprocessable(error.to_string()),
+        _ => ApiError::Internal(error.to_string()),
+    }
+}
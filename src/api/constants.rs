@@ -1,71 +1,298 @@
 use std::sync::Arc;
 
-use axum::extract::{Json, Path, State};
+use axum::extract::{Path, Query, State};
 use axum::http::StatusCode;
+use axum::response::Response;
 use diesel::r2d2::{ConnectionManager, PooledConnection};
 use diesel::SqliteConnection;
+use serde_derive::Serialize;
+use utoipa::ToSchema;
+use validator::Validate;
 
-use crate::api::{AppState, ErrorMessage, internal_error};
-use crate::models::constants::{Constant, UpdateConstant};
+use crate::api::{AppState, internal_error};
+use crate::api::error::ApiError;
+use crate::api::extract::Json;
+use crate::api::listing::{list_response, ListQuery};
+use crate::api::permissions::{authorize, Permission};
+use crate::common::secrets::SecretBox;
+use crate::models::constants::{Constant, ConstantKind, NewConstant, NewGlobalConstant, UpdateConstant};
 
+#[derive(Serialize, ToSchema)]
+pub struct RevealedConstant {
+    pub name: String,
+    pub value: String,
+}
+
+#[utoipa::path(
+    get,
+    path = "/constants/{user_id}",
+    tag = "constants",
+    params(ListQuery),
+    responses(
+        (status = 200, description = "Values of secrets are masked", body = [Constant], headers(
+            ("x-total-count" = i64, description = "Number of matching items"),
+            ("x-next-cursor" = String, description = "Offset of the next page as an opaque cursor, missing on the last page"),
+        )),
+    ),
+)]
 pub async fn get_constants_by_user_id(
     Path(id): Path<i32>,
+    Query(query): Query<ListQuery>,
     State(state): State<Arc<AppState>>,
-) -> Result<Json<Vec<Constant>>, (StatusCode, Json<ErrorMessage>)> {
+) -> Result<Response, ApiError> {
+    let listing = query.listing(&["id", "name"])?;
     let mut conn = state.db_pool.get().map_err(internal_error)?;
 
-    let constants_result = Constant::get_all_by_user_id(id, &mut conn);
+    let constants_result = Constant::list_by_user_id(id, &listing, &mut conn);
 
     match constants_result {
-        Ok(constants) => Ok(Json(constants)),
-        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorMessage { message: "Failed to load constants".to_string() }))),
+        Ok((constants, total)) => Ok(list_response(constants.into_iter().map(Constant::masked).collect::<Vec<_>>(), total, &listing)),
+        Err(_) => Err(ApiError::Internal("Failed to load constants".to_string())),
     }
 }
 
+#[utoipa::path(
+    post,
+    path = "/constants",
+    tag = "constants",
+    responses((status = 201, description = "Created")),
+)]
 pub async fn post_constant(
     State(state): State<Arc<AppState>>,
-    Json(new_constant): Json<crate::models::constants::NewConstant>,
-) -> Result<StatusCode, (StatusCode, Json<ErrorMessage>)> {
+    Json(new_constant): Json<NewConstant>,
+) -> Result<StatusCode, ApiError> {
+    authorize(&state, Permission::UserData(new_constant.user_id))?;
+    new_constant.validate()?;
     let mut conn = state.db_pool.get().map_err(internal_error)?;
 
     if constants_exists(&new_constant.user_id, &new_constant.name, &mut conn) {
-        return Err((StatusCode::FORBIDDEN, Json(ErrorMessage { message: "Constant with the same name already exists".to_string() })));
+        return Err(ApiError::Conflict("Constant with the same name already exists".to_string()));
     }
 
-    let constant_result = Constant::create(new_constant.user_id, &new_constant.name, &new_constant.value, &mut conn);
+    let stored = stored_value(&state.secrets, new_constant.kind, &new_constant.value)?;
+    let constant_result = Constant::create(new_constant.user_id, &new_constant.name, &stored, new_constant.kind, &mut conn);
 
     match constant_result {
         Ok(_) => Ok(StatusCode::CREATED),
-        Err(_) => Err((StatusCode::BAD_REQUEST, Json(ErrorMessage { message: "Failed to create constant".to_string() }))),
+        Err(e) => Err(ApiError::database("Constant")(e)),
     }
 }
 
+#[utoipa::path(
+    delete,
+    path = "/constants/{user_id}/{constant_name}",
+    tag = "constants",
+    responses((status = 204, description = "Done")),
+)]
 pub async fn delete_constant_by_user_id_and_name(
     Path((id, constant_name)): Path<(i32, String)>,
     State(state): State<Arc<AppState>>,
-) -> Result<StatusCode, (StatusCode, Json<ErrorMessage>)> {
+) -> Result<StatusCode, ApiError> {
+    authorize(&state, Permission::UserData(id))?;
     let mut conn = state.db_pool.get().map_err(internal_error)?;
 
     let delete_result = Constant::delete_by_user_id_and_name(id, &constant_name, &mut conn);
 
     match delete_result {
+        Ok(0) => Err(ApiError::NotFound("Constant not found".to_string())),
         Ok(_) => Ok(StatusCode::NO_CONTENT),
-        Err(_) => Err((StatusCode::BAD_REQUEST, Json(ErrorMessage { message: "Failed to delete constant".to_string() }))),
+        Err(e) => Err(ApiError::database("Constant")(e)),
     }
 }
 
+#[utoipa::path(
+    put,
+    path = "/constants/{user_id}/{constant_name}",
+    tag = "constants",
+    responses((status = 204, description = "Done")),
+)]
 pub async fn put_constant(
     Path((id, constant_name)): Path<(i32, String)>,
     State(state): State<Arc<AppState>>,
     Json(new_value): Json<UpdateConstant>,
-) -> Result<StatusCode, (StatusCode, Json<ErrorMessage>)> {
+) -> Result<StatusCode, ApiError> {
+    authorize(&state, Permission::UserData(id))?;
+    new_value.validate()?;
     let mut conn = state.db_pool.get().map_err(internal_error)?;
 
-    let update_result = Constant::update_value(id, &constant_name, &new_value.value, &mut conn);
+    let existing = Constant::get_by_user_id_and_name(id, &constant_name, &mut conn)
+        .map_err(ApiError::database("Constant"))?;
+
+    let kind = new_value.kind.unwrap_or(existing.kind);
+    let stored = stored_value(&state.secrets, kind, &new_value.value)?;
+    let update_result = Constant::update_value(id, &constant_name, &stored, kind, &mut conn);
 
     match update_result {
         Ok(_) => Ok(StatusCode::NO_CONTENT),
-        Err(_) => Err((StatusCode::BAD_REQUEST, Json(ErrorMessage { message: "Failed to update constant".to_string() }))),
+        Err(e) => Err(ApiError::database("Constant")(e)),
+    }
+}
+
+/// Returns the plain value of a secret. Only its owner may see it, not even admins.
+#[utoipa::path(
+    post,
+    path = "/constants/{user_id}/{constant_name}/reveal",
+    tag = "constants",
+    responses((status = 200, description = "OK", body = RevealedConstant)),
+)]
+pub async fn post_reveal_constant(
+    Path((id, constant_name)): Path<(i32, String)>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<RevealedConstant>, ApiError> {
+    let actor = authorize(&state, Permission::UserData(id))?;
+    if actor.is_none_or(|actor| actor.id != id) {
+        return Err(ApiError::Forbidden("Only the owner can reveal a secret".to_string()));
+    }
+    let mut conn = state.db_pool.get().map_err(internal_error)?;
+
+    let constant = Constant::get_by_user_id_and_name(id, &constant_name, &mut conn)
+        .map_err(ApiError::database("Constant"))?;
+
+    match constant.plain_value(&state.secrets) {
+        Ok(value) => Ok(Json(RevealedConstant { name: constant.name, value })),
+        Err(e) => Err(ApiError::Internal(e.to_string())),
+    }
+}
+
+/// Global and own constants merged, own ones overriding global ones with the same name
+#[utoipa::path(
+    get,
+    path = "/constants/{user_id}/resolved",
+    tag = "constants",
+    responses((status = 200, description = "Values of secrets are masked", body = [Constant])),
+)]
+pub async fn get_resolved_constants(
+    Path(id): Path<i32>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<Vec<Constant>>, ApiError> {
+    let mut conn = state.db_pool.get().map_err(internal_error)?;
+
+    match Constant::get_resolved(id, &mut conn) {
+        Ok(constants) => Ok(Json(constants.into_iter().map(Constant::masked).collect())),
+        Err(_) => Err(ApiError::Internal("Failed to load constants".to_string())),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/constants/global",
+    tag = "constants",
+    responses((status = 200, description = "Values of secrets are masked", body = [Constant])),
+)]
+pub async fn get_global_constants(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<Vec<Constant>>, ApiError> {
+    let mut conn = state.db_pool.get().map_err(internal_error)?;
+
+    match Constant::get_all_global(&mut conn) {
+        Ok(constants) => Ok(Json(constants.into_iter().map(Constant::masked).collect())),
+        Err(_) => Err(ApiError::Internal("Failed to load constants".to_string())),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/constants/global",
+    tag = "constants",
+    responses((status = 201, description = "Created")),
+)]
+pub async fn post_global_constant(
+    State(state): State<Arc<AppState>>,
+    Json(new_constant): Json<NewGlobalConstant>,
+) -> Result<StatusCode, ApiError> {
+    authorize(&state, Permission::ManageSystem)?;
+    new_constant.validate()?;
+    let mut conn = state.db_pool.get().map_err(internal_error)?;
+
+    if Constant::get_global_by_name(&new_constant.name, &mut conn).is_ok() {
+        return Err(ApiError::Conflict("Global constant with the same name already exists".to_string()));
+    }
+
+    let stored = stored_value(&state.secrets, new_constant.kind, &new_constant.value)?;
+
+    match Constant::create_global(&new_constant.name, &stored, new_constant.kind, &mut conn) {
+        Ok(_) => Ok(StatusCode::CREATED),
+        Err(e) => Err(ApiError::database("Constant")(e)),
+    }
+}
+
+#[utoipa::path(
+    put,
+    path = "/constants/global/{constant_name}",
+    tag = "constants",
+    responses((status = 204, description = "Done")),
+)]
+pub async fn put_global_constant(
+    Path(constant_name): Path<String>,
+    State(state): State<Arc<AppState>>,
+    Json(new_value): Json<UpdateConstant>,
+) -> Result<StatusCode, ApiError> {
+    authorize(&state, Permission::ManageSystem)?;
+    new_value.validate()?;
+    let mut conn = state.db_pool.get().map_err(internal_error)?;
+
+    let existing = Constant::get_global_by_name(&constant_name, &mut conn)
+        .map_err(ApiError::database("Constant"))?;
+
+    let kind = new_value.kind.unwrap_or(existing.kind);
+    let stored = stored_value(&state.secrets, kind, &new_value.value)?;
+
+    match Constant::update_global_value(&constant_name, &stored, kind, &mut conn) {
+        Ok(_) => Ok(StatusCode::NO_CONTENT),
+        Err(e) => Err(ApiError::database("Constant")(e)),
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/constants/global/{constant_name}",
+    tag = "constants",
+    responses((status = 204, description = "Done")),
+)]
+pub async fn delete_global_constant(
+    Path(constant_name): Path<String>,
+    State(state): State<Arc<AppState>>,
+) -> Result<StatusCode, ApiError> {
+    authorize(&state, Permission::ManageSystem)?;
+    let mut conn = state.db_pool.get().map_err(internal_error)?;
+
+    match Constant::delete_global_by_name(&constant_name, &mut conn) {
+        Ok(0) => Err(ApiError::NotFound("Constant not found".to_string())),
+        Ok(_) => Ok(StatusCode::NO_CONTENT),
+        Err(e) => Err(ApiError::database("Constant")(e)),
+    }
+}
+
+/// Global secrets have no owner, so only admins may see them
+#[utoipa::path(
+    post,
+    path = "/constants/global/{constant_name}/reveal",
+    tag = "constants",
+    responses((status = 200, description = "OK", body = RevealedConstant)),
+)]
+pub async fn post_reveal_global_constant(
+    Path(constant_name): Path<String>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<RevealedConstant>, ApiError> {
+    authorize(&state, Permission::ManageSystem)?;
+    let mut conn = state.db_pool.get().map_err(internal_error)?;
+
+    let constant = Constant::get_global_by_name(&constant_name, &mut conn)
+        .map_err(ApiError::database("Constant"))?;
+
+    match constant.plain_value(&state.secrets) {
+        Ok(value) => Ok(Json(RevealedConstant { name: constant.name, value })),
+        Err(e) => Err(ApiError::Internal(e.to_string())),
+    }
+}
+
+/// Validates a value for its kind and encrypts secrets
+pub(crate) fn stored_value(secrets: &SecretBox, kind: ConstantKind, value: &str) -> Result<String, ApiError> {
+    kind.validate(value).map_err(|message| ApiError::field("value", message))?;
+
+    match kind {
+        ConstantKind::Secret => secrets.encrypt(value).map_err(internal_error),
+        _ => Ok(value.to_string()),
     }
 }
 
@@ -0,0 +1,60 @@
+use std::sync::Arc;
+
+use axum::extract::State;
+use axum::http::StatusCode;
+use serde_derive::Deserialize;
+use utoipa::ToSchema;
+
+use crate::api::AppState;
+use crate::api::error::ApiError;
+use crate::api::extract::Json;
+use crate::hardware::simulated::Simulator;
+
+#[derive(Deserialize, ToSchema)]
+pub struct RfidScan {
+    pub uid: String,
+}
+
+#[utoipa::path(
+    post,
+    path = "/debug/rfid",
+    tag = "debug",
+    responses((status = 202, description = "Accepted")),
+)]
+pub async fn post_rfid_scan(
+    State(state): State<Arc<AppState>>,
+    Json(scan): Json<RfidScan>,
+) -> Result<StatusCode, ApiError> {
+    let simulator = get_simulator(&state)?;
+
+    if scan.uid.trim().is_empty() {
+        return Err(ApiError::field("rfid_uid", "RFID uid cannot be empty"));
+    }
+
+    match simulator.scan_rfid(scan.uid) {
+        Ok(_) => Ok(StatusCode::ACCEPTED),
+        Err(e) => Err(ApiError::Unavailable(e)),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/debug/touch",
+    tag = "debug",
+    responses((status = 202, description = "Accepted")),
+)]
+pub async fn post_touch(
+    State(state): State<Arc<AppState>>,
+) -> Result<StatusCode, ApiError> {
+    let simulator = get_simulator(&state)?;
+
+    match simulator.touch() {
+        Ok(_) => Ok(StatusCode::ACCEPTED),
+        Err(e) => Err(ApiError::Unavailable(e)),
+    }
+}
+
+fn get_simulator(state: &AppState) -> Result<&Simulator, ApiError> {
+    state.simulator.as_ref()
+        .ok_or(ApiError::NotFound("Simulated hardware backend is not enabled".to_string()))
+}
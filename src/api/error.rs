@@ -0,0 +1,199 @@
+use axum::extract::{Json, Request};
+use axum::http::{HeaderName, HeaderValue, StatusCode, Uri};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use diesel::r2d2::PoolError;
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
+use log::{error, warn};
+use rand::Rng;
+use serde_derive::Serialize;
+use thiserror::Error;
+use utoipa::ToSchema;
+use validator::ValidationErrors;
+
+use crate::models::validation::flatten_errors;
+
+pub const REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+
+tokio::task_local! {
+    static CURRENT_REQUEST_ID: String;
+}
+
+/// Error returned by every handler. The code is stable and meant for clients,
+/// the message is for humans and may change.
+#[derive(Error, Debug)]
+pub enum ApiError {
+    #[error("{0}")]
+    BadRequest(String),
+    #[error("{0}")]
+    Unauthorized(String),
+    #[error("{0}")]
+    Forbidden(String),
+    #[error("{0}")]
+    NotFound(String),
+    #[error("{0}")]
+    Conflict(String),
+    #[error("{0}")]
+    PayloadTooLarge(String),
+    #[error("{0}")]
+    TooManyRequests(String),
+    /// Well-formed, but not something the hub can use
+    #[error("{0}")]
+    Unprocessable(String),
+    /// Fields that failed validation, each with its own message
+    #[error("Invalid {}", .0.iter().map(|f| f.field.as_str()).collect::<Vec<_>>().join(", "))]
+    Validation(Vec<FieldError>),
+    #[error("{0}")]
+    BadGateway(String),
+    #[error("{0}")]
+    GatewayTimeout(String),
+    #[error("{0}")]
+    Unavailable(String),
+    #[error("{0}")]
+    Internal(String),
+}
+
+#[derive(Serialize, Debug, Clone, ToSchema)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+/// Body of every error response
+#[derive(Serialize, ToSchema)]
+pub struct ErrorBody {
+    /// Stable, e.g. `NOT_FOUND` or `VALIDATION_FAILED`
+    #[schema(example = "NOT_FOUND")]
+    code: &'static str,
+    message: String,
+    /// Only for `VALIDATION_FAILED`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    fields: Vec<FieldError>,
+    /// Same as the `X-Request-Id` response header
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_id: Option<String>,
+}
+
+impl ApiError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ApiError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            ApiError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            ApiError::Forbidden(_) => StatusCode::FORBIDDEN,
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::Conflict(_) => StatusCode::CONFLICT,
+            ApiError::PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            ApiError::TooManyRequests(_) => StatusCode::TOO_MANY_REQUESTS,
+            ApiError::Unprocessable(_) | ApiError::Validation(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            ApiError::BadGateway(_) => StatusCode::BAD_GATEWAY,
+            ApiError::GatewayTimeout(_) => StatusCode::GATEWAY_TIMEOUT,
+            ApiError::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::BadRequest(_) => "BAD_REQUEST",
+            ApiError::Unauthorized(_) => "UNAUTHORIZED",
+            ApiError::Forbidden(_) => "FORBIDDEN",
+            ApiError::NotFound(_) => "NOT_FOUND",
+            ApiError::Conflict(_) => "CONFLICT",
+            ApiError::PayloadTooLarge(_) => "PAYLOAD_TOO_LARGE",
+            ApiError::TooManyRequests(_) => "TOO_MANY_REQUESTS",
+            ApiError::Unprocessable(_) => "UNPROCESSABLE",
+            ApiError::Validation(_) => "VALIDATION_FAILED",
+            ApiError::BadGateway(_) => "UPSTREAM_FAILED",
+            ApiError::GatewayTimeout(_) => "UPSTREAM_TIMEOUT",
+            ApiError::Unavailable(_) => "UNAVAILABLE",
+            ApiError::Internal(_) => "INTERNAL_ERROR",
+        }
+    }
+
+    /// A single invalid field
+    pub fn field(field: &str, message: impl Into<String>) -> Self {
+        ApiError::Validation(vec![FieldError { field: field.to_string(), message: message.into() }])
+    }
+
+    /// Maps a database error on the given kind of record, for use with `map_err`
+    pub fn database(record: &'static str) -> impl Fn(DieselError) -> ApiError {
+        move |e| match e {
+            DieselError::NotFound => ApiError::NotFound(format!("{} not found", record)),
+            DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _) => ApiError::Conflict(format!("{} already exists", record)),
+            DieselError::DatabaseError(DatabaseErrorKind::ForeignKeyViolation, _) => {
+                ApiError::Unprocessable(format!("{} refers to something that doesn't exist", record))
+            }
+            DieselError::DatabaseError(DatabaseErrorKind::NotNullViolation | DatabaseErrorKind::CheckViolation, info) => {
+                ApiError::Unprocessable(info.message().to_string())
+            }
+            e => ApiError::Internal(e.to_string()),
+        }
+    }
+}
+
+impl From<DieselError> for ApiError {
+    fn from(e: DieselError) -> Self {
+        ApiError::database("Record")(e)
+    }
+}
+
+impl From<ValidationErrors> for ApiError {
+    fn from(errors: ValidationErrors) -> Self {
+        ApiError::Validation(flatten_errors(&errors).into_iter()
+            .map(|(field, message)| FieldError { field, message })
+            .collect())
+    }
+}
+
+impl From<PoolError> for ApiError {
+    fn from(e: PoolError) -> Self {
+        ApiError::Internal(e.to_string())
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let request_id = CURRENT_REQUEST_ID.try_with(|id| id.clone()).ok();
+        let status = self.status();
+
+        if status.is_server_error() {
+            error!("[{}] {}: {}", request_id.as_deref().unwrap_or("-"), self.code(), self);
+        } else {
+            warn!("[{}] {}: {}", request_id.as_deref().unwrap_or("-"), self.code(), self);
+        }
+
+        let body = ErrorBody {
+            code: self.code(),
+            message: self.to_string(),
+            fields: match self {
+                ApiError::Validation(fields) => fields,
+                _ => Vec::new(),
+            },
+            request_id,
+        };
+
+        (status, Json(body)).into_response()
+    }
+}
+
+/// Fallback of the router, so unknown routes get an error body as well
+pub async fn not_found(uri: Uri) -> ApiError {
+    ApiError::NotFound(format!("No route for {}", uri.path()))
+}
+
+/// Tags every request with an id, taken from the `X-Request-Id` header if the client sent a usable one.
+/// The id is returned in the same header and in error bodies, and prefixes error logs.
+pub async fn request_id(request: Request, next: Next) -> Response {
+    let id = request.headers().get(REQUEST_ID)
+        .and_then(|value| value.to_str().ok())
+        .filter(|id| !id.is_empty() && id.len() <= 64 && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-'))
+        .map(str::to_string)
+        .unwrap_or_else(|| format!("{:016x}", rand::thread_rng().gen::<u64>()));
+
+    let mut response = CURRENT_REQUEST_ID.scope(id.clone(), next.run(request)).await;
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        response.headers_mut().insert(REQUEST_ID, value);
+    }
+
+    response
+}
@@ -0,0 +1,94 @@
+use axum::async_trait;
+use axum::body::Bytes;
+use axum::extract::{FromRequest, Request};
+use axum::extract::rejection::BytesRejection;
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::error::Category;
+
+use crate::api::error::ApiError;
+
+/// `axum::Json` with rejections as `ApiError`, so a body with a wrong field type fails like any
+/// other invalid field instead of with a plain text error
+pub struct Json<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequest<S> for Json<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request(request: Request, state: &S) -> Result<Self, ApiError> {
+        if !is_json(request.headers()) {
+            return Err(ApiError::BadRequest("Expected a body with Content-Type: application/json".to_string()));
+        }
+
+        let body = Bytes::from_request(request, state).await.map_err(body_error)?;
+        let mut deserializer = serde_json::Deserializer::from_slice(&body);
+
+        let value = serde_path_to_error::deserialize(&mut deserializer).map_err(deserialize_error)?;
+        deserializer.end().map_err(|e| ApiError::BadRequest(format!("Invalid JSON: {}", e)))?;
+
+        Ok(Json(value))
+    }
+}
+
+impl<T: Serialize> IntoResponse for Json<T> {
+    fn into_response(self) -> Response {
+        axum::Json(self.0).into_response()
+    }
+}
+
+fn is_json(headers: &HeaderMap) -> bool {
+    headers.get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(';').next())
+        .is_some_and(|mime| {
+            let mime = mime.trim().to_ascii_lowercase();
+            mime == "application/json" || (mime.starts_with("application/") && mime.ends_with("+json"))
+        })
+}
+
+fn body_error(rejection: BytesRejection) -> ApiError {
+    match rejection.status() {
+        StatusCode::PAYLOAD_TOO_LARGE => ApiError::PayloadTooLarge("Body is too large".to_string()),
+        _ => ApiError::BadRequest(rejection.body_text()),
+    }
+}
+
+/// Data errors point at the field, anything else means the body isn't JSON
+fn deserialize_error(error: serde_path_to_error::Error<serde_json::Error>) -> ApiError {
+    let path = error.path().to_string();
+    let inner = error.into_inner();
+
+    if inner.classify() != Category::Data {
+        return ApiError::BadRequest(format!("Invalid JSON: {}", inner));
+    }
+
+    // serde_json appends the position, which doesn't help once the field is known
+    let message = inner.to_string();
+    let message = match message.rfind(" at line ") {
+        Some(position) => message[..position].to_string(),
+        None => message,
+    };
+
+    // A missing field is reported on the object that lacks it
+    match message.strip_prefix("missing field `").and_then(|rest| rest.strip_suffix('`')) {
+        Some(name) if path == "." => ApiError::field(name, "Required"),
+        Some(name) => ApiError::field(&format!("{}.{}", path, name), "Required"),
+        None if path == "." => ApiError::BadRequest(message),
+        None => ApiError::field(&path, capitalize(&message)),
+    }
+}
+
+fn capitalize(message: &str) -> String {
+    let mut chars = message.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
@@ -0,0 +1,121 @@
+use std::sync::Arc;
+
+use axum::extract::{Path, Query, State};
+use chrono::NaiveDateTime;
+use serde_derive::Deserialize;
+use utoipa::IntoParams;
+
+use crate::api::{AppState, internal_error};
+use crate::api::error::ApiError;
+use crate::api::extract::Json;
+use crate::models::action_runs::{ActionRun, ActionRunFilter, Trigger};
+
+const DEFAULT_LIMIT: i64 = 50;
+const MAX_LIMIT: i64 = 200;
+
+/// The filters are listed here instead of flattening `ActionRunFilter`, `Query` can't parse
+/// numbers inside a flattened struct
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct HistoryQuery {
+    /// Defaults to 50, at most 200
+    pub limit: Option<i64>,
+    /// Number of runs to skip
+    pub offset: Option<i64>,
+    /// `rfid`, `api`, `websocket` or `action`
+    pub source: Option<String>,
+    pub outcome: Option<String>,
+    pub action_id: Option<i32>,
+    pub request_id: Option<i32>,
+    pub since: Option<NaiveDateTime>,
+    pub until: Option<NaiveDateTime>,
+}
+
+impl HistoryQuery {
+    fn filter(self) -> Result<ActionRunFilter, ApiError> {
+        if let Some(source) = self.source.as_deref().filter(|source| !Trigger::SOURCES.contains(source)) {
+            return Err(ApiError::field("source", format!("Unknown source {}, use one of: {}", source, Trigger::SOURCES.join(", "))));
+        }
+
+        Ok(ActionRunFilter {
+            source: self.source,
+            outcome: self.outcome,
+            action_id: self.action_id,
+            request_id: self.request_id,
+            since: self.since,
+            until: self.until,
+        })
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/users/{user_id}/history",
+    tag = "users",
+    params(HistoryQuery),
+    responses((status = 200, description = "OK", body = [ActionRun])),
+)]
+pub async fn get_history_by_user_id(
+    Path(user_id): Path<i32>,
+    Query(query): Query<HistoryQuery>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<Vec<ActionRun>>, ApiError> {
+    let mut conn = state.db_pool.get().map_err(internal_error)?;
+
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let offset = query.offset.unwrap_or(0).max(0);
+    let filter = query.filter()?;
+
+    let runs_result = ActionRun::get_all_by_user_id(user_id, &filter, limit, offset, &mut conn);
+
+    match runs_result {
+        Ok(runs) => Ok(Json(runs)),
+        Err(_) => Err(ApiError::Internal("Failed to load history".to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::Uri;
+
+    use super::*;
+
+    fn query(uri: &str) -> HistoryQuery {
+        let uri: Uri = uri.parse().unwrap();
+        Query::<HistoryQuery>::try_from_uri(&uri).unwrap().0
+    }
+
+    #[test]
+    fn parses_numeric_filters() {
+        let filter = query("/users/1/history?action_id=1&request_id=2&limit=10&offset=20");
+
+        assert_eq!(filter.limit, Some(10));
+        assert_eq!(filter.offset, Some(20));
+        assert_eq!(filter.action_id, Some(1));
+        assert_eq!(filter.request_id, Some(2));
+    }
+
+    #[test]
+    fn parses_text_and_date_filters() {
+        let filter = query("/users/1/history?source=rfid&outcome=failed&since=2024-01-01T00:00:00").filter().unwrap();
+
+        assert_eq!(filter.source.as_deref(), Some("rfid"));
+        assert_eq!(filter.outcome.as_deref(), Some("failed"));
+        assert_eq!(filter.since, "2024-01-01T00:00:00".parse().ok());
+        assert_eq!(filter.until, None);
+    }
+
+    #[test]
+    fn rejects_unknown_sources() {
+        assert!(query("/users/1/history?source=schedule").filter().is_err());
+        for source in Trigger::SOURCES {
+            assert!(query(&format!("/users/1/history?source={}", source)).filter().is_ok());
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_numbers() {
+        let uri: Uri = "/users/1/history?action_id=abc".parse().unwrap();
+        assert!(Query::<HistoryQuery>::try_from_uri(&uri).is_err());
+    }
+}
@@ -0,0 +1,87 @@
+use axum::http::{HeaderName, HeaderValue};
+use axum::response::{IntoResponse, Response};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use serde::Serialize;
+use serde_derive::Deserialize;
+use utoipa::IntoParams;
+
+use crate::api::error::ApiError;
+use crate::api::extract::Json;
+use crate::models::listing::Listing;
+
+const DEFAULT_LIMIT: i64 = 100;
+const MAX_LIMIT: i64 = 500;
+
+pub const TOTAL_COUNT: HeaderName = HeaderName::from_static("x-total-count");
+pub const NEXT_CURSOR: HeaderName = HeaderName::from_static("x-next-cursor");
+
+/// Query parameters shared by the list endpoints. Pages are read by offset, so rows added or
+/// deleted between two requests shift the following pages by that many items.
+#[derive(Deserialize, Default, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ListQuery {
+    pub limit: Option<i64>,
+    /// Taken from the `X-Next-Cursor` header of the previous page. It holds an offset, items added
+    /// or deleted in the meantime can be skipped or repeated.
+    pub cursor: Option<String>,
+    /// Column name, prefixed with `-` for descending order
+    pub sort: Option<String>,
+    /// Case-insensitive search text
+    pub q: Option<String>,
+}
+
+impl ListQuery {
+    /// Checks the query against the columns an endpoint can be sorted by, the first one is the default
+    pub fn listing(self, sort_columns: &[&str]) -> Result<Listing, ApiError> {
+        let offset = match self.cursor {
+            Some(cursor) => decode_cursor(&cursor)
+                .ok_or(ApiError::BadRequest("Invalid cursor".to_string()))?,
+            None => 0,
+        };
+
+        let sort = self.sort.unwrap_or_else(|| sort_columns[0].to_string());
+        let (column, descending) = match sort.strip_prefix('-') {
+            Some(column) => (column, true),
+            None => (sort.as_str(), false),
+        };
+        if !sort_columns.contains(&column) {
+            return Err(ApiError::BadRequest(format!("Can't sort by {}, use one of: {}", column, sort_columns.join(", "))));
+        }
+
+        Ok(Listing {
+            limit: self.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT),
+            offset,
+            sort: column.to_string(),
+            descending,
+            search: self.q.map(|q| q.trim().to_string()).filter(|q| !q.is_empty()),
+        })
+    }
+}
+
+/// Returns a page as a plain JSON array, the total count and the cursor of the next page go in headers
+pub fn list_response<T: Serialize>(items: Vec<T>, total: i64, listing: &Listing) -> Response {
+    let end = listing.offset + items.len() as i64;
+    let mut response = Json(items).into_response();
+
+    let headers = response.headers_mut();
+    headers.insert(TOTAL_COUNT, HeaderValue::from(total));
+    if end < total {
+        if let Ok(cursor) = HeaderValue::from_str(&encode_cursor(end)) {
+            headers.insert(NEXT_CURSOR, cursor);
+        }
+    }
+
+    response
+}
+
+// Cursors are opaque to clients, so switching to keyset pagination later won't break them.
+// For now they are plain offsets.
+fn encode_cursor(offset: i64) -> String {
+    URL_SAFE_NO_PAD.encode(format!("offset:{}", offset))
+}
+
+fn decode_cursor(cursor: &str) -> Option<i64> {
+    let decoded = String::from_utf8(URL_SAFE_NO_PAD.decode(cursor).ok()?).ok()?;
+    decoded.strip_prefix("offset:")?.parse().ok().filter(|offset| *offset >= 0)
+}
@@ -1,67 +1,177 @@
 use std::sync::Arc;
 
+use std::net::SocketAddr;
+
 use axum::{Extension, extract::{
+    ConnectInfo,
     State,
     ws::WebSocketUpgrade,
-}, Json, response::IntoResponse, Router, routing::get};
+}, response::IntoResponse, Router, routing::get};
+use axum::extract::DefaultBodyLimit;
+use axum::middleware;
 use axum::routing::{delete, post, put};
-use http::StatusCode;
 use log::info;
 use reqwest::Client;
-use serde_derive::Serialize;
 use tokio::net::TcpListener;
-use tokio::sync::broadcast;
-use tokio::sync::mpsc::Sender;
-use tower_http::cors::CorsLayer;
+use tokio::sync::mpsc::{Receiver, Sender};
+use tower_http::cors::{AllowOrigin, CorsLayer};
 
 use crate::api::actions::{delete_user_action_by_id, get_user_actions_by_user_id, post_user_action, put_user_action};
-use crate::api::constants::{delete_constant_by_user_id_and_name, get_constants_by_user_id, post_constant, put_constant};
-use crate::api::requests::{delete_user_request_by_id, get_user_requests_by_user_id, post_user_request, put_user_request};
-use crate::api::system::{connect_wifi, disconnect_wifi, get_current_network_status, get_info, get_scan_results, post_reboot, post_shutdown, proxy_image, start_scan, start_wpa_supplicant, stop_wpa_supplicant};
+use crate::api::backup::{MAX_RESTORE_SIZE, post_backup, post_restore};
+use crate::api::auth::{delete_token, delete_tokens, get_tokens, Pairings, post_pairing, post_pairing_confirmation, require_token};
+use crate::api::debug::{post_rfid_scan, post_touch};
+use crate::api::error::{ApiError, not_found, request_id};
+use crate::api::history::get_history_by_user_id;
+use crate::api::openapi::{get_event_schema, get_openapi};
+use crate::api::constants::{delete_constant_by_user_id_and_name, delete_global_constant, get_constants_by_user_id, get_global_constants, get_resolved_constants, post_constant, post_global_constant, post_reveal_constant, post_reveal_global_constant, put_constant, put_global_constant};
+use crate::api::session::{delete_session, delete_user_tag, get_session, get_user_tags, post_session, post_user_tag, put_user_pin};
+use crate::api::requests::{delete_user_request_by_id, execute_user_request_by_id, get_user_requests_by_user_id, post_user_request, put_user_request};
+use crate::api::system::{connect_wifi, disconnect_wifi, get_clients, get_current_network_status, get_event_stats, get_info, get_scan_results, post_reboot, post_shutdown, proxy_image, start_scan, start_wpa_supplicant, stop_wpa_supplicant};
+use crate::api::transfer::{get_user_export, post_user_import};
+use crate::api::versioning::{deprecated, V1};
 use crate::api::users::{delete_user, get_user_by_id, get_users, post_user, put_user};
 use crate::common::db::DatabasePool;
-use crate::config::ServerConf;
+use crate::common::event_bus::EventBus;
+use crate::common::secrets::SecretBox;
+use crate::Config;
+use crate::config::{BackupConf, NetworkConf, SharedConfig};
 use crate::enums::system_command::SystemCommand;
-use crate::handlers::connection_handler::handle_connection;
-use crate::models::websocket::WebSocketMessage;
+use crate::handlers::action_handler::action_handler;
+use crate::handlers::connection_handler::{ClientRegistry, handle_connection};
+use crate::handlers::session_handler::{session_handler, SessionStore};
+use crate::handlers::state_handler::StateStore;
+use crate::hardware::simulated::Simulator;
+use crate::hardware::traits::Backlight;
+use crate::models::user_actions::UserAction;
 
 mod system;
 mod users;
 mod constants;
 mod actions;
 mod requests;
+mod debug;
+mod history;
+mod auth;
+mod session;
+mod backup;
+mod transfer;
+mod listing;
+mod extract;
+mod openapi;
+mod versioning;
+pub mod error;
+pub mod permissions;
 
 pub struct AppState {
-    pub tx: broadcast::Sender<WebSocketMessage>,
+    pub tx: EventBus,
     pub tx_dbus: Sender<SystemCommand>,
     pub db_pool: DatabasePool,
+    pub client: Arc<Client>,
+    pub backlight: Arc<dyn Backlight>,
+    pub simulator: Option<Simulator>,
+    pub state_store: Arc<StateStore>,
+    pub clients: Arc<ClientRegistry>,
+    pub config: SharedConfig,
+    pub pairings: Pairings,
+    pub session: SessionStore,
+    pub backup: BackupConf,
+    pub network: NetworkConf,
+    pub secrets: SecretBox,
 }
 
-#[derive(Serialize)]
-pub struct ErrorMessage {
-    pub message: String,
+#[allow(clippy::too_many_arguments)]
+pub async fn init(conf: &Config, config: SharedConfig, tx: EventBus, tx_dbus: Sender<SystemCommand>, rx_actions: Receiver<UserAction>, db_pool: &DatabasePool, backlight: Arc<dyn Backlight>, simulator: Option<Simulator>, state_store: Arc<StateStore>, secrets: SecretBox) {
+    let address = format!("{}:{}", conf.server.address, conf.server.port);
+
+    let shared_client = Arc::new(Client::new());
+    let app_state = Arc::new(AppState {
+        tx,
+        tx_dbus,
+        db_pool: db_pool.clone(),
+        client: shared_client.clone(),
+        backlight,
+        simulator,
+        state_store,
+        clients: Arc::new(ClientRegistry::default()),
+        config: config.clone(),
+        pairings: Pairings::default(),
+        session: SessionStore::default(),
+        backup: conf.backup.clone(),
+        network: conf.network.clone(),
+        secrets,
+    });
+
+    if conf.auth.kiosk_mode {
+        info!("Kiosk mode enabled, requests from localhost don't need a token");
+    }
+
+    // Execute RFID actions server-side, independent of connected clients
+    tokio::spawn(action_handler(rx_actions, app_state.clone()));
+    tokio::spawn(session_handler(app_state.clone(), app_state.tx.subscribe()));
+
+    let mut app = Router::new()
+        .nest(V1, v1(&app_state));
+
+    // Incompatible changes go into a v2 router nested next to v1, v1 stays as it is until clients moved
+
+    // A route layer, so unknown paths get a plain 404 instead of a deprecated one
+    if conf.server.legacy_routes.enabled {
+        app = app.merge(v1(&app_state)
+            .route_layer(middleware::from_fn_with_state(config.clone(), deprecated)));
+    }
+
+    let app = app
+        .fallback(not_found)
+        .layer(CorsLayer::permissive().allow_origin(allowed_origins(config)))
+        .layer(middleware::from_fn(request_id))
+        .layer(Extension(shared_client))
+        .with_state(app_state);
+
+    let try_socket = TcpListener::bind(&address).await;
+
+    let listener = try_socket.expect("Failed to bind");
+    info!("Listening on: {}", address);
+
+    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await.unwrap();
 }
 
-pub async fn init(web_socket_conf: &ServerConf, tx: broadcast::Sender<WebSocketMessage>, tx_dbus: Sender<SystemCommand>, db_pool: &DatabasePool) {
-    let address = format!("{}:{}", web_socket_conf.address, web_socket_conf.port);
 
-    let app_state = Arc::new(AppState { tx, tx_dbus, db_pool: db_pool.clone() });
-    let shared_client = Arc::new(Client::new());
-    
-    let app = Router::new()
+/// Routes of the current API, also served without prefix for older clients
+fn v1(app_state: &Arc<AppState>) -> Router<Arc<AppState>> {
+    let mut router = Router::new()
         .route("/ws", get(websocket_handler))
-        .route("/", get(get_info))
         .route("/system/reboot", post(post_reboot))
         .route("/system/shutdown", post(post_shutdown))
+        .route("/system/events", get(get_event_stats))
+        .route("/system/clients", get(get_clients))
+        .route("/system/backup", post(post_backup))
+        .route("/system/restore", post(post_restore).layer(DefaultBodyLimit::max(MAX_RESTORE_SIZE)))
         .route("/users", get(get_users))
         .route("/users", post(post_user))
         .route("/users/:user_id", get(get_user_by_id))
         .route("/users/:user_id", delete(delete_user))
         .route("/users/:user_id", put(put_user))
+        .route("/users/import", post(post_user_import))
+        .route("/users/:user_id/export", get(get_user_export))
+        .route("/users/:user_id/history", get(get_history_by_user_id))
+        .route("/users/:user_id/pin", put(put_user_pin))
+        .route("/users/:user_id/tags", get(get_user_tags))
+        .route("/users/:user_id/tags", post(post_user_tag))
+        .route("/users/:user_id/tags/:rfid_uid", delete(delete_user_tag))
+        .route("/session", get(get_session))
+        .route("/session", post(post_session))
+        .route("/session", delete(delete_session))
         .route("/constants/:user_id", get(get_constants_by_user_id))
+        .route("/constants/:user_id/resolved", get(get_resolved_constants))
         .route("/constants", post(post_constant))
+        .route("/constants/global", get(get_global_constants))
+        .route("/constants/global", post(post_global_constant))
+        .route("/constants/global/:constant_name", put(put_global_constant))
+        .route("/constants/global/:constant_name", delete(delete_global_constant))
+        .route("/constants/global/:constant_name/reveal", post(post_reveal_global_constant))
         .route("/constants/:user_id/:constant_name", delete(delete_constant_by_user_id_and_name))
         .route("/constants/:user_id/:constant_name", put(put_constant))
+        .route("/constants/:user_id/:constant_name/reveal", post(post_reveal_constant))
         .route("/actions/:id", get(get_user_actions_by_user_id))
         .route("/actions", post(post_user_action))
         .route("/actions/:id", delete(delete_user_action_by_id))
@@ -70,6 +180,7 @@ pub async fn init(web_socket_conf: &ServerConf, tx: broadcast::Sender<WebSocketM
         .route("/requests", post(post_user_request))
         .route("/requests/:id", delete(delete_user_request_by_id))
         .route("/requests/:id", put(put_user_request))
+        .route("/requests/:id/execute", post(execute_user_request_by_id))
         .route("/wifi/start", post(start_wpa_supplicant))
         .route("/wifi/stop", post(stop_wpa_supplicant))
         .route("/wifi/scan/start", post(start_scan))
@@ -77,28 +188,44 @@ pub async fn init(web_socket_conf: &ServerConf, tx: broadcast::Sender<WebSocketM
         .route("/wifi/status", get(get_current_network_status))
         .route("/wifi/connect", post(connect_wifi))
         .route("/wifi/disconnect", post(disconnect_wifi))
-        .route("/proxy-image", get(proxy_image))
-        .layer(CorsLayer::permissive())
-        .layer(Extension(shared_client))
-        .with_state(app_state);
+        .route("/proxy-image", get(proxy_image));
 
-    let try_socket = TcpListener::bind(&address).await;
-
-    let listener = try_socket.expect("Failed to bind");
-    info!("Listening on: {}", address);
+    // Debug endpoints to inject hardware events, only available with the simulated backend
+    if app_state.simulator.is_some() {
+        router = router
+            .route("/debug/rfid", post(post_rfid_scan))
+            .route("/debug/touch", post(post_touch));
+    }
 
-    axum::serve(listener, app).await.unwrap();
+    // Everything above needs a token, pairing is how a device gets one
+    router
+        .route("/auth/tokens", get(get_tokens))
+        .route("/auth/tokens", delete(delete_tokens))
+        .route("/auth/tokens/:id", delete(delete_token))
+        .route_layer(middleware::from_fn_with_state(app_state.clone(), require_token))
+        .route("/", get(get_info))
+        .route("/openapi.json", get(get_openapi))
+        .route("/openapi/events.json", get(get_event_schema))
+        .route("/auth/pair", post(post_pairing))
+        .route("/auth/pair/:pairing_id", post(post_pairing_confirmation))
 }
 
-
 async fn websocket_handler(
     ws: WebSocketUpgrade,
+    ConnectInfo(address): ConnectInfo<SocketAddr>,
     State(state): State<Arc<AppState>>,
 ) -> impl IntoResponse {
-    ws.on_upgrade(|socket| handle_connection(socket, state))
+    ws.on_upgrade(move |socket| handle_connection(socket, address, state))
 }
 
-pub fn internal_error<E>(err: E) -> (StatusCode, Json<ErrorMessage>) where E: std::error::Error, {
-    (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorMessage { message: err.to_string() }))
+pub fn internal_error<E>(err: E) -> ApiError where E: std::error::Error, {
+    ApiError::Internal(err.to_string())
 }
 
+/// Follows `server.cors_origins` on reloads, any origin is allowed while it's empty
+fn allowed_origins(config: SharedConfig) -> AllowOrigin {
+    AllowOrigin::predicate(move |origin, _| {
+        let origins = &config.borrow().server.cors_origins;
+        origins.is_empty() || origins.iter().any(|allowed| allowed.as_bytes() == origin.as_bytes())
+    })
+}
@@ -0,0 +1,188 @@
+use serde_json::{json, Map, Value};
+use utoipa::{Modify, OpenApi};
+use utoipa::openapi::{ContentBuilder, Ref, ResponseBuilder};
+use utoipa::openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme};
+
+use crate::api::{actions, auth, backup, constants, debug, history, requests, session, system, transfer, users};
+use crate::api::auth::{IssuedToken, PairingConfirmation, PairingRequest, PairingResponse};
+use crate::api::constants::RevealedConstant;
+use crate::api::debug::RfidScan;
+use crate::api::error::{ErrorBody, FieldError};
+use crate::api::extract::Json;
+use crate::api::session::{LoginRequest, PinChange, TagRequest};
+use crate::api::system::{InfoResponse, MessageResponse, NetworkStatusResponse, WifiCredentials};
+use crate::api::transfer::{ImportReport, RfidConflict, UsernameConflict};
+use crate::api::users::CreateUser;
+use crate::common::event_bus::EventBusStats;
+use crate::handlers::bluetooth_handler::BluetoothDevice;
+use crate::handlers::connection_handler::ClientInfo;
+use crate::handlers::request_handler::RequestResult;
+use crate::handlers::session_handler::{ActiveSession, LoginMethod};
+use crate::models::action_runs::ActionRun;
+use crate::models::api_tokens::ApiToken;
+use crate::models::constants::{Constant, ConstantKind, NewConstant, NewGlobalConstant, UpdateConstant};
+use crate::models::events::{ActionFailed, ActionStarted, ActionSucceeded, ConfigReloaded, DatabaseRestored, DisplayStatus, EVENTS, NetworkStatus, PairingCompleted, PairingRequested, Ready, ReadyBluetooth, ReadyNetwork, ReadyUpdates, RequestExecuted, RequestFailed, RfidDetected, SessionChanged, UpdateMessage};
+use crate::models::interface::{Addr, NetworkInterface, ScanResult, V4IfAddr, V6IfAddr};
+use crate::models::rfid_tags::RfidTag;
+use crate::models::user::{NewUser, Role, User, UserChangeset};
+use crate::models::user_actions::{NewUserAction, UserAction, UserActionChangeset};
+use crate::models::user_bundle::{BundleAction, BundleConstant, BundleRequest, BundleUser, UserBundle};
+use crate::models::user_requests::{NewUserRequest, UserRequest, UserRequestChangeset};
+use crate::models::websocket::{Ack, CommandError, CommandInfo, Hello, OpCode, Subscriptions};
+
+const COMPONENTS_PREFIX: &str = "#/components/schemas/";
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "smarthub-backend"),
+    servers((url = "/api/v1")),
+    paths(
+        system::get_info, system::get_event_stats, system::get_clients, system::post_reboot, system::post_shutdown,
+        system::proxy_image, backup::post_backup, backup::post_restore,
+        users::get_users, users::post_user, users::get_user_by_id, users::put_user, users::delete_user,
+        transfer::post_user_import, transfer::get_user_export, history::get_history_by_user_id,
+        session::put_user_pin, session::get_user_tags, session::post_user_tag, session::delete_user_tag,
+        session::get_session, session::post_session, session::delete_session,
+        constants::get_constants_by_user_id, constants::get_resolved_constants, constants::post_constant,
+        constants::put_constant, constants::delete_constant_by_user_id_and_name, constants::post_reveal_constant,
+        constants::get_global_constants, constants::post_global_constant, constants::put_global_constant,
+        constants::delete_global_constant, constants::post_reveal_global_constant,
+        actions::get_user_actions_by_user_id, actions::post_user_action, actions::put_user_action, actions::delete_user_action_by_id,
+        requests::get_user_requests_by_user_id, requests::post_user_request, requests::put_user_request,
+        requests::delete_user_request_by_id, requests::execute_user_request_by_id,
+        system::start_wpa_supplicant, system::stop_wpa_supplicant, system::start_scan, system::get_scan_results,
+        system::get_current_network_status, system::connect_wifi, system::disconnect_wifi,
+        auth::post_pairing, auth::post_pairing_confirmation, auth::get_tokens, auth::delete_tokens, auth::delete_token,
+        debug::post_rfid_scan, debug::post_touch,
+    ),
+    components(schemas(
+        ErrorBody, FieldError,
+        User, Role, NewUser, CreateUser, UserChangeset, UserBundle, BundleUser, BundleConstant, BundleRequest, BundleAction,
+        ImportReport, UsernameConflict, RfidConflict, ActionRun, RfidTag, ActiveSession, LoginMethod,
+        LoginRequest, PinChange, TagRequest,
+        Constant, ConstantKind, NewConstant, NewGlobalConstant, UpdateConstant, RevealedConstant,
+        UserAction, NewUserAction, UserActionChangeset, UserRequest, NewUserRequest, UserRequestChangeset, RequestResult,
+        InfoResponse, MessageResponse, NetworkStatusResponse, WifiCredentials, ScanResult, NetworkInterface, Addr, V4IfAddr, V6IfAddr,
+        EventBusStats, ClientInfo, Subscriptions, OpCode, BluetoothDevice,
+        PairingRequest, PairingResponse, PairingConfirmation, IssuedToken, ApiToken, RfidScan,
+        DisplayStatus, NetworkStatus, SessionChanged, DatabaseRestored, ConfigReloaded, PairingRequested, PairingCompleted, RfidDetected,
+        ActionStarted, ActionSucceeded, ActionFailed, RequestExecuted, RequestFailed, UpdateMessage,
+        Ready, ReadyNetwork, ReadyBluetooth, ReadyUpdates, Hello, Ack, CommandError, CommandInfo,
+    )),
+    modifiers(&ApiConventions),
+    security(("api_token" = [])),
+)]
+pub struct ApiDoc;
+
+/// Adds what applies to every operation: the token and the error body
+struct ApiConventions;
+
+impl Modify for ApiConventions {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        openapi.info.version = env!("CARGO_PKG_VERSION").to_string();
+        openapi.info.description = None;
+        openapi.info.license = None;
+
+        if let Some(components) = openapi.components.as_mut() {
+            components.add_security_scheme("api_token", SecurityScheme::Http(
+                HttpBuilder::new()
+                    .scheme(HttpAuthScheme::Bearer)
+                    .description(Some("Issued by pairing, not needed from localhost in kiosk mode"))
+                    .build(),
+            ));
+            components.responses.insert("Error".to_string(), ResponseBuilder::new()
+                .description("Error with a stable code, see `ErrorBody`")
+                .content("application/json", ContentBuilder::new().schema(Ref::from_schema_name("ErrorBody")).build())
+                .build()
+                .into());
+        }
+
+        for path in openapi.paths.paths.values_mut() {
+            for operation in path.operations.values_mut() {
+                operation.responses.responses
+                    .entry("default".to_string())
+                    .or_insert_with(|| Ref::from_response_name("Error").into());
+            }
+        }
+    }
+}
+
+pub async fn get_openapi() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+/// JSON Schema of every WebSocket event. Each event is in `$defs` under its `t`,
+/// the payload types under the same names as in `/openapi.json`.
+pub async fn get_event_schema() -> Json<Value> {
+    let mut defs = Map::new();
+
+    if let Some(components) = ApiDoc::openapi().components {
+        for (name, schema) in components.schemas {
+            defs.insert(name, serde_json::to_value(schema).unwrap_or_default());
+        }
+    }
+
+    for event in EVENTS {
+        defs.insert(event.t.to_string(), json!({
+            "description": event.description,
+            "type": "object",
+            "properties": {
+                "op": { "const": u8::from(event.op) },
+                "t": { "const": event.t },
+                "d": serde_json::to_value((event.payload)()).unwrap_or_default(),
+            },
+            "required": ["op", "t", "d"],
+        }));
+    }
+
+    let mut schema = json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": "smarthub-backend WebSocket events",
+        "oneOf": EVENTS.iter().map(|event| json!({ "$ref": format!("#/$defs/{}", event.t) })).collect::<Vec<_>>(),
+        "$defs": defs,
+    });
+    to_json_schema(&mut schema);
+
+    Json(schema)
+}
+
+/// Rewrites the OpenAPI 3.0 flavour of schemas into plain JSON Schema
+fn to_json_schema(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            if let Some(Value::String(reference)) = map.get_mut("$ref") {
+                if let Some(name) = reference.strip_prefix(COMPONENTS_PREFIX) {
+                    *reference = format!("#/$defs/{}", name);
+                }
+            }
+
+            if let Some(example) = map.remove("example") {
+                map.insert("examples".to_string(), json!([example]));
+            }
+
+            for child in map.values_mut() {
+                to_json_schema(child);
+            }
+
+            if map.remove("nullable") == Some(Value::Bool(true)) {
+                match map.get_mut("type") {
+                    Some(Value::String(kind)) => {
+                        let kind = std::mem::take(kind);
+                        map.insert("type".to_string(), json!([kind, "null"]));
+                        if let Some(Value::Array(values)) = map.get_mut("enum") {
+                            values.push(Value::Null);
+                        }
+                    }
+                    // References and combinations can't take a type
+                    _ => *value = json!({ "oneOf": [Value::Object(std::mem::take(map)), { "type": "null" }] }),
+                }
+            }
+        }
+        Value::Array(values) => {
+            for child in values {
+                to_json_schema(child);
+            }
+        }
+        _ => {}
+    }
+}
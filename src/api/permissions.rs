@@ -0,0 +1,66 @@
+use diesel::result::Error as DieselError;
+
+use crate::api::{AppState, internal_error};
+use crate::api::error::ApiError;
+use crate::models::user::{Role, User};
+use crate::models::user_requests::UserRequest;
+
+/// Something a profile has to be allowed to do. Reading is open to everyone.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Permission {
+    /// Users, network, updates, power and API tokens
+    ManageSystem,
+    /// Pairing and connecting Bluetooth devices
+    ManageDevices,
+    /// Changing or running the constants, actions, requests, PIN and tags of a user
+    UserData(i32),
+}
+
+impl Permission {
+    /// Admins and members can change more than their own data, so selecting them isn't enough
+    /// to log in. They need a PIN or one of their RFID tags.
+    pub fn needs_verified_login(role: Role) -> bool {
+        matches!(role, Role::Admin | Role::Member)
+    }
+
+    pub fn allowed(&self, role: Role, actor_id: i32) -> bool {
+        match (role, self) {
+            (Role::Admin, _) => true,
+            (Role::Member, Permission::ManageDevices | Permission::UserData(_)) => true,
+            (Role::Child, Permission::UserData(user_id)) => *user_id == actor_id,
+            _ => false,
+        }
+    }
+}
+
+/// Checks the permission against the logged in user and returns them. Without a session
+/// nothing may be changed, the first user is created without one, see `users::post_user`.
+pub fn authorize(state: &AppState, permission: Permission) -> Result<Option<User>, ApiError> {
+    let mut conn = state.db_pool.get().map_err(internal_error)?;
+
+    // Reload the user so role changes and deletions apply to running sessions
+    let actor = match state.session.current() {
+        Some(session) => match User::get_by_id(session.user.id, &mut conn) {
+            Ok(user) => Some(user),
+            Err(DieselError::NotFound) => None,
+            Err(e) => return Err(internal_error(e)),
+        },
+        None => None,
+    };
+
+    match actor {
+        Some(user) if permission.allowed(user.role, user.id) => Ok(Some(user)),
+        Some(user) => Err(ApiError::Forbidden(format!("The {} role is not allowed to do this", user.role.as_str()))),
+        None => Err(ApiError::Unauthorized("Log in to do this".to_string())),
+    }
+}
+
+/// Running or changing a request needs access to the data of its owner
+pub fn authorize_request(state: &AppState, request_id: i32) -> Result<Option<User>, ApiError> {
+    let owner = {
+        let mut conn = state.db_pool.get().map_err(internal_error)?;
+        UserRequest::get_by_id(request_id, &mut conn).map_err(ApiError::database("Request"))?.user_id
+    };
+
+    authorize(state, Permission::UserData(owner))
+}
@@ -1,76 +1,133 @@
 use std::sync::Arc;
 
-use axum::extract::{Json, Path, State};
+use axum::extract::{Path, Query, State};
 use axum::http::StatusCode;
+use axum::response::Response;
 use diesel::r2d2::{ConnectionManager, PooledConnection};
 use diesel::SqliteConnection;
-
-use crate::api::{AppState, ErrorMessage, internal_error};
+use validator::Validate;
+
+use crate::api::{AppState, internal_error};
+use crate::api::error::ApiError;
+use crate::api::extract::Json;
+use crate::api::listing::{list_response, ListQuery};
+use crate::api::permissions::{authorize, authorize_request, Permission};
+use crate::handlers::request_handler::{RequestError, RequestResult, run_user_request};
+use crate::models::action_runs::Trigger;
 use crate::models::user_requests::{NewUserRequest, UserRequest, UserRequestChangeset};
 
+#[utoipa::path(
+    get,
+    path = "/requests/{user_id}",
+    tag = "requests",
+    params(ListQuery),
+    responses(
+        (status = 200, description = "OK", body = [UserRequest], headers(
+            ("x-total-count" = i64, description = "Number of matching items"),
+            ("x-next-cursor" = String, description = "Offset of the next page as an opaque cursor, missing on the last page"),
+        )),
+    ),
+)]
 pub async fn get_user_requests_by_user_id(
     Path(id): Path<i32>,
+    Query(query): Query<ListQuery>,
     State(state): State<Arc<AppState>>,
-) -> Result<Json<Vec<UserRequest>>, (StatusCode, Json<ErrorMessage>)> {
+) -> Result<Response, ApiError> {
+    let listing = query.listing(&["id", "name", "created_on"])?;
     let mut conn = state.db_pool.get().map_err(internal_error)?;
 
-    let user_requests_result = UserRequest::get_all_by_user_id(id, &mut conn);
+    let user_requests_result = UserRequest::list_by_user_id(id, &listing, &mut conn);
 
     match user_requests_result {
-        Ok(user_requests) => Ok(Json(user_requests)),
-        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorMessage { message: "Failed to load user requests".to_string() }))),
+        Ok((user_requests, total)) => Ok(list_response(user_requests, total, &listing)),
+        Err(_) => Err(ApiError::Internal("Failed to load user requests".to_string())),
     }
 }
 
+#[utoipa::path(
+    post,
+    path = "/requests",
+    tag = "requests",
+    responses((status = 201, description = "Created")),
+)]
 pub async fn post_user_request(
     State(state): State<Arc<AppState>>,
     Json(new_user_request): Json<NewUserRequest>,
-) -> Result<StatusCode, (StatusCode, Json<ErrorMessage>)> {
+) -> Result<StatusCode, ApiError> {
+    authorize(&state, Permission::UserData(new_user_request.user_id))?;
+    new_user_request.validate()?;
     let mut conn = state.db_pool.get().map_err(internal_error)?;
 
     if action_exists(&new_user_request.user_id, &new_user_request.name, &mut conn) {
-        return Err((StatusCode::FORBIDDEN, Json(ErrorMessage { message: "Request with the same name already exists".to_string() })));
+        return Err(ApiError::Conflict("Request with the same name already exists".to_string()));
     }
 
-
-    let create_result = UserRequest::create(new_user_request, &mut conn);
-
-    match create_result {
-        Ok(_) => Ok(StatusCode::CREATED),
-        Err(_) => Err((StatusCode::BAD_REQUEST, Json(ErrorMessage { message: "Failed to create user request".to_string() }))),
-    }
+    UserRequest::create(new_user_request, &mut conn).map_err(ApiError::database("User request"))?;
+    Ok(StatusCode::CREATED)
 }
 
+#[utoipa::path(
+    delete,
+    path = "/requests/{request_id}",
+    tag = "requests",
+    responses((status = 204, description = "Done")),
+)]
 pub async fn delete_user_request_by_id(
     Path(id): Path<i32>,
     State(state): State<Arc<AppState>>,
-) -> Result<StatusCode, (StatusCode, Json<ErrorMessage>)> {
+) -> Result<StatusCode, ApiError> {
+    authorize_request(&state, id)?;
     let mut conn = state.db_pool.get().map_err(internal_error)?;
 
-    let delete_result = UserRequest::delete_by_id(id, &mut conn);
-
-    match delete_result {
-        Ok(_) => Ok(StatusCode::NO_CONTENT),
-        Err(_) => Err((StatusCode::BAD_REQUEST, Json(ErrorMessage { message: "Failed to delete user request".to_string() }))),
-    }
+    UserRequest::delete_by_id(id, &mut conn).map_err(ApiError::database("User request"))?;
+    Ok(StatusCode::NO_CONTENT)
 }
 
+#[utoipa::path(
+    put,
+    path = "/requests/{request_id}",
+    tag = "requests",
+    responses((status = 204, description = "Done")),
+)]
 pub async fn put_user_request(
     Path(id): Path<i32>,
     State(state): State<Arc<AppState>>,
     Json(changes): Json<UserRequestChangeset>,
-) -> Result<StatusCode, (StatusCode, Json<ErrorMessage>)> {
+) -> Result<StatusCode, ApiError> {
+    authorize_request(&state, id)?;
+    changes.validate()?;
     let mut conn = state.db_pool.get().map_err(internal_error)?;
 
     if action_exists(&id, &changes.name, &mut conn) {
-        return Err((StatusCode::FORBIDDEN, Json(ErrorMessage { message: "Request with the same name already exists".to_string() })));
+        return Err(ApiError::Conflict("Request with the same name already exists".to_string()));
     }
 
-    let update_result = UserRequest::update(id, changes, &mut conn);
+    UserRequest::update(id, changes, &mut conn).map_err(ApiError::database("User request"))?;
+    Ok(StatusCode::NO_CONTENT)
+}
 
-    match update_result {
-        Ok(_) => Ok(StatusCode::NO_CONTENT),
-        Err(_) => Err((StatusCode::BAD_REQUEST, Json(ErrorMessage { message: "Failed to update user request".to_string() }))),
+#[utoipa::path(
+    post,
+    path = "/requests/{request_id}/execute",
+    tag = "requests",
+    responses((status = 200, description = "OK", body = RequestResult)),
+)]
+pub async fn execute_user_request_by_id(
+    Path(id): Path<i32>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<RequestResult>, ApiError> {
+    let actor = authorize_request(&state, id)?;
+
+    match run_user_request(&state, id, Trigger::Api, actor.map(|actor| actor.id)).await {
+        Ok(result) => Ok(Json(result)),
+        Err(e) => Err(match e {
+            RequestError::NotFound => ApiError::NotFound(e.to_string()),
+            RequestError::InvalidParameters(_) => ApiError::Unprocessable(e.to_string()),
+            RequestError::Timeout => ApiError::GatewayTimeout(e.to_string()),
+            RequestError::Failed(_) => ApiError::BadGateway(e.to_string()),
+            RequestError::SecretWithheld(_) => ApiError::Forbidden(e.to_string()),
+            RequestError::Database(_) | RequestError::Secret(_) => ApiError::Internal(e.to_string()),
+        }),
     }
 }
 
@@ -0,0 +1,275 @@
+use std::sync::Arc;
+
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use diesel::r2d2::{ConnectionManager, PooledConnection};
+use diesel::SqliteConnection;
+use serde_derive::Deserialize;
+use utoipa::ToSchema;
+
+use crate::api::{AppState, internal_error};
+use crate::api::error::ApiError;
+use crate::api::extract::Json;
+use crate::api::permissions::{authorize, Permission};
+use crate::handlers::session_handler::{ActiveSession, hash_pin, LoginMethod, PinError, SessionStore};
+use crate::models::rfid_tags::{NewRfidTag, RfidTag};
+use crate::models::user::{Role, User};
+use crate::models::validation;
+
+#[derive(Deserialize, ToSchema)]
+pub struct LoginRequest {
+    pub user_id: i32,
+    pub pin: Option<String>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct PinChange {
+    /// New PIN, `null` removes it
+    pub pin: Option<String>,
+    /// Required when the user already has a PIN
+    pub current_pin: Option<String>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct TagRequest {
+    pub rfid_uid: String,
+}
+
+#[utoipa::path(
+    get,
+    path = "/session",
+    tag = "session",
+    responses(
+        (status = 200, description = "The active session, `null` if nobody is logged in", body = Option<ActiveSession>),
+    ),
+)]
+pub async fn get_session(
+    State(state): State<Arc<AppState>>,
+) -> Json<Option<ActiveSession>> {
+    Json(state.session.current())
+}
+
+#[utoipa::path(
+    post,
+    path = "/session",
+    tag = "session",
+    responses((status = 200, description = "OK", body = ActiveSession)),
+)]
+pub async fn post_session(
+    State(state): State<Arc<AppState>>,
+    Json(login): Json<LoginRequest>,
+) -> Result<Json<ActiveSession>, ApiError> {
+    let mut conn = state.db_pool.get().map_err(internal_error)?;
+
+    let user = User::get_by_id(login.user_id, &mut conn)
+        .map_err(ApiError::database("User"))?;
+
+    let method = login_method(&user, login.pin.as_deref(), &state.session, &mut conn)?;
+
+    Ok(Json(state.session.login(&state.tx, user, method)))
+}
+
+fn login_method(
+    user: &User,
+    pin: Option<&str>,
+    session: &SessionStore,
+    conn: &mut PooledConnection<ConnectionManager<SqliteConnection>>,
+) -> Result<LoginMethod, ApiError> {
+    match (&user.pin_hash, pin) {
+        // Without any admin PIN nobody could set one, so admins select themselves until then
+        (None, _) if user.role == Role::Admin && !User::admin_has_pin(conn).map_err(internal_error)? => {
+            Ok(LoginMethod::Select)
+        }
+        (None, _) if Permission::needs_verified_login(user.role) => {
+            Err(ApiError::Forbidden("Admins and members log in with a PIN or an RFID tag".to_string()))
+        }
+        (None, _) => Ok(LoginMethod::Select),
+        (Some(_), None) => Err(ApiError::Unauthorized("PIN required".to_string())),
+        (Some(pin_hash), Some(pin)) => {
+            session.check_pin(user.id, pin, pin_hash).map_err(pin_error)?;
+            Ok(LoginMethod::Pin)
+        }
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/session",
+    tag = "session",
+    responses((status = 204, description = "Done")),
+)]
+pub async fn delete_session(
+    State(state): State<Arc<AppState>>,
+) -> StatusCode {
+    state.session.logout(&state.tx, "logout");
+    StatusCode::NO_CONTENT
+}
+
+#[utoipa::path(
+    put,
+    path = "/users/{user_id}/pin",
+    tag = "users",
+    responses((status = 204, description = "Done")),
+)]
+pub async fn put_user_pin(
+    Path(user_id): Path<i32>,
+    State(state): State<Arc<AppState>>,
+    Json(change): Json<PinChange>,
+) -> Result<StatusCode, ApiError> {
+    let actor = authorize(&state, Permission::UserData(user_id))?;
+    let mut conn = state.db_pool.get().map_err(internal_error)?;
+
+    let user = User::get_by_id(user_id, &mut conn)
+        .map_err(ApiError::database("User"))?;
+
+    // Admins can reset a forgotten PIN of someone else
+    let is_reset = actor.is_some_and(|actor| actor.role == Role::Admin && actor.id != user_id);
+
+    if let Some(pin_hash) = user.pin_hash.as_deref().filter(|_| !is_reset) {
+        match state.session.check_pin(user_id, change.current_pin.as_deref().unwrap_or_default(), pin_hash) {
+            Ok(()) => {}
+            Err(PinError::Invalid) => return Err(ApiError::Forbidden("Current PIN is invalid".to_string())),
+            Err(e) => return Err(pin_error(e)),
+        }
+    }
+
+    let new_hash = match change.pin {
+        Some(pin) => {
+            validation::pin(&pin).map_err(|_| ApiError::field("pin", "PIN must be 4 to 8 digits"))?;
+            Some(hash_pin(&pin).map_err(ApiError::Internal)?)
+        }
+        // Without a PIN they could only log in with an RFID tag
+        None if Permission::needs_verified_login(user.role) => {
+            return Err(ApiError::field("pin", "Admins and members can't remove their PIN"));
+        }
+        None => None,
+    };
+
+    match User::set_pin_hash(user_id, new_hash, &mut conn) {
+        Ok(_) => Ok(StatusCode::NO_CONTENT),
+        Err(e) => Err(ApiError::database("User")(e)),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/users/{user_id}/tags",
+    tag = "users",
+    responses((status = 200, description = "OK", body = [RfidTag])),
+)]
+pub async fn get_user_tags(
+    Path(user_id): Path<i32>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<Vec<RfidTag>>, ApiError> {
+    let mut conn = state.db_pool.get().map_err(internal_error)?;
+
+    match RfidTag::get_all_by_user_id(user_id, &mut conn) {
+        Ok(tags) => Ok(Json(tags)),
+        Err(_) => Err(ApiError::Internal("Failed to load RFID tags".to_string())),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/users/{user_id}/tags",
+    tag = "users",
+    responses((status = 201, description = "Created")),
+)]
+pub async fn post_user_tag(
+    Path(user_id): Path<i32>,
+    State(state): State<Arc<AppState>>,
+    Json(tag): Json<TagRequest>,
+) -> Result<StatusCode, ApiError> {
+    authorize(&state, Permission::UserData(user_id))?;
+    let mut conn = state.db_pool.get().map_err(internal_error)?;
+
+    let rfid_uid = tag.rfid_uid.trim().to_string();
+    if rfid_uid.is_empty() {
+        return Err(ApiError::field("rfid_uid", "RFID uid cannot be empty"));
+    }
+
+    if User::get_by_id(user_id, &mut conn).is_err() {
+        return Err(ApiError::NotFound("User not found".to_string()));
+    }
+
+    if RfidTag::get_by_uid(&rfid_uid, &mut conn).is_ok() {
+        return Err(ApiError::Conflict("RFID tag is already bound to a user".to_string()));
+    }
+
+    match RfidTag::create(NewRfidTag { user_id, rfid_uid }, &mut conn) {
+        Ok(_) => Ok(StatusCode::CREATED),
+        Err(e) => Err(ApiError::database("RFID tag")(e)),
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/users/{user_id}/tags/{rfid_uid}",
+    tag = "users",
+    responses((status = 204, description = "Done")),
+)]
+pub async fn delete_user_tag(
+    Path((user_id, rfid_uid)): Path<(i32, String)>,
+    State(state): State<Arc<AppState>>,
+) -> Result<StatusCode, ApiError> {
+    authorize(&state, Permission::UserData(user_id))?;
+    let mut conn = state.db_pool.get().map_err(internal_error)?;
+
+    match RfidTag::delete_by_user_id_and_uid(user_id, &rfid_uid, &mut conn) {
+        Ok(0) => Err(ApiError::NotFound("RFID tag not found".to_string())),
+        Ok(_) => Ok(StatusCode::NO_CONTENT),
+        Err(e) => Err(ApiError::database("RFID tag")(e)),
+    }
+}
+
+fn pin_error(error: PinError) -> ApiError {
+    match error {
+        PinError::Invalid => ApiError::Forbidden(error.to_string()),
+        PinError::LockedOut(_) => ApiError::TooManyRequests(error.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use diesel::connection::SimpleConnection;
+    use diesel::r2d2::Pool;
+    use diesel_migrations::MigrationHarness;
+
+    use crate::common::db::{self, MIGRATIONS};
+
+    use super::*;
+
+    /// Last migration before users had roles and PINs
+    const BASELINE: &str = "20240110173940";
+
+    #[test]
+    fn upgraded_admin_logs_in_to_set_a_pin() {
+        let pool = Pool::builder()
+            .max_size(1)
+            .build(ConnectionManager::<SqliteConnection>::new(":memory:"))
+            .unwrap();
+        let mut conn = pool.get().unwrap();
+
+        while conn.run_next_migration(MIGRATIONS).unwrap().to_string() != BASELINE {}
+        conn.batch_execute("INSERT INTO user_users (username, birthday) VALUES ('Max', '1990-01-01'), ('Erika', '1992-01-01');").unwrap();
+        db::run_migrations(&mut conn).unwrap();
+
+        let session = SessionStore::default();
+        let admin = User::get_by_id(1, &mut conn).unwrap();
+        let member = User::get_by_id(2, &mut conn).unwrap();
+        assert_eq!(admin.role, Role::Admin);
+
+        assert_eq!(login_method(&admin, None, &session, &mut conn).unwrap(), LoginMethod::Select);
+        assert!(matches!(login_method(&member, None, &session, &mut conn), Err(ApiError::Forbidden(_))));
+
+        // Once the admin has a PIN, the usual rules apply
+        User::set_pin_hash(admin.id, Some(hash_pin("1234").unwrap()), &mut conn).unwrap();
+        let admin = User::get_by_id(1, &mut conn).unwrap();
+        assert!(matches!(login_method(&admin, None, &session, &mut conn), Err(ApiError::Unauthorized(_))));
+        assert_eq!(login_method(&admin, Some("1234"), &session, &mut conn).unwrap(), LoginMethod::Pin);
+
+        conn.batch_execute("UPDATE user_users SET role = 'admin' WHERE id = 2;").unwrap();
+        let second_admin = User::get_by_id(2, &mut conn).unwrap();
+        assert!(matches!(login_method(&second_admin, None, &session, &mut conn), Err(ApiError::Forbidden(_))));
+    }
+}
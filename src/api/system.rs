@@ -1,37 +1,39 @@
 use std::process::Command;
 use std::sync::Arc;
 
-use axum::Json;
 use log::debug;
 use serde_derive::{Deserialize, Serialize};
-use axum::{
-    body::Bytes,
-    http::{HeaderMap, HeaderValue, StatusCode},
-    response::{IntoResponse, Response},
-    routing::get,
-    Router,
-};
-use axum::extract::Query;
+use utoipa::{IntoParams, ToSchema};
+use axum::response::{IntoResponse, Response};
+use axum::extract::{Query, State};
 use http::header::CONTENT_TYPE;
 use reqwest::Client;
-use tokio::sync::OnceCell;
 
-use crate::api::ErrorMessage;
+use crate::api::AppState;
+use crate::api::error::ApiError;
+use crate::api::extract::Json;
+use crate::api::permissions::{authorize, Permission};
+use crate::common::db;
+use crate::common::event_bus::EventBusStats;
+use crate::handlers::connection_handler::ClientInfo;
+use crate::models::interface::ScanResult;
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 pub struct InfoResponse {
     health: String,
     version: String,
     app_name: String,
     app_description: String,
+    /// Newest database migration, `null` if the database is unreachable
+    schema_version: Option<String>,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 pub struct MessageResponse {
     message: String,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 pub struct NetworkStatusResponse {
     pub ssid: String,
     pub status: String,
@@ -42,16 +44,55 @@ const VERSION: &str = env!("CARGO_PKG_VERSION");
 const NAME: &str = env!("CARGO_PKG_NAME");
 const DESCRIPTION: &str = env!("CARGO_PKG_DESCRIPTION");
 
-pub async fn get_info() -> Result<Json<InfoResponse>, (StatusCode, String)> {
+#[utoipa::path(
+    get,
+    path = "/",
+    tag = "system",
+    security(()),
+    responses((status = 200, description = "OK", body = InfoResponse)),
+)]
+pub async fn get_info(State(state): State<Arc<AppState>>) -> Result<Json<InfoResponse>, ApiError> {
+    let schema_version = state.db_pool.get().ok()
+        .and_then(|mut conn| db::schema_version(&mut conn).ok())
+        .flatten();
+
     Ok(Json(InfoResponse {
         version: VERSION.to_string(),
         health: "healthy".to_string(),
         app_description: DESCRIPTION.to_string(),
         app_name: NAME.to_string(),
+        schema_version,
     }))
 }
 
-pub async fn post_reboot() -> Result<(), (StatusCode, Json<ErrorMessage>)> {
+#[utoipa::path(
+    get,
+    path = "/system/events",
+    tag = "system",
+    responses((status = 200, description = "OK", body = EventBusStats)),
+)]
+pub async fn get_event_stats(State(state): State<Arc<AppState>>) -> Json<EventBusStats> {
+    Json(state.tx.stats())
+}
+
+#[utoipa::path(
+    get,
+    path = "/system/clients",
+    tag = "system",
+    responses((status = 200, description = "OK", body = [ClientInfo])),
+)]
+pub async fn get_clients(State(state): State<Arc<AppState>>) -> Json<Vec<ClientInfo>> {
+    Json(state.clients.list())
+}
+
+#[utoipa::path(
+    post,
+    path = "/system/reboot",
+    tag = "system",
+    responses((status = 200, description = "OK")),
+)]
+pub async fn post_reboot(State(state): State<Arc<AppState>>) -> Result<(), ApiError> {
+    authorize(&state, Permission::ManageSystem)?;
     debug!("Rebooting system...");
     let status = Command::new("sudo")
         .arg("reboot")
@@ -59,11 +100,18 @@ pub async fn post_reboot() -> Result<(), (StatusCode, Json<ErrorMessage>)> {
 
     match status {
         Ok(_) => Ok(()),
-        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorMessage { message: "Failed to reboot".to_string() }))),
+        Err(_) => Err(ApiError::Internal("Failed to reboot".to_string())),
     }
 }
 
-pub async fn post_shutdown() -> Result<Json<()>, (StatusCode, Json<ErrorMessage>)> {
+#[utoipa::path(
+    post,
+    path = "/system/shutdown",
+    tag = "system",
+    responses((status = 200, description = "OK")),
+)]
+pub async fn post_shutdown(State(state): State<Arc<AppState>>) -> Result<Json<()>, ApiError> {
+    authorize(&state, Permission::ManageSystem)?;
     debug!("Shutting down system...");
     let status = Command::new("sudo")
         .arg("shutdown")
@@ -72,16 +120,18 @@ pub async fn post_shutdown() -> Result<Json<()>, (StatusCode, Json<ErrorMessage>
 
     match status {
         Ok(_) => Ok(Json(())),
-        Err(_) => {
-            let error_message = Json(ErrorMessage {
-                message: "Failed to shutdown".to_string(),
-            });
-            Err((StatusCode::INTERNAL_SERVER_ERROR, error_message))
-        }
+        Err(_) => Err(ApiError::Internal("Failed to shutdown".to_string())),
     }
 }
 
-pub async fn start_wpa_supplicant() -> Result<Json<MessageResponse>, (StatusCode, Json<ErrorMessage>)> {
+#[utoipa::path(
+    post,
+    path = "/wifi/start",
+    tag = "wifi",
+    responses((status = 200, description = "OK", body = MessageResponse)),
+)]
+pub async fn start_wpa_supplicant(State(state): State<Arc<AppState>>) -> Result<Json<MessageResponse>, ApiError> {
+    authorize(&state, Permission::ManageSystem)?;
     debug!("Starting wpa_supplicant...");
     let status = Command::new("sudo")
         .arg("systemctl")
@@ -91,11 +141,18 @@ pub async fn start_wpa_supplicant() -> Result<Json<MessageResponse>, (StatusCode
 
     match status {
         Ok(_) => Ok(Json(MessageResponse { message: "wpa_supplicant started".to_string() })),
-        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorMessage { message: "Failed to start wpa_supplicant".to_string() }))),
+        Err(_) => Err(ApiError::Internal("Failed to start wpa_supplicant".to_string())),
     }
 }
 
-pub async fn stop_wpa_supplicant() -> Result<Json<MessageResponse>, (StatusCode, Json<ErrorMessage>)> {
+#[utoipa::path(
+    post,
+    path = "/wifi/stop",
+    tag = "wifi",
+    responses((status = 200, description = "OK", body = MessageResponse)),
+)]
+pub async fn stop_wpa_supplicant(State(state): State<Arc<AppState>>) -> Result<Json<MessageResponse>, ApiError> {
+    authorize(&state, Permission::ManageSystem)?;
     debug!("Stopping wpa_supplicant...");
     let status = Command::new("sudo")
         .arg("systemctl")
@@ -105,39 +162,45 @@ pub async fn stop_wpa_supplicant() -> Result<Json<MessageResponse>, (StatusCode,
 
     match status {
         Ok(_) => Ok(Json(MessageResponse { message: "wpa_supplicant stopped".to_string() })),
-        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorMessage { message: "Failed to stop wpa_supplicant".to_string() }))),
+        Err(_) => Err(ApiError::Internal("Failed to stop wpa_supplicant".to_string())),
     }
 }
 
-pub async fn start_scan() -> Result<Json<MessageResponse>, (StatusCode, Json<ErrorMessage>)> {
+#[utoipa::path(
+    post,
+    path = "/wifi/scan/start",
+    tag = "wifi",
+    responses(
+        (status = 200, description = "Scan started, results follow as `SCAN_RESULTS`", body = MessageResponse),
+    ),
+)]
+pub async fn start_scan(State(state): State<Arc<AppState>>) -> Result<Json<MessageResponse>, ApiError> {
+    authorize(&state, Permission::ManageSystem)?;
     debug!("Starting Wi-Fi scan...");
     let status = Command::new("wpa_cli")
         .arg("scan")
         .arg("-i")
-        .arg("wlan0")
+        .arg(&state.network.interface)
         .status();
 
     match status {
         Ok(_) => Ok(Json(MessageResponse { message: "Scan started".to_string() })),
-        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorMessage { message: "Failed to start scan".to_string() }))),
+        Err(_) => Err(ApiError::Internal("Failed to start scan".to_string())),
     }
 }
 
-#[derive(Serialize)]
-pub struct ScanResult {
-    bssid: String,
-    frequency: String,
-    signal_level: String,
-    flags: String,
-    ssid: String,
-}
-
-pub async fn get_scan_results() -> Result<Json<Vec<ScanResult>>, (StatusCode, Json<ErrorMessage>)> {
+#[utoipa::path(
+    get,
+    path = "/wifi/scan/results",
+    tag = "wifi",
+    responses((status = 200, description = "OK", body = [ScanResult])),
+)]
+pub async fn get_scan_results(State(state): State<Arc<AppState>>) -> Result<Json<Vec<ScanResult>>, ApiError> {
     debug!("Retrieving scan results...");
     let output = Command::new("wpa_cli")
         .arg("scan_results")
         .arg("-i")
-        .arg("wlan0")
+        .arg(&state.network.interface)
         .output();
 
     match output {
@@ -161,18 +224,24 @@ pub async fn get_scan_results() -> Result<Json<Vec<ScanResult>>, (StatusCode, Js
 
                 Ok(Json(results))
             } else {
-                Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorMessage { message: "Failed to get scan results".to_string() })))
+                Err(ApiError::Internal("Failed to get scan results".to_string()))
             }
         }
-        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorMessage { message: "Failed to execute scan results command".to_string() }))),
+        Err(_) => Err(ApiError::Internal("Failed to execute scan results command".to_string())),
     }
 }
 
-pub async fn get_current_network_status() -> Result<Json<NetworkStatusResponse>, (StatusCode, Json<ErrorMessage>)> {
+#[utoipa::path(
+    get,
+    path = "/wifi/status",
+    tag = "wifi",
+    responses((status = 200, description = "OK", body = NetworkStatusResponse)),
+)]
+pub async fn get_current_network_status(State(state): State<Arc<AppState>>) -> Result<Json<NetworkStatusResponse>, ApiError> {
     let output = Command::new("wpa_cli")
         .arg("status")
         .arg("-i")
-        .arg("wlan0")
+        .arg(&state.network.interface)
         .output();
 
     match output {
@@ -201,20 +270,27 @@ pub async fn get_current_network_status() -> Result<Json<NetworkStatusResponse>,
                     ip_address,
                 }))
             } else {
-                Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorMessage { message: "Failed to get network status".to_string() })))
+                Err(ApiError::Internal("Failed to get network status".to_string()))
             }
         }
-        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorMessage { message: "Failed to execute status command".to_string() }))),
+        Err(_) => Err(ApiError::Internal("Failed to execute status command".to_string())),
     }
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 pub struct WifiCredentials {
     ssid: String,
     psk: Option<String>, // PSK is optional for open networks
 }
 
-pub async fn connect_wifi(Json(credentials): Json<WifiCredentials>) -> Result<Json<MessageResponse>, (StatusCode, Json<ErrorMessage>)> {
+#[utoipa::path(
+    post,
+    path = "/wifi/connect",
+    tag = "wifi",
+    responses((status = 200, description = "OK", body = MessageResponse)),
+)]
+pub async fn connect_wifi(State(state): State<Arc<AppState>>, Json(credentials): Json<WifiCredentials>) -> Result<Json<MessageResponse>, ApiError> {
+    authorize(&state, Permission::ManageSystem)?;
     debug!("Connecting to Wi-Fi...");
 
     // Create the wpa_supplicant configuration content
@@ -231,20 +307,15 @@ pub async fn connect_wifi(Json(credentials): Json<WifiCredentials>) -> Result<Js
     };
 
     // Write the configuration to the wpa_supplicant.conf file
-    let write_status = std::fs::write("/etc/wpa_supplicant/wpa_supplicant.conf", config_content);
+    let write_status = std::fs::write(&state.network.wpa_supplicant_conf, config_content);
 
     if write_status.is_err() {
-        return Err((
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ErrorMessage {
-                message: "Failed to write wpa_supplicant configuration".to_string(),
-            }),
-        ));
+        return Err(ApiError::Internal("Failed to write wpa_supplicant configuration".to_string()));
     }
 
     // Restart wpa_supplicant to apply the new configuration
     let reconfig_status = tokio::process::Command::new("systemctl")
-        .args(&["restart", "wpa_supplicant"])
+        .args(["restart", "wpa_supplicant"])
         .status()
         .await;
 
@@ -252,22 +323,24 @@ pub async fn connect_wifi(Json(credentials): Json<WifiCredentials>) -> Result<Js
         Ok(_) => Ok(Json(MessageResponse {
             message: "Connected to Wi-Fi".to_string(),
         })),
-        Err(_) => Err((
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ErrorMessage {
-                message: "Failed to reconfigure wpa_supplicant".to_string(),
-            }),
-        )),
+        Err(_) => Err(ApiError::Internal("Failed to reconfigure wpa_supplicant".to_string())),
     }
 }
 
-pub async fn disconnect_wifi() -> Result<Json<MessageResponse>, (StatusCode, Json<ErrorMessage>)> {
+#[utoipa::path(
+    post,
+    path = "/wifi/disconnect",
+    tag = "wifi",
+    responses((status = 200, description = "OK", body = MessageResponse)),
+)]
+pub async fn disconnect_wifi(State(state): State<Arc<AppState>>) -> Result<Json<MessageResponse>, ApiError> {
+    authorize(&state, Permission::ManageSystem)?;
     debug!("Disconnecting from Wi-Fi...");
 
     let status = Command::new("sudo")
         .arg("wpa_cli")
         .arg("-i")
-        .arg("wlan0")
+        .arg(&state.network.interface)
         .arg("disconnect")
         .status();
 
@@ -275,40 +348,44 @@ pub async fn disconnect_wifi() -> Result<Json<MessageResponse>, (StatusCode, Jso
         Ok(_) => Ok(Json(MessageResponse {
             message: "Disconnected from Wi-Fi".to_string(),
         })),
-        Err(_) => Err((
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ErrorMessage {
-                message: "Failed to disconnect from Wi-Fi".to_string(),
-            }),
-        )),
+        Err(_) => Err(ApiError::Internal("Failed to disconnect from Wi-Fi".to_string())),
     }
 }
 
-
-#[derive(Deserialize)]
-struct ImageQuery {
-    url: String,
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ImageQuery {
+    url: Option<String>,
 }
 
+#[utoipa::path(
+    get,
+    path = "/proxy-image",
+    tag = "system",
+    params(ImageQuery),
+    responses(
+        (status = 200, description = "The image", content_type = "image/png", body = Vec<u8>),
+    ),
+)]
 pub async fn proxy_image(
-    Query(params): Query<std::collections::HashMap<String, String>>,
+    Query(query): Query<ImageQuery>,
     client: axum::Extension<Arc<Client>>,
-) -> Result<Response, StatusCode> {
-    let image_url = params.get("url").ok_or(StatusCode::BAD_REQUEST)?;
+) -> Result<Response, ApiError> {
+    let image_url = query.url.ok_or_else(|| ApiError::field("url", "Missing image url"))?;
     let response = client
-        .get(image_url)
+        .get(&image_url)
         .send()
         .await
-        .map_err(|_| StatusCode::BAD_GATEWAY)?;
+        .map_err(|e| ApiError::BadGateway(format!("Failed to load image: {}", e)))?;
 
     if response.status().is_success() {
         let bytes = response
             .bytes()
             .await
-            .map_err(|_| StatusCode::BAD_GATEWAY)?;
+            .map_err(|e| ApiError::BadGateway(format!("Failed to load image: {}", e)))?;
 
         Ok(([(CONTENT_TYPE, "image/png")], bytes.to_vec()).into_response())
     } else {
-        Err(StatusCode::BAD_GATEWAY)
+        Err(ApiError::BadGateway(format!("Image server responded with {}", response.status())))
     }
 }
\ No newline at end of file
@@ -0,0 +1,250 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use diesel::Connection;
+use diesel::r2d2::{ConnectionManager, PooledConnection};
+use diesel::SqliteConnection;
+use log::info;
+use serde_derive::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use validator::Validate;
+
+use crate::api::{AppState, internal_error};
+use crate::api::error::ApiError;
+use crate::api::extract::Json;
+use crate::api::permissions::{authorize, Permission};
+use crate::common::secrets::SecretError;
+use crate::api::constants::stored_value;
+use crate::models::constants::{Constant, ConstantKind};
+use crate::models::rfid_tags::{NewRfidTag, RfidTag};
+use crate::models::user::{NewUser, User};
+use crate::models::user_actions::{NewUserAction, UserAction};
+use crate::models::user_bundle::{BUNDLE_VERSION, BundleAction, BundleConstant, BundleRequest, BundleUser, remap_details, UserBundle};
+use crate::models::user_requests::{NewUserRequest, UserRequest};
+
+#[derive(Deserialize, Default, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ImportOptions {
+    #[serde(default)]
+    pub on_username_conflict: UsernameConflict,
+    #[serde(default)]
+    pub on_rfid_conflict: RfidConflict,
+}
+
+#[derive(Deserialize, Default, PartialEq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum UsernameConflict {
+    #[default]
+    Fail,
+    /// Import as "name (2)", "name (3)", ...
+    Rename,
+}
+
+#[derive(Deserialize, Default, PartialEq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum RfidConflict {
+    #[default]
+    Fail,
+    /// Leave out actions and tags whose RFID uid is already in use
+    Skip,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ImportReport {
+    pub user_id: i32,
+    pub username: String,
+    pub constants: usize,
+    pub requests: usize,
+    pub actions: usize,
+    pub tags: usize,
+    pub skipped_rfid_uids: Vec<String>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/users/{user_id}/export",
+    tag = "users",
+    responses((status = 200, description = "OK", body = UserBundle)),
+)]
+pub async fn get_user_export(
+    Path(user_id): Path<i32>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<UserBundle>, ApiError> {
+    let actor = authorize(&state, Permission::UserData(user_id))?;
+    let mut conn = state.db_pool.get().map_err(internal_error)?;
+
+    let user = User::get_by_id(user_id, &mut conn)
+        .map_err(ApiError::database("User"))?;
+
+    // Like revealing them, only the owner may export secrets in plain text
+    let is_owner = actor.is_some_and(|actor| actor.id == user_id);
+    let constants = Constant::get_all_by_user_id(user_id, &mut conn)
+        .map_err(internal_error)?
+        .into_iter()
+        .filter(|c| is_owner || c.kind != ConstantKind::Secret)
+        .map(|c| Ok(BundleConstant { value: c.plain_value(&state.secrets)?, name: c.name, kind: c.kind }))
+        .collect::<Result<Vec<_>, SecretError>>()
+        .map_err(internal_error)?;
+    let requests = UserRequest::get_all_by_user_id(user_id, &mut conn).map_err(internal_error)?;
+    let actions = UserAction::get_all_by_user_id(user_id, &mut conn).map_err(internal_error)?;
+    let tags = RfidTag::get_all_by_user_id(user_id, &mut conn).map_err(internal_error)?;
+
+    Ok(Json(UserBundle {
+        version: BUNDLE_VERSION,
+        exported_on: chrono::Utc::now().naive_utc(),
+        user: BundleUser {
+            id: user.id,
+            username: user.username,
+            theme: user.theme,
+            birthday: user.birthday,
+            language: user.language,
+            keyboard: user.keyboard,
+            role: user.role,
+        },
+        constants,
+        requests: requests.into_iter().map(|r| BundleRequest { id: r.id, name: r.name, endpoint: r.endpoint, parameters: r.parameters }).collect(),
+        actions: actions.into_iter().map(|a| BundleAction { rfid_uid: a.rfid_uid, type_name: a.type_name, details: a.details }).collect(),
+        tags: tags.into_iter().map(|t| t.rfid_uid).collect(),
+    }))
+}
+
+/// Recreates an exported user as a new user, all or nothing
+#[utoipa::path(
+    post,
+    path = "/users/import",
+    tag = "users",
+    params(ImportOptions),
+    responses((status = 201, description = "Created", body = ImportReport)),
+)]
+pub async fn post_user_import(
+    Query(options): Query<ImportOptions>,
+    State(state): State<Arc<AppState>>,
+    Json(mut bundle): Json<UserBundle>,
+) -> Result<(StatusCode, Json<ImportReport>), ApiError> {
+    authorize(&state, Permission::ManageSystem)?;
+    let mut conn = state.db_pool.get().map_err(internal_error)?;
+
+    if bundle.version > BUNDLE_VERSION {
+        return Err(ApiError::Unprocessable(format!("Bundle version {} is not supported, this hub reads up to version {}", bundle.version, BUNDLE_VERSION)));
+    }
+
+    bundle.validate()?;
+
+    let username = bundle.user.username.trim().to_string();
+
+    let username = match (User::get_by_username(&username, &mut conn).is_ok(), &options.on_username_conflict) {
+        (false, _) => username,
+        (true, UsernameConflict::Rename) => free_username(&username, &mut conn),
+        (true, UsernameConflict::Fail) => {
+            return Err(ApiError::Conflict(format!("User {} already exists", username)));
+        }
+    };
+
+    // Uids already used on this hub, or more than once within the bundle
+    let mut conflicts = Vec::new();
+    let mut seen = HashSet::new();
+    let mut actions = Vec::new();
+    for action in std::mem::take(&mut bundle.actions) {
+        let in_use = UserAction::get_by_rfid_id(&action.rfid_uid, &mut conn).map_err(internal_error)?.is_some();
+        if in_use || !seen.insert(action.rfid_uid.clone()) {
+            conflicts.push(action.rfid_uid);
+        } else {
+            actions.push(action);
+        }
+    }
+
+    let mut seen = HashSet::new();
+    let mut tags = Vec::new();
+    for tag in std::mem::take(&mut bundle.tags) {
+        if RfidTag::get_by_uid(&tag, &mut conn).is_ok() || !seen.insert(tag.clone()) {
+            conflicts.push(tag);
+        } else {
+            tags.push(tag);
+        }
+    }
+
+    if !conflicts.is_empty() && options.on_rfid_conflict == RfidConflict::Fail {
+        return Err(ApiError::Conflict(format!("RFID uids already in use: {}", conflicts.join(", "))));
+    }
+    bundle.actions = actions;
+    bundle.tags = tags;
+
+    let constants = bundle.constants.iter()
+        .map(|c| Ok((c.name.as_str(), stored_value(&state.secrets, c.kind, &c.value)?, c.kind)))
+        .collect::<Result<Vec<_>, ApiError>>()?;
+
+    let report = conn.transaction::<_, diesel::result::Error, _>(|conn| {
+        User::new(NewUser {
+            username: username.clone(),
+            birthday: bundle.user.birthday,
+            theme: bundle.user.theme,
+            language: bundle.user.language.clone(),
+            keyboard: bundle.user.keyboard.clone(),
+            role: bundle.user.role,
+        }, conn)?;
+        let user_id = User::get_by_username(&username, conn)?.id;
+
+        for (name, value, kind) in &constants {
+            Constant::create(user_id, name, value, *kind, conn)?;
+        }
+
+        let mut request_ids = HashMap::new();
+        for request in &bundle.requests {
+            UserRequest::create(NewUserRequest {
+                user_id,
+                name: request.name.clone(),
+                endpoint: request.endpoint.clone(),
+                parameters: request.parameters.clone(),
+            }, conn)?;
+
+            let created = UserRequest::get_all_by_user_id_and_name(user_id, &request.name, conn)?;
+            if let Some(new_id) = created.iter().map(|r| r.id).max() {
+                request_ids.insert(request.id, new_id);
+            }
+        }
+
+        for action in &bundle.actions {
+            let details = match serde_json::from_str(&action.details) {
+                Ok(mut details) => {
+                    remap_details(&mut details, &request_ids, (bundle.user.id, user_id));
+                    details.to_string()
+                }
+                Err(_) => action.details.clone(),
+            };
+
+            UserAction::create(NewUserAction {
+                user_id,
+                rfid_uid: action.rfid_uid.clone(),
+                type_name: action.type_name.clone(),
+                details,
+            }, conn)?;
+        }
+
+        for tag in &bundle.tags {
+            RfidTag::create(NewRfidTag { user_id, rfid_uid: tag.clone() }, conn)?;
+        }
+
+        Ok(ImportReport {
+            user_id,
+            username: username.clone(),
+            constants: bundle.constants.len(),
+            requests: bundle.requests.len(),
+            actions: bundle.actions.len(),
+            tags: bundle.tags.len(),
+            skipped_rfid_uids: conflicts,
+        })
+    }).map_err(internal_error)?;
+
+    info!("Imported user {} ({})", report.username, report.user_id);
+
+    Ok((StatusCode::CREATED, Json(report)))
+}
+
+fn free_username(username: &str, conn: &mut PooledConnection<ConnectionManager<SqliteConnection>>) -> String {
+    (2..)
+        .map(|n| format!("{} ({})", username, n))
+        .find(|candidate| User::get_by_username(candidate, conn).is_err())
+        .unwrap_or_else(|| username.to_string())
+}
@@ -1,100 +1,189 @@
 use std::sync::Arc;
 
-use axum::extract::{Path, State};
-use axum::Json;
+use axum::extract::{Path, Query, State};
+use axum::response::Response;
+use diesel::Connection;
+use diesel::r2d2::{ConnectionManager, PooledConnection};
+use diesel::SqliteConnection;
 use http::StatusCode;
+use log::info;
+use serde_derive::Deserialize;
+use utoipa::ToSchema;
+use validator::Validate;
+
+use crate::api::{AppState, internal_error};
+use crate::api::error::ApiError;
+use crate::api::extract::Json;
+use crate::api::listing::{list_response, ListQuery};
+use crate::api::permissions::{authorize, Permission};
+use crate::handlers::session_handler::hash_pin;
+use crate::models::user::{NewUser, Role, User, UserChangeset};
+use crate::models::validation::pin as pin_format;
+
+#[derive(Deserialize, ToSchema, Validate)]
+pub struct CreateUser {
+    #[serde(flatten)]
+    #[validate(nested)]
+    pub user: NewUser,
+    /// Required for admins and members, who can't log in without one
+    #[validate(custom(function = "pin_format"))]
+    pub pin: Option<String>,
+}
 
-use crate::api::{AppState, ErrorMessage, internal_error};
-use crate::models::user::{NewUser, User, UserChangeset};
-
+#[utoipa::path(
+    get,
+    path = "/users",
+    tag = "users",
+    params(ListQuery),
+    responses(
+        (status = 200, description = "OK", body = [User], headers(
+            ("x-total-count" = i64, description = "Number of matching items"),
+            ("x-next-cursor" = String, description = "Offset of the next page as an opaque cursor, missing on the last page"),
+        )),
+    ),
+)]
 pub async fn get_users(
+    Query(query): Query<ListQuery>,
     State(state): State<Arc<AppState>>,
-) -> Result<Json<Vec<User>>, (StatusCode, Json<ErrorMessage>)> {
+) -> Result<Response, ApiError> {
+    let listing = query.listing(&["id", "username", "created_on"])?;
     let mut conn = state.db_pool.get().map_err(internal_error)?;
 
-    let users_result = User::all(&mut conn);
+    let users_result = User::list(&listing, &mut conn);
 
     match users_result {
-        Ok(users) => Ok(Json(users)),
-        Err(_) => Err((StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorMessage { message: "Failed to load all users".to_string() }))),
+        Ok((users, total)) => Ok(list_response(users, total, &listing)),
+        Err(_) => Err(ApiError::Internal("Failed to load all users".to_string())),
     }
 }
 
+#[utoipa::path(
+    get,
+    path = "/users/{user_id}",
+    tag = "users",
+    responses((status = 200, description = "OK", body = User)),
+)]
 pub async fn get_user_by_id(
     Path(user_id): Path<i32>,
     State(state): State<Arc<AppState>>,
-) -> Result<Json<User>, (StatusCode, Json<ErrorMessage>)> {
+) -> Result<Json<User>, ApiError> {
     let mut conn = state.db_pool.get().map_err(internal_error)?;
 
-    let user_result = User::get_by_id(user_id, &mut conn);
-
-    match user_result {
-        Ok(user) => Ok(Json(user)),
-        Err(_) => Err((StatusCode::NOT_FOUND, Json(ErrorMessage { message: "User not found".to_string() }))),
-    }
+    User::get_by_id(user_id, &mut conn)
+        .map(Json)
+        .map_err(ApiError::database("User"))
 }
 
+/// The first user sets up the hub, so it is created without a session and becomes an admin
+#[utoipa::path(
+    post,
+    path = "/users",
+    tag = "users",
+    request_body = CreateUser,
+    responses((status = 204, description = "Done")),
+)]
 pub async fn post_user(
     State(state): State<Arc<AppState>>,
-    Json(new_user): Json<NewUser>,
-) -> Result<StatusCode, (StatusCode, Json<ErrorMessage>)> {
+    Json(mut create): Json<CreateUser>,
+) -> Result<StatusCode, ApiError> {
     let mut conn = state.db_pool.get().map_err(internal_error)?;
 
-    if new_user.username.trim().is_empty() {
-        return Err((
-            StatusCode::BAD_REQUEST,
-            Json(ErrorMessage {
-                message: "Username cannot be empty".to_string(),
-            }),
-        ));
+    let is_setup = User::count(&mut conn).map_err(internal_error)? == 0;
+    if is_setup {
+        create.user.role = Role::Admin;
+    } else {
+        authorize(&state, Permission::ManageSystem)?;
     }
 
+    create.validate()?;
+    let CreateUser { user: new_user, pin } = create;
+
+    let pin_hash = match pin {
+        Some(pin) => Some(hash_pin(&pin).map_err(ApiError::Internal)?),
+        None if Permission::needs_verified_login(new_user.role) => {
+            return Err(ApiError::field("pin", "Admins and members need a PIN"));
+        }
+        None => None,
+    };
+
     let existing_user = User::get_by_username(&new_user.username, &mut conn);
-    if let Ok(_) = existing_user {
-        return Err((StatusCode::FORBIDDEN, Json(ErrorMessage { message: "User with the same name already exists".to_string() })));
+    if existing_user.is_ok() {
+        return Err(ApiError::Conflict("User with the same name already exists".to_string()));
     }
 
-    let user_result = User::new(new_user, &mut conn);
-    match user_result {
-        Ok(_) => Ok(StatusCode::NO_CONTENT),
-        Err(_) => Err((StatusCode::BAD_REQUEST, Json(ErrorMessage { message: "Failed to create user".to_string() }))),
+    let username = new_user.username.clone();
+    conn.transaction(|conn| {
+        User::new(new_user, conn)?;
+        let user = User::get_by_username(&username, conn)?;
+        User::set_pin_hash(user.id, pin_hash, conn)
+    }).map_err(ApiError::database("User"))?;
+
+    if is_setup {
+        info!("Created {} as the first admin", username);
     }
+
+    Ok(StatusCode::NO_CONTENT)
 }
 
+#[utoipa::path(
+    delete,
+    path = "/users/{user_id}",
+    tag = "users",
+    responses((status = 204, description = "Done")),
+)]
 pub async fn delete_user(
     State(state): State<Arc<AppState>>,
     Path(user_id): Path<i32>,
-) -> Result<StatusCode, (StatusCode, Json<ErrorMessage>)> {
+) -> Result<StatusCode, ApiError> {
+    authorize(&state, Permission::ManageSystem)?;
     let mut conn = state.db_pool.get().map_err(internal_error)?;
 
-    let user_result = User::delete(user_id, &mut conn);
-    match user_result {
-        Ok(_) => Ok(StatusCode::NO_CONTENT),
-        Err(_) => Err((StatusCode::BAD_REQUEST, Json(ErrorMessage { message: "Failed to delete user".to_string() }))),
+    if is_last_admin(user_id, &mut conn)? {
+        return Err(ApiError::Conflict("Cannot delete the last admin".to_string()));
+    }
+
+    match User::delete(user_id, &mut conn).map_err(ApiError::database("User"))? {
+        0 => Err(ApiError::NotFound("User not found".to_string())),
+        _ => {
+            state.session.logout_user(&state.tx, user_id, "user_deleted");
+            Ok(StatusCode::NO_CONTENT)
+        }
     }
 }
 
+#[utoipa::path(
+    put,
+    path = "/users/{user_id}",
+    tag = "users",
+    responses((status = 204, description = "Done")),
+)]
 pub async fn put_user(
     State(state): State<Arc<AppState>>,
     Path(user_id): Path<i32>,
     Json(updated_user): Json<UserChangeset>,
-) -> Result<StatusCode, (StatusCode, Json<ErrorMessage>)> {
+) -> Result<StatusCode, ApiError> {
+    // Profiles may edit their own settings, but only admins hand out roles
+    let permission = match updated_user.role {
+        Some(_) => Permission::ManageSystem,
+        None => Permission::UserData(user_id),
+    };
+    authorize(&state, permission)?;
+    updated_user.validate()?;
     let mut conn = state.db_pool.get().map_err(internal_error)?;
 
-    if let Some(ref username) = updated_user.username {
-        if username.trim().is_empty() {
-            return Err((
-                StatusCode::BAD_REQUEST,
-                Json(ErrorMessage {
-                    message: "Username cannot be empty".to_string(),
-                }),
-            ));
-        }
+    if updated_user.role.is_some_and(|role| role != Role::Admin) && is_last_admin(user_id, &mut conn)? {
+        return Err(ApiError::Conflict("Cannot demote the last admin".to_string()));
     }
-    
-    let user_result = User::update(user_id, updated_user, &mut conn);
-    match user_result {
-        Ok(_) => Ok(StatusCode::NO_CONTENT),
-        Err(_) => Err((StatusCode::BAD_REQUEST, Json(ErrorMessage { message: "Failed to update user".to_string() }))),
+
+    match User::update(user_id, updated_user, &mut conn).map_err(ApiError::database("User"))? {
+        0 => Err(ApiError::NotFound("User not found".to_string())),
+        _ => Ok(StatusCode::NO_CONTENT),
     }
-}
\ No newline at end of file
+}
+
+fn is_last_admin(user_id: i32, conn: &mut PooledConnection<ConnectionManager<SqliteConnection>>) -> Result<bool, ApiError> {
+    match User::get_by_id(user_id, conn) {
+        Ok(user) if user.role == Role::Admin => Ok(User::count_by_role(Role::Admin, conn).map_err(internal_error)? <= 1),
+        _ => Ok(false),
+    }
+}
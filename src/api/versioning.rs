@@ -0,0 +1,108 @@
+use axum::extract::{Request, State};
+use axum::http::{HeaderName, HeaderValue};
+use axum::middleware::Next;
+use axum::response::Response;
+
+use crate::config::SharedConfig;
+
+/// Prefix of the current API, new clients should use it
+pub const V1: &str = "/api/v1";
+
+/// When the unprefixed routes were deprecated (2026-10-17), in the `Deprecation` header's format
+const LEGACY_DEPRECATED_AT: &str = "@1792195200";
+
+const DEPRECATION: HeaderName = HeaderName::from_static("deprecation");
+const SUNSET: HeaderName = HeaderName::from_static("sunset");
+
+/// Marks responses of the unprefixed routes as deprecated (RFC 9745) and links the `/api/v1` route
+/// that replaces them. The `Sunset` header (RFC 8594) is only sent once a date is configured.
+pub async fn deprecated(State(config): State<SharedConfig>, request: Request, next: Next) -> Response {
+    let successor = format!("<{}>; rel=\"successor-version\"", successor_path(request.uri().path()));
+    let mut response = next.run(request).await;
+    let headers = response.headers_mut();
+
+    headers.insert(DEPRECATION, HeaderValue::from_static(LEGACY_DEPRECATED_AT));
+    let sunset = config.borrow().server.legacy_routes.sunset;
+    if let Some(sunset) = sunset {
+        let date = sunset.format("%a, %d %b %Y 00:00:00 GMT").to_string();
+        if let Ok(value) = HeaderValue::from_str(&date) {
+            headers.insert(SUNSET, value);
+        }
+    }
+    if let Ok(value) = HeaderValue::from_str(&successor) {
+        headers.append(axum::http::header::LINK, value);
+    }
+
+    response
+}
+
+/// The nested router serves its root at `/api/v1`, `/api/v1/` is a 404
+fn successor_path(path: &str) -> String {
+    match path {
+        "/" => V1.to_string(),
+        path => format!("{}{}", V1, path),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use ::config::{File, FileFormat};
+    use axum::middleware;
+    use axum::Router;
+    use axum::routing::get;
+    use tokio::net::TcpListener;
+    use tokio::sync::watch;
+
+    use crate::config::Config;
+
+    use super::*;
+
+    const CONFIG: &str = r#"
+        [app]
+        environment = "dev"
+        [log]
+        file = "latest.api.log"
+        [server]
+        address = "0.0.0.0"
+        port = 6814
+        [database]
+        connection_string = "Database.db"
+    "#;
+
+    #[test]
+    fn prefixes_legacy_paths() {
+        assert_eq!(successor_path("/wifi/status"), "/api/v1/wifi/status");
+        assert_eq!(successor_path("/"), "/api/v1");
+    }
+
+    #[tokio::test]
+    async fn successor_links_exist() {
+        let config: Config = ::config::Config::builder()
+            .add_source(File::from_str(CONFIG, FileFormat::Toml))
+            .build()
+            .and_then(|config| config.try_deserialize())
+            .unwrap();
+        let (_sender, config) = watch::channel(Arc::new(config));
+
+        // Laid out like `api::init`
+        let v1 = || Router::new().route("/", get(|| async { "info" })).route("/wifi/status", get(|| async { "status" }));
+        let app = Router::new()
+            .nest(V1, v1())
+            .merge(v1().route_layer(middleware::from_fn_with_state(config, deprecated)));
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        for legacy in ["/", "/wifi/status"] {
+            let response = reqwest::get(format!("http://{}{}", address, legacy)).await.unwrap();
+            let link = response.headers()[axum::http::header::LINK].to_str().unwrap();
+            let successor = link.trim_start_matches('<').split('>').next().unwrap();
+
+            let response = reqwest::get(format!("http://{}{}", address, successor)).await.unwrap();
+            assert_eq!(response.status(), 200, "successor {} of {}", successor, legacy);
+        }
+    }
+}
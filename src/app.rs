@@ -2,34 +2,64 @@ use std::sync::{Arc, Mutex};
 use std::time::Instant;
 
 use log::{error, info};
-use tokio::sync::{broadcast, oneshot};
+use tokio::sync::{oneshot, watch};
 use tokio::sync::mpsc::{channel, Receiver, Sender};
 
-use crate::{Config, hardware};
+use crate::Config;
 use crate::api;
 use crate::common::db;
+use crate::common::event_bus::EventBus;
+use crate::common::secrets::SecretBox;
+use crate::config::HardwareBackend;
 use crate::enums::system_command::SystemCommand;
+use crate::handlers::backup_handler::backup_handler;
+use crate::handlers::config_handler::config_handler;
+use crate::handlers::state_handler::{state_handler, StateStore};
 use crate::handlers::system_handler;
-use crate::hardware::rfid;
-use crate::models::websocket::WebSocketMessage;
+use crate::hardware::{display, rfid, simulated};
+use crate::hardware::display::{EvdevTouchInput, SysfsBacklight};
+use crate::hardware::rfid::Mfrc522Reader;
+use crate::hardware::simulated::SimulatedBacklight;
+use crate::hardware::traits::Backlight;
+use crate::models::user_actions::UserAction;
 
 #[tokio::main]
-pub async fn launch(conf: &Config) {
+pub async fn launch(conf: &Config, location: &str) {
     // Print welcome message
     info!("Starting App in {}", conf.app.environment);
 
-    let db_connection = db::establish_connection_pool(&conf.database.connection_string);
+    let db_connection = match db::establish_connection_pool(&conf.database.connection_string) {
+        Ok(pool) => pool,
+        Err(e) => {
+            error!("Refusing to start: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let secrets = match SecretBox::load_or_create(&conf.secrets.key_file) {
+        Ok(secrets) => secrets,
+        Err(e) => {
+            error!("Refusing to start: {}", e);
+            std::process::exit(1);
+        }
+    };
     let db_connection_cloned = db_connection.clone();
 
     // Messaging setup for WebSocket and system handlers
-    let (tx, _rx) = broadcast::channel::<WebSocketMessage>(10);
+    let tx = EventBus::new(conf.events.capacity);
+    let rx_state = tx.subscribe();
     let (tx_dbus, rx_dbus): (Sender<SystemCommand>, Receiver<SystemCommand>) = channel::<SystemCommand>(32);
-   
+    let (tx_actions, rx_actions): (Sender<UserAction>, Receiver<UserAction>) = channel::<UserAction>(32);
+
+    // Reloadable settings are read from here, the rest of conf stays as it was at startup
+    let (tx_config, config) = watch::channel(Arc::new(conf.clone()));
+    tokio::spawn(config_handler(location.to_string(), tx_config, tx.clone()));
+
     // Clone tx for multiple uses
     let tx1 = tx.clone();
     let tx2 = tx.clone();
     let tx3 = tx.clone();
-    
+
     // Shared state across threads
     let last_event_time = Arc::new(Mutex::new(Instant::now()));
     let last_event_time_clone = Arc::clone(&last_event_time);
@@ -37,30 +67,76 @@ pub async fn launch(conf: &Config) {
     let (shutdown_tx, shutdown_rx) = oneshot::channel();
 
     // Launch hardware handlers in separate threads
-    std::thread::spawn(|| {
-        if let Err(e) = rfid::control_rfid(tx, shutdown_rx, last_event_time, db_connection_cloned) {
-            error!("Failed in control_rfid: {}", e);
+    let (backlight, simulator): (Arc<dyn Backlight>, _) = match conf.hardware.backend {
+        HardwareBackend::RaspberryPi => {
+            let backlight: Arc<dyn Backlight> = Arc::new(SysfsBacklight::new(&conf.hardware.backlight));
+            let rfid_backlight = backlight.clone();
+            let display_backlight = backlight.clone();
+            let spi_device = conf.hardware.spi_device.clone();
+            let reset_pin = conf.hardware.rfid_reset_pin;
+            let touch_device = conf.hardware.touch_device.clone();
+            let display_config = config.clone();
+
+            std::thread::spawn(move || {
+                let result = Mfrc522Reader::open(&spi_device, reset_pin)
+                    .and_then(|reader| rfid::control_rfid(reader, rfid_backlight, tx, tx_actions, shutdown_rx, last_event_time, db_connection_cloned));
+                if let Err(e) = result {
+                    error!("Failed in control_rfid: {}", e);
+                }
+            });
+
+            std::thread::spawn(move || {
+                let result = EvdevTouchInput::open(&touch_device)
+                    .and_then(|touch_input| display::display_handler_sleep(tx1, touch_input, display_backlight, last_event_time_clone, display_config));
+                if let Err(e) = result {
+                    error!("Failed in systemd handler sleep: {}", e);
+                }
+            });
+
+            (backlight, None)
         }
-    });
-    
-    // Launch system handler
-    std::thread::spawn(|| {
-        if let Err(e) = hardware::display::display_handler_sleep(tx1, last_event_time_clone) {
-            error!("Failed in systemd handler sleep: {}", e);
+        HardwareBackend::Simulated => {
+            info!("Using simulated hardware backend");
+
+            let (simulator, rfid_reader, touch_input) = simulated::create();
+            let backlight: Arc<dyn Backlight> = Arc::new(SimulatedBacklight::default());
+            let rfid_backlight = backlight.clone();
+            let display_backlight = backlight.clone();
+            let display_config = config.clone();
+
+            std::thread::spawn(move || {
+                if let Err(e) = rfid::control_rfid(rfid_reader, rfid_backlight, tx, tx_actions, shutdown_rx, last_event_time, db_connection_cloned) {
+                    error!("Failed in control_rfid: {}", e);
+                }
+            });
+
+            std::thread::spawn(move || {
+                if let Err(e) = display::display_handler_sleep(tx1, touch_input, display_backlight, last_event_time_clone, display_config) {
+                    error!("Failed in systemd handler sleep: {}", e);
+                }
+            });
+
+            (backlight, Some(simulator))
         }
-    });
+    };
+
+    // Cache the latest state for clients connecting later
+    let state_store = Arc::new(StateStore::new(backlight.is_on()));
+    tokio::spawn(state_handler(state_store.clone(), tx3.clone(), rx_state));
+
+    tokio::spawn(backup_handler(db_connection.clone(), conf.backup.clone()));
 
     // Launch system_handler
-    std::thread::spawn(|| {
-        if let Err(e) = system_handler::system_handler(tx2, rx_dbus) {
+    let system_config = config.clone();
+    std::thread::spawn(move || {
+        if let Err(e) = system_handler::system_handler(tx2, rx_dbus, system_config) {
             error!("Failed in bluetooth_handler: {}", e);
         }
     });
 
     // Initialize and run the WebSocket server
-    api::init(&conf.server, tx3, tx_dbus, &db_connection).await;
+    api::init(conf, config, tx3, tx_dbus, rx_actions, &db_connection, backlight, simulator, state_store, secrets).await;
 
     // Send shutdown signal
     let _ = shutdown_tx.send(());
 }
-
@@ -1,10 +1,87 @@
+use diesel::connection::SimpleConnection;
+use diesel::migration::{MigrationSource, MigrationVersion};
 use diesel::prelude::*;
-use diesel::r2d2::{ConnectionManager, Pool};
+use diesel::r2d2::{ConnectionManager, CustomizeConnection, Pool, PoolError};
+use diesel::sqlite::Sqlite;
+use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
+use log::info;
+use thiserror::Error;
 
 pub type DatabasePool = Pool<ConnectionManager<SqliteConnection>>;
 
-pub fn establish_connection_pool(database_url: &str) -> DatabasePool {
+pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!("migrations");
+
+/// How long a connection waits for a lock held by another thread before failing
+const BUSY_TIMEOUT_MS: u32 = 5000;
+
+#[derive(Error, Debug)]
+pub enum DatabaseError {
+    #[error("Failed to create pool: {0}")]
+    Pool(#[from] PoolError),
+    #[error("Failed to run migrations: {0}")]
+    Migration(String),
+    #[error("Database schema {found} is newer than the latest known migration {known}")]
+    SchemaTooNew { found: String, known: String },
+}
+
+/// Creates the pool and brings the schema up to date
+pub fn establish_connection_pool(database_url: &str) -> Result<DatabasePool, DatabaseError> {
     let manager = ConnectionManager::<SqliteConnection>::new(database_url);
+    let pool = Pool::builder()
+        .connection_customizer(Box::new(ConnectionOptions))
+        .build(manager)?;
+
+    let mut conn = pool.get()?;
+    run_migrations(&mut conn)?;
+
+    Ok(pool)
+}
+
+/// Applied to every pooled connection, SQLite keeps these settings per connection
+#[derive(Debug)]
+struct ConnectionOptions;
+
+impl CustomizeConnection<SqliteConnection, diesel::r2d2::Error> for ConnectionOptions {
+    fn on_acquire(&self, conn: &mut SqliteConnection) -> Result<(), diesel::r2d2::Error> {
+        // WAL lets the RFID thread read while an API handler writes
+        conn.batch_execute(&format!(
+            "PRAGMA busy_timeout = {}; PRAGMA journal_mode = WAL; PRAGMA synchronous = NORMAL; PRAGMA foreign_keys = ON;",
+            BUSY_TIMEOUT_MS
+        )).map_err(diesel::r2d2::Error::QueryError)
+    }
+}
+
+pub fn run_migrations(conn: &mut SqliteConnection) -> Result<(), DatabaseError> {
+    let known = latest_known_version()?;
+
+    // A downgraded backend must not touch a schema it doesn't understand
+    if let (Some(found), Some(known)) = (schema_version(conn)?, &known) {
+        if found > *known {
+            return Err(DatabaseError::SchemaTooNew { found, known: known.clone() });
+        }
+    }
+
+    let applied = conn.run_pending_migrations(MIGRATIONS)
+        .map_err(|e| DatabaseError::Migration(e.to_string()))?;
+    for version in &applied {
+        info!("Applied database migration {}", version);
+    }
+
+    info!("Database schema at version {}", schema_version(conn)?.unwrap_or_else(|| "none".to_string()));
+    Ok(())
+}
+
+/// Version of the newest migration applied to the database
+pub fn schema_version(conn: &mut SqliteConnection) -> Result<Option<String>, DatabaseError> {
+    let applied: Vec<MigrationVersion> = conn.applied_migrations()
+        .map_err(|e| DatabaseError::Migration(e.to_string()))?;
+
+    Ok(applied.iter().map(|version| version.to_string()).max())
+}
+
+fn latest_known_version() -> Result<Option<String>, DatabaseError> {
+    let migrations = MigrationSource::<Sqlite>::migrations(&MIGRATIONS)
+        .map_err(|e| DatabaseError::Migration(e.to_string()))?;
 
-    Pool::builder().build(manager).expect("Failed to create pool.")
-}
\ No newline at end of file
+    Ok(migrations.iter().map(|migration| migration.name().version().to_string()).max())
+}
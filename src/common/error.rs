@@ -2,6 +2,7 @@ use std::process::ExitStatus;
 use std::string::{FromUtf16Error, FromUtf8Error};
 use thiserror::Error;
 
+#[allow(clippy::enum_variant_names)]
 #[derive(Error, Debug)]
 pub enum Error {
     #[error("Couldn't find command")]
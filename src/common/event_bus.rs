@@ -0,0 +1,71 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use log::warn;
+use serde_derive::Serialize;
+use tokio::sync::broadcast;
+use utoipa::ToSchema;
+
+use crate::models::websocket::WebSocketMessage;
+
+pub const DEFAULT_CAPACITY: usize = 64;
+
+/// Broadcasts events to every WebSocket client and internal subscriber.
+/// Sending never fails, even while nobody is subscribed.
+#[derive(Clone)]
+pub struct EventBus {
+    tx: broadcast::Sender<WebSocketMessage>,
+    capacity: usize,
+    metrics: Arc<BusMetrics>,
+}
+
+#[derive(Default)]
+struct BusMetrics {
+    published: AtomicU64,
+    lagged: AtomicU64,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct EventBusStats {
+    pub capacity: usize,
+    pub subscribers: usize,
+    /// Events sent on the bus
+    pub published: u64,
+    /// Events skipped by subscribers that fell behind
+    pub lagged: u64,
+}
+
+impl EventBus {
+    pub fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        let (tx, _rx) = broadcast::channel::<WebSocketMessage>(capacity);
+
+        EventBus { tx, capacity, metrics: Arc::new(BusMetrics::default()) }
+    }
+
+    pub fn send(&self, message: WebSocketMessage) {
+        self.metrics.published.fetch_add(1, Ordering::Relaxed);
+
+        // Only fails without subscribers, then there is nobody to miss the event
+        let _ = self.tx.send(message);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<WebSocketMessage> {
+        self.tx.subscribe()
+    }
+
+    /// Called by subscribers when `recv` reported that events were skipped
+    pub fn record_lag(&self, subscriber: &str, skipped: u64) {
+        self.metrics.lagged.fetch_add(skipped, Ordering::Relaxed);
+        warn!("{} fell behind and missed {} events", subscriber, skipped);
+    }
+
+    pub fn stats(&self) -> EventBusStats {
+        EventBusStats {
+            capacity: self.capacity,
+            subscribers: self.tx.receiver_count(),
+            published: self.metrics.published.load(Ordering::Relaxed),
+            lagged: self.metrics.lagged.load(Ordering::Relaxed),
+        }
+    }
+}
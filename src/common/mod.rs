@@ -1,4 +1,6 @@
 pub mod utils;
 pub mod error;
 pub mod unix;
-pub mod db;
\ No newline at end of file
+pub mod db;
+pub mod event_bus;
+pub mod secrets;
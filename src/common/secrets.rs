@@ -0,0 +1,78 @@
+use std::fs;
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
+
+use aes_gcm::{Aes256Gcm, Key, KeyInit, Nonce};
+use aes_gcm::aead::{Aead, AeadCore, OsRng};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use log::info;
+use thiserror::Error;
+
+/// Marks encrypted values, so the format can change later
+const PREFIX: &str = "v1:";
+const NONCE_LENGTH: usize = 12;
+
+#[derive(Error, Debug)]
+pub enum SecretError {
+    #[error("Failed to read device key: {0}")]
+    Key(#[from] std::io::Error),
+    #[error("Device key must be 32 bytes, found {0}")]
+    KeyLength(usize),
+    #[error("Failed to encrypt secret")]
+    Encrypt,
+    #[error("Failed to decrypt secret, it may have been encrypted with another device key")]
+    Decrypt,
+}
+
+/// Encrypts secret constants with a key that never leaves the device
+pub struct SecretBox {
+    cipher: Aes256Gcm,
+}
+
+impl SecretBox {
+    /// Loads the device key, creating it on first start
+    pub fn load_or_create(path: &str) -> Result<Self, SecretError> {
+        let key = if Path::new(path).exists() {
+            fs::read(path)?
+        } else {
+            let key = Aes256Gcm::generate_key(OsRng);
+            fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .mode(0o600)
+                .open(path)?
+                .write_all(&key)?;
+            info!("Created device key {}", path);
+            key.to_vec()
+        };
+
+        if key.len() != 32 {
+            return Err(SecretError::KeyLength(key.len()));
+        }
+
+        Ok(SecretBox { cipher: Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key)) })
+    }
+
+    pub fn encrypt(&self, plaintext: &str) -> Result<String, SecretError> {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = self.cipher.encrypt(&nonce, plaintext.as_bytes()).map_err(|_| SecretError::Encrypt)?;
+
+        let mut data = nonce.to_vec();
+        data.extend_from_slice(&ciphertext);
+        Ok(format!("{}{}", PREFIX, STANDARD.encode(data)))
+    }
+
+    pub fn decrypt(&self, stored: &str) -> Result<String, SecretError> {
+        let data = stored.strip_prefix(PREFIX)
+            .and_then(|encoded| STANDARD.decode(encoded).ok())
+            .filter(|data| data.len() > NONCE_LENGTH)
+            .ok_or(SecretError::Decrypt)?;
+
+        let (nonce, ciphertext) = data.split_at(NONCE_LENGTH);
+        let plaintext = self.cipher.decrypt(Nonce::from_slice(nonce), ciphertext).map_err(|_| SecretError::Decrypt)?;
+
+        String::from_utf8(plaintext).map_err(|_| SecretError::Decrypt)
+    }
+}
@@ -1,99 +1,614 @@
-use std::{env, fs};
+use std::collections::BTreeMap;
+use std::env;
 use std::net::IpAddr;
+use std::str::FromStr;
+use std::sync::Arc;
 
-use log::{debug, error};
-use serde_derive::Deserialize;
+use ::config::{Environment, File, FileFormat};
+use chrono::NaiveDate;
+use log::LevelFilter;
+use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
 use thiserror::Error;
+use tokio::sync::watch;
+use validator::{Validate, ValidationError};
 
-#[derive(Deserialize, Debug)]
+use crate::common::event_bus;
+use crate::models::validation::{flatten_errors, not_blank};
+
+/// Environment variables starting with `SMARTHUB_` override the file, sections are separated
+/// by a double underscore, e.g. `SMARTHUB_SERVER__PORT=8080` or `SMARTHUB_HARDWARE__RFID_RESET_PIN=17`
+pub const ENV_PREFIX: &str = "SMARTHUB";
+
+/// Env variable with the path of the config file
+pub const LOCATION_VAR: &str = "CONFIG_LOCATION";
+pub const DEFAULT_LOCATION: &str = "config.toml";
+
+/// Settings applied on a reload, a setting is also reloadable if it is inside one of these.
+/// Everything else is read once at startup. Has to match `Config::apply_reloadable`, which the tests check.
+pub const RELOADABLE: &[&str] = &[
+    "log.level",
+    "display.timeout",
+    "network.poll_interval",
+    "server.heartbeat",
+    "server.cors_origins",
+    "server.legacy_routes.sunset",
+    "auth.kiosk_mode",
+];
+
+/// The running config. Only reloadable settings ever change, see `handlers::config_handler`.
+pub type SharedConfig = watch::Receiver<Arc<Config>>;
+
+#[derive(Deserialize, Serialize, Debug, Clone, Validate)]
 pub struct Config {
+    #[validate(nested)]
     pub app: AppConf,
+    #[validate(nested)]
     pub server: ServerConf,
+    #[validate(nested)]
     pub database: DatabaseConfig,
+    #[validate(nested)]
     pub log: LogConf,
+    #[serde(default)]
+    #[validate(nested)]
+    pub hardware: HardwareConf,
+    #[serde(default)]
+    #[validate(nested)]
+    pub display: DisplayConf,
+    #[serde(default)]
+    #[validate(nested)]
+    pub network: NetworkConf,
+    #[serde(default)]
+    #[validate(nested)]
+    pub events: EventsConf,
+    #[serde(default)]
+    pub auth: AuthConf,
+    #[serde(default)]
+    #[validate(nested)]
+    pub backup: BackupConf,
+    #[serde(default)]
+    #[validate(nested)]
+    pub secrets: SecretsConf,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Serialize, Debug, Clone, Validate)]
 pub struct ServerConf {
     pub address: IpAddr,
+    #[validate(range(min = 1))]
     pub port: u16,
+    #[serde(default)]
+    #[validate(nested)]
+    pub heartbeat: HeartbeatConf,
+    /// Origins allowed to call the API from a browser, any origin if empty
+    #[serde(default)]
+    pub cors_origins: Vec<String>,
+    #[serde(default)]
+    pub legacy_routes: LegacyRoutesConf,
 }
 
+/// WebSocket ping interval and how long a client may stay silent, in seconds
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Validate)]
+#[validate(schema(function = "validate_heartbeat", skip_on_field_errors = false))]
+pub struct HeartbeatConf {
+    #[serde(default = "default_heartbeat_interval")]
+    #[validate(range(min = 1))]
+    pub interval: u64,
+    #[serde(default = "default_heartbeat_timeout")]
+    pub timeout: u64,
+}
 
-#[derive(Deserialize, Debug)]
+impl Default for HeartbeatConf {
+    fn default() -> Self {
+        HeartbeatConf { interval: default_heartbeat_interval(), timeout: default_heartbeat_timeout() }
+    }
+}
+
+fn default_heartbeat_interval() -> u64 {
+    30
+}
+
+fn default_heartbeat_timeout() -> u64 {
+    75
+}
+
+fn validate_heartbeat(conf: &HeartbeatConf) -> Result<(), ValidationError> {
+    match conf.timeout > conf.interval {
+        true => Ok(()),
+        false => Err(ValidationError::new("timeout").with_message("Timeout must be longer than interval".into())),
+    }
+}
+
+/// The unprefixed routes from before `/api/v1`, served as deprecated aliases
+#[derive(Deserialize, Serialize, Debug, Clone, Copy)]
+pub struct LegacyRoutesConf {
+    #[serde(default = "default_legacy_routes_enabled")]
+    pub enabled: bool,
+    /// Announced in the `Sunset` header as the day they may be removed
+    pub sunset: Option<NaiveDate>,
+}
+
+impl Default for LegacyRoutesConf {
+    fn default() -> Self {
+        LegacyRoutesConf { enabled: default_legacy_routes_enabled(), sunset: None }
+    }
+}
+
+fn default_legacy_routes_enabled() -> bool {
+    true
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Validate)]
 pub struct LogConf {
+    #[validate(custom(function = "not_blank"))]
     pub file: String,
+    /// `off`, `error`, `warn`, `info`, `debug` or `trace`
+    #[serde(default = "default_log_level")]
+    #[validate(custom(function = "log_level"))]
+    pub level: String,
+}
+
+impl LogConf {
+    pub fn level_filter(&self) -> LevelFilter {
+        LevelFilter::from_str(&self.level).unwrap_or(LevelFilter::Debug)
+    }
+}
+
+fn default_log_level() -> String {
+    "debug".to_string()
 }
 
-#[derive(Deserialize, Debug)]
+fn log_level(value: &str) -> Result<(), ValidationError> {
+    match LevelFilter::from_str(value) {
+        Ok(_) => Ok(()),
+        Err(_) => Err(ValidationError::new("level").with_message("Must be off, error, warn, info, debug or trace".into())),
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Validate)]
 pub struct AppConf {
+    #[validate(custom(function = "not_blank"))]
     pub environment: String,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Serialize, Debug, Clone, Validate)]
 pub struct DatabaseConfig {
+    #[validate(custom(function = "not_blank"))]
     pub connection_string: String,
 }
 
+/// Devices of the Raspberry Pi backend, unused by the simulated one
+#[derive(Deserialize, Serialize, Debug, Clone, Validate)]
+pub struct HardwareConf {
+    #[serde(default)]
+    pub backend: HardwareBackend,
+    /// sysfs `bl_power` file of the display
+    #[serde(default = "default_backlight")]
+    #[validate(custom(function = "absolute_path"))]
+    pub backlight: String,
+    /// evdev device of the touch panel
+    #[serde(default = "default_touch_device")]
+    #[validate(custom(function = "absolute_path"))]
+    pub touch_device: String,
+    /// SPI bus of the MFRC522 RFID reader
+    #[serde(default = "default_spi_device")]
+    #[validate(custom(function = "absolute_path"))]
+    pub spi_device: String,
+    /// GPIO (BCM numbering) wired to the reset pin of the RFID reader
+    #[serde(default = "default_rfid_reset_pin")]
+    #[validate(range(max = 27))]
+    pub rfid_reset_pin: u64,
+}
+
+impl Default for HardwareConf {
+    fn default() -> Self {
+        HardwareConf {
+            backend: HardwareBackend::default(),
+            backlight: default_backlight(),
+            touch_device: default_touch_device(),
+            spi_device: default_spi_device(),
+            rfid_reset_pin: default_rfid_reset_pin(),
+        }
+    }
+}
+
+fn default_backlight() -> String {
+    "/sys/class/backlight/10-0045/bl_power".to_string()
+}
+
+fn default_touch_device() -> String {
+    "/dev/input/by-path/platform-fe205000.i2c-event".to_string()
+}
+
+fn default_spi_device() -> String {
+    "/dev/spidev0.0".to_string()
+}
+
+fn default_rfid_reset_pin() -> u64 {
+    22
+}
+
+fn absolute_path(value: &str) -> Result<(), ValidationError> {
+    match value.starts_with('/') {
+        true => Ok(()),
+        false => Err(ValidationError::new("path").with_message("Must be an absolute path".into())),
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Validate)]
+pub struct DisplayConf {
+    /// Seconds without touch or RFID scan until the backlight is switched off
+    #[serde(default = "default_display_timeout")]
+    #[validate(range(min = 10))]
+    pub timeout: u64,
+}
+
+impl Default for DisplayConf {
+    fn default() -> Self {
+        DisplayConf { timeout: default_display_timeout() }
+    }
+}
+
+fn default_display_timeout() -> u64 {
+    300
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Validate)]
+pub struct NetworkConf {
+    /// Wi-Fi interface controlled through wpa_cli
+    #[serde(default = "default_interface")]
+    #[validate(length(max = 15), custom(function = "interface_name"))]
+    pub interface: String,
+    /// Written when connecting to a network
+    #[serde(default = "default_wpa_supplicant_conf")]
+    #[validate(custom(function = "absolute_path"))]
+    pub wpa_supplicant_conf: String,
+    /// Seconds between network status, interface and scan result updates
+    #[serde(default = "default_poll_interval")]
+    #[validate(range(min = 1))]
+    pub poll_interval: u64,
+}
+
+impl Default for NetworkConf {
+    fn default() -> Self {
+        NetworkConf {
+            interface: default_interface(),
+            wpa_supplicant_conf: default_wpa_supplicant_conf(),
+            poll_interval: default_poll_interval(),
+        }
+    }
+}
+
+fn default_interface() -> String {
+    "wlan0".to_string()
+}
+
+fn default_wpa_supplicant_conf() -> String {
+    "/etc/wpa_supplicant/wpa_supplicant.conf".to_string()
+}
+
+fn default_poll_interval() -> u64 {
+    5
+}
+
+/// Passed to wpa_cli as an argument, so only what Linux allows in interface names
+fn interface_name(value: &str) -> Result<(), ValidationError> {
+    match !value.is_empty() && value.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.')) {
+        true => Ok(()),
+        false => Err(ValidationError::new("interface").with_message("Must be an interface name like wlan0".into())),
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct AuthConf {
+    /// Allow requests from localhost without a token, for the kiosk frontend running on the device
+    #[serde(default = "default_kiosk_mode")]
+    pub kiosk_mode: bool,
+}
+
+impl Default for AuthConf {
+    fn default() -> Self {
+        AuthConf { kiosk_mode: default_kiosk_mode() }
+    }
+}
+
+fn default_kiosk_mode() -> bool {
+    true
+}
+
+/// Scheduled database snapshots
+#[derive(Deserialize, Serialize, Debug, Clone, Validate)]
+pub struct BackupConf {
+    /// Where snapshots are written, e.g. a mounted USB drive
+    #[serde(default = "default_backup_directory")]
+    #[validate(custom(function = "not_blank"))]
+    pub directory: String,
+    /// Hours between snapshots, 0 disables them
+    #[serde(default = "default_backup_interval")]
+    pub interval: u64,
+    /// Number of snapshots kept, older ones are deleted
+    #[serde(default = "default_backup_retention")]
+    #[validate(range(min = 1))]
+    pub retention: usize,
+}
+
+impl Default for BackupConf {
+    fn default() -> Self {
+        BackupConf {
+            directory: default_backup_directory(),
+            interval: default_backup_interval(),
+            retention: default_backup_retention(),
+        }
+    }
+}
+
+fn default_backup_directory() -> String {
+    "backups".to_string()
+}
+
+fn default_backup_interval() -> u64 {
+    24
+}
+
+fn default_backup_retention() -> usize {
+    7
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Validate)]
+pub struct SecretsConf {
+    /// Key encrypting secret constants, created on first start
+    #[serde(default = "default_key_file")]
+    #[validate(custom(function = "not_blank"))]
+    pub key_file: String,
+}
+
+impl Default for SecretsConf {
+    fn default() -> Self {
+        SecretsConf { key_file: default_key_file() }
+    }
+}
+
+fn default_key_file() -> String {
+    "device.key".to_string()
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Validate)]
+pub struct EventsConf {
+    #[serde(default = "default_event_capacity")]
+    #[validate(range(min = 1))]
+    pub capacity: usize,
+}
+
+impl Default for EventsConf {
+    fn default() -> Self {
+        EventsConf { capacity: default_event_capacity() }
+    }
+}
+
+fn default_event_capacity() -> usize {
+    event_bus::DEFAULT_CAPACITY
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum HardwareBackend {
+    #[default]
+    RaspberryPi,
+    Simulated,
+}
+
 #[derive(Error, Debug)]
 pub enum ConfigError {
-    #[error("Config File could not be found")]
-    ConfigNotFound(std::io::Error),
-
-    #[error("Config File could not be found")]
-    ParsingError(toml::de::Error),
+    #[error("{0}")]
+    Load(#[from] ::config::ConfigError),
 
-    #[error("Env variable could not be found")]
-    EnvVarNotFound(std::env::VarError),
+    #[error("Invalid config:\n{}", .0.iter().map(|problem| format!("  {}", problem)).collect::<Vec<_>>().join("\n"))]
+    Invalid(Vec<String>),
 }
 
 impl Config {
-    pub fn from_any() -> Result<Self, ConfigError> {
-        //Try to read from path env var
-        let env_result = Self::from_env_path();
-        match env_result {
-            Ok(config) => {
-                debug!("Loaded config from env path");
-                return Ok(config);
-            }
-            Err(error) => {
-                debug!("Could not load config from env path: {}", error);
-            }
-        }
+    /// Path in `CONFIG_LOCATION`, or `config.toml` in the working directory
+    pub fn location() -> String {
+        env::var(LOCATION_VAR).unwrap_or_else(|_| DEFAULT_LOCATION.to_string())
+    }
 
-        //Try to read default path
-        let default_result = Self::from_default_path();
-        match default_result {
-            Ok(config) => {
-                debug!("Loaded config from default path");
-                return Ok(config);
-            }
-            Err(error) => {
-                error!("Could not load config: {}", error);
-                return Err(error);
+    /// Reads the file, applies `SMARTHUB_*` overrides and validates the result
+    pub fn from_file_path(path: &str) -> Result<Self, ConfigError> {
+        let config: Config = ::config::Config::builder()
+            .add_source(File::new(path, FileFormat::Toml))
+            .add_source(Environment::with_prefix(ENV_PREFIX)
+                .prefix_separator("_")
+                .separator("__")
+                .try_parsing(true)
+                .list_separator(",")
+                .with_list_parse_key("server.cors_origins"))
+            .build()?
+            .try_deserialize()?;
+
+        config.validate().map_err(|errors| {
+            ConfigError::Invalid(flatten_errors(&errors).into_iter()
+                .map(|(field, message)| format!("{}: {}", field, message))
+                .collect())
+        })?;
+
+        Ok(config)
+    }
+
+    /// Settings that differ from `other`, as paths like `display.timeout`
+    pub fn changes(&self, other: &Config) -> Vec<String> {
+        let (current, other) = (self.settings(), other.settings());
+
+        let mut changes: Vec<String> = current.iter()
+            .filter(|(path, value)| other.get(*path) != Some(*value))
+            .map(|(path, _)| path.clone())
+            .collect();
+        changes.extend(other.keys().filter(|path| !current.contains_key(*path)).cloned());
+        changes.sort();
+
+        changes
+    }
+
+    /// Settings that differ from `new`, split into the ones `apply_reloadable` takes over and the
+    /// ones that need a restart
+    pub fn split_changes(&self, new: &Config) -> (Vec<String>, Vec<String>) {
+        self.changes(new).into_iter().partition(|setting| is_reloadable(setting))
+    }
+
+    /// Takes over the reloadable settings of `new`
+    pub fn apply_reloadable(&mut self, new: &Config) {
+        self.log.level = new.log.level.clone();
+        self.display.timeout = new.display.timeout;
+        self.network.poll_interval = new.network.poll_interval;
+        self.server.heartbeat = new.server.heartbeat;
+        self.server.cors_origins = new.server.cors_origins.clone();
+        self.server.legacy_routes.sunset = new.server.legacy_routes.sunset;
+        self.auth.kiosk_mode = new.auth.kiosk_mode;
+    }
+
+    fn settings(&self) -> BTreeMap<String, Value> {
+        let mut settings = BTreeMap::new();
+        flatten("", serde_json::to_value(self).unwrap_or_default(), &mut settings);
+        settings
+    }
+}
+
+pub fn is_reloadable(setting: &str) -> bool {
+    RELOADABLE.iter().any(|reloadable| {
+        setting == *reloadable || setting.strip_prefix(reloadable).is_some_and(|rest| rest.starts_with('.'))
+    })
+}
+
+/// Lists are compared as a whole
+fn flatten(prefix: &str, value: Value, settings: &mut BTreeMap<String, Value>) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map {
+                let path = match prefix {
+                    "" => key,
+                    prefix => format!("{}.{}", prefix, key),
+                };
+                flatten(&path, value, settings);
             }
         }
+        value => {
+            settings.insert(prefix.to_string(), value);
+        }
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE: &str = r#"
+        [app]
+        environment = "dev"
+        [log]
+        file = "latest.api.log"
+        [server]
+        address = "0.0.0.0"
+        port = 6814
+        [database]
+        connection_string = "Database.db"
+    "#;
 
-    // Read Config from default path
-    pub fn from_default_path() -> Result<Self, ConfigError> {
-        let path = "config.toml";
-        Self::from_file_path(&path)
+    /// Every setting differs from `BASE` or the defaults
+    const CHANGED: &str = r#"
+        [app]
+        environment = "prod"
+        [log]
+        file = "other.log"
+        level = "warn"
+        [server]
+        address = "127.0.0.1"
+        port = 7000
+        cors_origins = ["http://hub.local"]
+        [server.heartbeat]
+        interval = 10
+        timeout = 20
+        [server.legacy_routes]
+        enabled = false
+        sunset = "2027-06-30"
+        [database]
+        connection_string = "Other.db"
+        [hardware]
+        backend = "simulated"
+        backlight = "/sys/other"
+        touch_device = "/dev/other"
+        spi_device = "/dev/spidev1.0"
+        rfid_reset_pin = 17
+        [display]
+        timeout = 60
+        [network]
+        interface = "wlan1"
+        wpa_supplicant_conf = "/etc/other.conf"
+        poll_interval = 30
+        [events]
+        capacity = 8
+        [auth]
+        kiosk_mode = false
+        [backup]
+        directory = "/mnt/usb"
+        interval = 1
+        retention = 2
+        [secrets]
+        key_file = "other.key"
+    "#;
+
+    fn parse(toml: &str) -> Config {
+        let config: Config = ::config::Config::builder()
+            .add_source(File::from_str(toml, FileFormat::Toml))
+            .build()
+            .and_then(|config| config.try_deserialize())
+            .unwrap();
+        config.validate().unwrap();
+        config
     }
 
-    // Read Config from path in CONFIG_LOCATION env variable
-    pub fn from_env_path() -> Result<Self, ConfigError> {
-        let path = env::var("CONFIG_LOCATION")
-            .map_err(|e| ConfigError::EnvVarNotFound(e))?;
-        Self::from_file_path(&path)
+    #[test]
+    fn same_config_has_no_changes() {
+        assert!(parse(BASE).changes(&parse(BASE)).is_empty());
     }
 
-    // Read and Parse Config from path
-    pub fn from_file_path(path: &str) -> Result<Self, ConfigError> {
-        let data = fs::read_to_string(path)
-            .map_err(|e| ConfigError::ConfigNotFound(e))?;
+    #[test]
+    fn lists_changed_settings_by_path() {
+        let new = parse(&BASE.replace("port = 6814", "port = 7000\ncors_origins = [\"http://hub.local\"]"));
+
+        assert_eq!(parse(BASE).changes(&new), vec!["server.cors_origins", "server.port"]);
+    }
+
+    #[test]
+    fn splits_live_and_restart_settings() {
+        let new = parse(&format!("{}\n[display]\ntimeout = 60\n[hardware]\nbackend = \"simulated\"", BASE.replace("latest.api.log\"", "latest.api.log\"\nlevel = \"info\"")));
+        let (applied, restart_required) = parse(BASE).split_changes(&new);
+
+        assert_eq!(applied, vec!["display.timeout", "log.level"]);
+        assert_eq!(restart_required, vec!["hardware.backend"]);
+    }
+
+    #[test]
+    fn reloadable_settings_match_apply_reloadable() {
+        let (running, new) = (parse(BASE), parse(CHANGED));
+        let (applied, restart_required) = running.split_changes(&new);
+
+        // Every entry of RELOADABLE is covered by CHANGED
+        for reloadable in RELOADABLE {
+            assert!(applied.iter().any(|setting| is_reloadable(setting) && setting.starts_with(reloadable)), "{} is not changed", reloadable);
+        }
+
+        let mut updated = running.clone();
+        updated.apply_reloadable(&new);
+
+        // apply_reloadable takes over exactly the settings listed as reloadable
+        assert_eq!(updated.changes(&running), applied);
+        assert_eq!(updated.changes(&new), restart_required);
+    }
 
-        toml::from_str(data.as_str())
-            .map_err(|e| ConfigError::ParsingError(e))
+    #[test]
+    fn nested_settings_are_reloadable_with_their_parent() {
+        assert!(is_reloadable("server.heartbeat.interval"));
+        assert!(is_reloadable("log.level"));
+        assert!(!is_reloadable("server.heartbeat_interval"));
+        assert!(!is_reloadable("server.legacy_routes.enabled"));
+        assert!(!is_reloadable("log"));
     }
 }
@@ -1,12 +1,17 @@
+use tokio::sync::oneshot;
+
+/// Receives the outcome of a command once the system handler has executed it
+pub(crate) type Responder = Option<oneshot::Sender<Result<(), String>>>;
+
 pub(crate) enum SystemCommand {
-    BluetoothDiscovering(String),
-    GetAllBluetoothDevices,
-    ConnectBluetoothDevice(String),
-    DisconnectBluetoothDevice(String),
-    PairBluetoothDevice(String),
-    UnpairBluetoothDevice(String),
-    TrustBluetoothDevice(String),
-    UntrustBluetoothDevice(String),
+    BluetoothDiscovering(String, Responder),
+    GetAllBluetoothDevices(Responder),
+    ConnectBluetoothDevice(String, Responder),
+    DisconnectBluetoothDevice(String, Responder),
+    PairBluetoothDevice(String, Responder),
+    UnpairBluetoothDevice(String, Responder),
+    TrustBluetoothDevice(String, Responder),
+    UntrustBluetoothDevice(String, Responder),
     UpdateSystem,
     ListingSystemUpdates,
-}
\ No newline at end of file
+}
@@ -0,0 +1,298 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use futures::future::BoxFuture;
+use log::{debug, error};
+use serde_derive::Deserialize;
+use serde_json::{json, Value};
+use tokio::sync::mpsc::Receiver;
+use tokio::sync::oneshot;
+
+use crate::api::AppState;
+use crate::api::permissions::Permission;
+use crate::enums::system_command::SystemCommand;
+use crate::handlers::request_handler::run_user_request;
+use crate::handlers::session_handler::LoginMethod;
+use crate::models::action_runs::{ActionRun, NewActionRun, Trigger};
+use crate::models::events::{ActionFailed, ActionStarted, ActionSucceeded, DisplayStatus};
+use crate::models::user::User;
+use crate::models::user_actions::UserAction;
+use crate::models::websocket::{OpCode, WebSocketMessage};
+
+/// An action type that can be stored in `user_actions.type_name` and executed by the backend.
+/// `details` is the parsed JSON of the `details` column, `trigger` is passed on to any
+/// requests the action runs.
+pub trait ActionExecutor: Send + Sync {
+    fn execute<'a>(&'a self, engine: &'a ActionEngine, trigger: &'a Trigger, details: Value) -> BoxFuture<'a, Result<Value, String>>;
+}
+
+pub struct ActionEngine {
+    state: Arc<AppState>,
+    executors: HashMap<&'static str, Box<dyn ActionExecutor>>,
+}
+
+#[derive(Deserialize)]
+struct HttpRequestDetails {
+    request_id: i32,
+}
+
+#[derive(Deserialize)]
+struct SwitchUserDetails {
+    user_id: i32,
+}
+
+#[derive(Deserialize)]
+struct DisplayToggleDetails {
+    status: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct BluetoothConnectDetails {
+    address: String,
+}
+
+#[derive(Deserialize)]
+struct SceneStep {
+    type_name: String,
+    #[serde(default)]
+    details: Value,
+}
+
+#[derive(Deserialize)]
+struct RunSceneDetails {
+    actions: Vec<SceneStep>,
+}
+
+struct HttpRequestAction;
+struct SwitchUserAction;
+struct DisplayToggleAction;
+struct BluetoothConnectAction;
+struct RunSceneAction;
+
+pub async fn action_handler(mut rx: Receiver<UserAction>, state: Arc<AppState>) {
+    let engine = Arc::new(ActionEngine::new(state));
+
+    while let Some(action) = rx.recv().await {
+        let engine = engine.clone();
+        tokio::spawn(async move {
+            let trigger = Trigger::Rfid(action.rfid_uid.clone());
+            engine.run(&action, trigger).await;
+        });
+    }
+}
+
+impl ActionEngine {
+    pub fn new(state: Arc<AppState>) -> Self {
+        let mut engine = ActionEngine { state, executors: HashMap::new() };
+
+        engine.register("http_request", Box::new(HttpRequestAction));
+        engine.register("switch_user", Box::new(SwitchUserAction));
+        engine.register("display_toggle", Box::new(DisplayToggleAction));
+        engine.register("bluetooth_connect", Box::new(BluetoothConnectAction));
+        engine.register("run_scene", Box::new(RunSceneAction));
+
+        engine
+    }
+
+    pub fn register(&mut self, type_name: &'static str, executor: Box<dyn ActionExecutor>) {
+        self.executors.insert(type_name, executor);
+    }
+
+    /// Runs a stored action and reports its progress over the WebSocket.
+    /// Action types unknown to the backend are left to the frontend.
+    pub async fn run(&self, action: &UserAction, trigger: Trigger) {
+        if !self.executors.contains_key(action.type_name.as_str()) {
+            debug!("No executor registered for action type {}", action.type_name);
+            return;
+        }
+
+        let details = parse_details(&action.details);
+        let started = chrono::Utc::now().naive_utc();
+
+        self.notify("ACTION_STARTED", json!(ActionStarted {
+            action_id: action.id,
+            type_name: action.type_name.clone(),
+        }));
+
+        let result = self.execute(&action.type_name, &Trigger::Action(action.id), details).await;
+
+        match &result {
+            Ok(result) => self.notify("ACTION_SUCCEEDED", json!(ActionSucceeded {
+                action_id: action.id,
+                type_name: action.type_name.clone(),
+                result: result.clone(),
+            })),
+            Err(e) => {
+                error!("Action {} ({}) failed: {}", action.id, action.type_name, e);
+                self.notify("ACTION_FAILED", json!(ActionFailed {
+                    action_id: action.id,
+                    type_name: action.type_name.clone(),
+                    error: e.clone(),
+                }));
+            }
+        }
+
+        let mut run = NewActionRun::new(action.user_id, &trigger, started, result.map(|r| Some(r.to_string())));
+        run.action_id = Some(action.id);
+        record_run(&self.state, run);
+    }
+
+    pub fn execute<'a>(&'a self, type_name: &str, trigger: &'a Trigger, details: Value) -> BoxFuture<'a, Result<Value, String>> {
+        match self.executors.get(type_name) {
+            Some(executor) => executor.execute(self, trigger, details),
+            None => {
+                let error = format!("Unknown action type: {}", type_name);
+                Box::pin(async move { Err(error) })
+            }
+        }
+    }
+
+    /// The user whose action is running
+    pub fn action_owner(&self, trigger: &Trigger) -> Option<i32> {
+        let Trigger::Action(action_id) = trigger else {
+            return None;
+        };
+
+        let mut conn = self.state.db_pool.get().ok()?;
+        UserAction::get_by_id(*action_id, &mut conn).ok().map(|action| action.user_id)
+    }
+
+    pub fn state(&self) -> &Arc<AppState> {
+        &self.state
+    }
+
+    pub fn notify(&self, event: &str, data: Value) {
+        let notification = WebSocketMessage {
+            t: Some(event.to_string()),
+            op: OpCode::Actions,
+            d: Some(data),
+        };
+
+        self.state.tx.send(notification);
+    }
+}
+
+impl ActionExecutor for HttpRequestAction {
+    fn execute<'a>(&'a self, engine: &'a ActionEngine, trigger: &'a Trigger, details: Value) -> BoxFuture<'a, Result<Value, String>> {
+        Box::pin(async move {
+            let details = serde_json::from_value::<HttpRequestDetails>(details).map_err(|e| e.to_string())?;
+
+            // Runs on behalf of whoever owns the action, which may not be the owner of the request
+            let runner = engine.action_owner(trigger);
+            let result = run_user_request(engine.state(), details.request_id, trigger.clone(), runner).await
+                .map_err(|e| format!("Request {}: {}", details.request_id, e))?;
+
+            if !(200..300).contains(&result.status) {
+                return Err(format!("Request {} returned status {}", details.request_id, result.status));
+            }
+
+            Ok(json!({ "status": result.status }))
+        })
+    }
+}
+
+/// Anyone may switch to a child or guest, admins and members only with their own actions,
+/// like they would with their own RFID tag
+impl ActionExecutor for SwitchUserAction {
+    fn execute<'a>(&'a self, engine: &'a ActionEngine, trigger: &'a Trigger, details: Value) -> BoxFuture<'a, Result<Value, String>> {
+        Box::pin(async move {
+            let details = serde_json::from_value::<SwitchUserDetails>(details).map_err(|e| e.to_string())?;
+
+            let user = {
+                let mut conn = engine.state().db_pool.get().map_err(|e| e.to_string())?;
+                let user = User::get_by_id(details.user_id, &mut conn)
+                    .map_err(|_| format!("User {} not found", details.user_id))?;
+
+                user
+            };
+
+            if Permission::needs_verified_login(user.role) && engine.action_owner(trigger) != Some(user.id) {
+                return Err(format!("Only actions of {} can switch to them", user.username));
+            }
+
+            engine.notify("SWITCH_USER", json!(user));
+            let session = engine.state().session.login(&engine.state().tx, user, LoginMethod::Action);
+
+            Ok(json!({ "user_id": session.user.id }))
+        })
+    }
+}
+
+impl ActionExecutor for DisplayToggleAction {
+    fn execute<'a>(&'a self, engine: &'a ActionEngine, _trigger: &'a Trigger, details: Value) -> BoxFuture<'a, Result<Value, String>> {
+        Box::pin(async move {
+            let details = serde_json::from_value::<DisplayToggleDetails>(details).unwrap_or(DisplayToggleDetails { status: None });
+            let backlight = &engine.state().backlight;
+
+            let on = match details.status.as_deref() {
+                Some("on") => true,
+                Some("off") => false,
+                Some(status) => return Err(format!("Invalid display status: {}", status)),
+                None => !backlight.is_on(),
+            };
+
+            backlight.set_power(on);
+
+            let status = DisplayStatus::new(on);
+            engine.state().tx.send(WebSocketMessage {
+                t: Some("DISPLAY_STATUS".to_string()),
+                op: OpCode::System,
+                d: Some(json!(status)),
+            });
+
+            Ok(json!(status))
+        })
+    }
+}
+
+impl ActionExecutor for BluetoothConnectAction {
+    fn execute<'a>(&'a self, engine: &'a ActionEngine, _trigger: &'a Trigger, details: Value) -> BoxFuture<'a, Result<Value, String>> {
+        Box::pin(async move {
+            let details = serde_json::from_value::<BluetoothConnectDetails>(details).map_err(|e| e.to_string())?;
+
+            let (responder, reply) = oneshot::channel();
+            engine.state().tx_dbus.send(SystemCommand::ConnectBluetoothDevice(details.address.clone(), Some(responder))).await
+                .map_err(|e| e.to_string())?;
+
+            reply.await.map_err(|_| "System handler is not running".to_string())??;
+
+            Ok(json!({ "address": details.address }))
+        })
+    }
+}
+
+impl ActionExecutor for RunSceneAction {
+    fn execute<'a>(&'a self, engine: &'a ActionEngine, trigger: &'a Trigger, details: Value) -> BoxFuture<'a, Result<Value, String>> {
+        Box::pin(async move {
+            let details = serde_json::from_value::<RunSceneDetails>(details).map_err(|e| e.to_string())?;
+
+            let mut results = Vec::new();
+            for (index, step) in details.actions.into_iter().enumerate() {
+                if step.type_name == "run_scene" {
+                    return Err("Scenes cannot contain other scenes".to_string());
+                }
+
+                let result = engine.execute(&step.type_name, trigger, step.details).await
+                    .map_err(|e| format!("Step {} ({}) failed: {}", index + 1, step.type_name, e))?;
+                results.push(result);
+            }
+
+            Ok(json!(results))
+        })
+    }
+}
+
+/// Stores a finished run in the history. Failing to do so must not fail the run itself.
+pub fn record_run(state: &AppState, run: NewActionRun) {
+    let result = state.db_pool.get()
+        .map_err(|e| e.to_string())
+        .and_then(|mut conn| ActionRun::create(run, &mut conn).map_err(|e| e.to_string()));
+
+    if let Err(e) = result {
+        error!("Failed to record action run: {}", e);
+    }
+}
+
+fn parse_details(details: &str) -> Value {
+    serde_json::from_str(details).unwrap_or(Value::Null)
+}
@@ -0,0 +1,203 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use diesel::prelude::*;
+use diesel::r2d2::PoolError;
+use diesel::sql_query;
+use diesel::sql_types::Text;
+use log::{error, info, warn};
+use rand::Rng;
+use thiserror::Error;
+use tokio::time::{Duration, interval};
+
+use crate::common::db::{self, DatabaseError, DatabasePool};
+use crate::config::BackupConf;
+
+const SNAPSHOT_PREFIX: &str = "smarthub-";
+const SNAPSHOT_EXTENSION: &str = "db";
+
+#[derive(Error, Debug)]
+pub enum BackupError {
+    #[error("Invalid backup: {0}")]
+    Invalid(String),
+    #[error("File error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Database error: {0}")]
+    Database(#[from] diesel::result::Error),
+    #[error("Database error: {0}")]
+    Connection(#[from] ConnectionError),
+    #[error("Database error: {0}")]
+    Pool(#[from] PoolError),
+}
+
+#[derive(QueryableByName)]
+struct IntegrityCheck {
+    #[diesel(sql_type = Text)]
+    integrity_check: String,
+}
+
+#[derive(QueryableByName)]
+struct TableName {
+    #[diesel(sql_type = Text)]
+    name: String,
+}
+
+/// Writes snapshots at the configured interval and keeps the newest `retention` of them
+pub async fn backup_handler(pool: DatabasePool, conf: BackupConf) {
+    if conf.interval == 0 {
+        info!("Scheduled database snapshots are disabled");
+        return;
+    }
+
+    let mut ticker = interval(Duration::from_secs(conf.interval * 3600));
+    // The first tick completes immediately, a restart shouldn't create a snapshot every time
+    ticker.tick().await;
+
+    loop {
+        ticker.tick().await;
+
+        let pool = pool.clone();
+        let conf = conf.clone();
+        let result = tokio::task::spawn_blocking(move || create_snapshot(&pool, &conf, "scheduled")).await;
+
+        match result {
+            Ok(Ok(path)) => info!("Wrote database snapshot {}", path.display()),
+            Ok(Err(e)) => error!("Failed to write database snapshot: {}", e),
+            Err(e) => error!("Database snapshot task failed: {}", e),
+        }
+    }
+}
+
+/// Consistent copy of the live database, taken without blocking other connections
+pub fn backup_to(pool: &DatabasePool, path: &Path) -> Result<(), BackupError> {
+    let mut conn = pool.get()?;
+
+    sql_query("VACUUM INTO ?")
+        .bind::<Text, _>(path.to_string_lossy())
+        .execute(&mut conn)?;
+
+    Ok(())
+}
+
+/// Writes a snapshot to the backup directory and removes the ones past the retention count
+pub fn create_snapshot(pool: &DatabasePool, conf: &BackupConf, label: &str) -> Result<PathBuf, BackupError> {
+    let directory = Path::new(&conf.directory);
+    fs::create_dir_all(directory)?;
+
+    let name = format!("{}{}-{}.{}", SNAPSHOT_PREFIX, chrono::Local::now().format("%Y%m%d-%H%M%S%.3f"), label, SNAPSHOT_EXTENSION);
+    let path = directory.join(name);
+    backup_to(pool, &path)?;
+
+    if let Err(e) = prune_snapshots(directory, conf.retention) {
+        warn!("Failed to remove old snapshots: {}", e);
+    }
+
+    Ok(path)
+}
+
+fn prune_snapshots(directory: &Path, retention: usize) -> Result<(), std::io::Error> {
+    let mut snapshots = fs::read_dir(directory)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.extension().is_some_and(|extension| extension == SNAPSHOT_EXTENSION)
+                && path.file_name().is_some_and(|name| name.to_string_lossy().starts_with(SNAPSHOT_PREFIX))
+        })
+        .collect::<Vec<_>>();
+
+    // Names start with the timestamp, so they sort oldest first
+    snapshots.sort();
+
+    let excess = snapshots.len().saturating_sub(retention);
+    for path in snapshots.into_iter().take(excess) {
+        fs::remove_file(&path)?;
+        info!("Removed old database snapshot {}", path.display());
+    }
+
+    Ok(())
+}
+
+/// Replaces all data with the uploaded database. The upload is checked and migrated to the
+/// current schema first, and a snapshot of the current data is kept in case it was the wrong file.
+pub fn restore(pool: &DatabasePool, conf: &BackupConf, data: &[u8]) -> Result<(), BackupError> {
+    let path = temp_path("restore");
+    fs::write(&path, data)?;
+
+    let result = prepare_upload(&path).and_then(|_| {
+        let snapshot = create_snapshot(pool, conf, "pre-restore")?;
+        info!("Saved current database to {} before restoring", snapshot.display());
+
+        replace_data(pool, &path)
+    });
+
+    if let Err(e) = fs::remove_file(&path) {
+        warn!("Failed to remove uploaded backup {}: {}", path.display(), e);
+    }
+
+    result
+}
+
+fn prepare_upload(path: &Path) -> Result<(), BackupError> {
+    let mut upload = SqliteConnection::establish(&path.to_string_lossy())?;
+
+    let check = sql_query("PRAGMA integrity_check")
+        .load::<IntegrityCheck>(&mut upload)
+        .map_err(|e| BackupError::Invalid(e.to_string()))?;
+    if check.iter().any(|row| row.integrity_check != "ok") {
+        return Err(BackupError::Invalid("Integrity check failed".to_string()));
+    }
+
+    // Migrating an unrelated database would just create empty tables
+    let tables = table_names(&mut upload)?;
+    if !tables.iter().any(|table| table == "__diesel_schema_migrations") || !tables.iter().any(|table| table == "user_users") {
+        return Err(BackupError::Invalid("Not a smarthub database".to_string()));
+    }
+
+    db::run_migrations(&mut upload).map_err(|e| match e {
+        DatabaseError::SchemaTooNew { .. } => BackupError::Invalid(e.to_string()),
+        e => BackupError::Invalid(format!("Failed to migrate backup: {}", e)),
+    })
+}
+
+fn replace_data(pool: &DatabasePool, path: &Path) -> Result<(), BackupError> {
+    let mut conn = pool.get()?;
+
+    sql_query("ATTACH DATABASE ? AS restore")
+        .bind::<Text, _>(path.to_string_lossy())
+        .execute(&mut conn)?;
+
+    let result = conn.transaction::<_, diesel::result::Error, _>(|conn| {
+        // Rows are replaced table by table, references only have to match at the end
+        sql_query("PRAGMA defer_foreign_keys = ON").execute(conn)?;
+
+        let tables = table_names(conn)?
+            .into_iter()
+            .filter(|table| table != "__diesel_schema_migrations")
+            .collect::<Vec<_>>();
+
+        for table in &tables {
+            sql_query(format!("DELETE FROM main.\"{}\"", table)).execute(conn)?;
+        }
+        for table in &tables {
+            sql_query(format!("INSERT INTO main.\"{0}\" SELECT * FROM restore.\"{0}\"", table)).execute(conn)?;
+        }
+
+        Ok(())
+    });
+
+    if let Err(e) = sql_query("DETACH DATABASE restore").execute(&mut conn) {
+        warn!("Failed to detach restored database: {}", e);
+    }
+
+    Ok(result?)
+}
+
+fn table_names(conn: &mut SqliteConnection) -> Result<Vec<String>, diesel::result::Error> {
+    let tables = sql_query("SELECT name FROM main.sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%'")
+        .load::<TableName>(conn)?;
+
+    Ok(tables.into_iter().map(|table| table.name).collect())
+}
+
+pub fn temp_path(label: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("{}{}-{:08x}.{}", SNAPSHOT_PREFIX, label, rand::thread_rng().gen::<u32>(), SNAPSHOT_EXTENSION))
+}
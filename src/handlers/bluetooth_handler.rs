@@ -3,19 +3,20 @@ use std::error::Error;
 use std::sync::Arc;
 use std::time::Duration;
 
-use dbus::{Message, nonblock, Path};
+use dbus::{Message, nonblock};
 use dbus::arg::{RefArg, Variant};
 use dbus::nonblock::stdintf::org_freedesktop_dbus::{ObjectManager, Properties};
 use dbus::nonblock::SyncConnection;
 use log::{debug, error};
 use serde_derive::{Deserialize, Serialize};
 use serde_json::json;
-use tokio::sync::broadcast::Sender;
 use tokio::task;
+use utoipa::ToSchema;
 
-use crate::models::websocket::WebSocketMessage;
+use crate::common::event_bus::EventBus;
+use crate::models::websocket::{OpCode, WebSocketMessage};
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, ToSchema)]
 pub struct BluetoothDevice {
     pub name: String,
     pub address: String,
@@ -48,7 +49,7 @@ pub async fn get_bluetooth_device_properties(conn: &Arc<SyncConnection>, device_
     })
 }
 
-pub async fn set_bluetooth_device_property(conn: &Arc<SyncConnection>, device_path: &str, property: &str, value: bool) {
+pub async fn set_bluetooth_device_property(conn: &Arc<SyncConnection>, device_path: &str, property: &str, value: bool) -> Result<(), String> {
     let proxy = nonblock::Proxy::new("org.bluez", device_path, Duration::from_secs(5), conn.clone());
     match proxy.method_call::<(), (&str, &str, dbus::arg::Variant<bool>), &str, &str>(
         "org.freedesktop.DBus.Properties",
@@ -59,34 +60,52 @@ pub async fn set_bluetooth_device_property(conn: &Arc<SyncConnection>, device_pa
             Variant(value),
         ),
     ).await {
-        Ok(_) => debug!("Property {} set successfully", property),
-        Err(e) => error!("Error setting property {}: {}", property, e),
+        Ok(_) => {
+            debug!("Property {} set successfully", property);
+            Ok(())
+        }
+        Err(e) => {
+            error!("Error setting property {}: {}", property, e);
+            Err(e.to_string())
+        }
     }
 }
 
-pub async fn handle_bluetooth_device_command(conn: &Arc<SyncConnection>, device_path: &str, method: &str) {
+pub async fn handle_bluetooth_device_command(conn: &Arc<SyncConnection>, device_path: &str, method: &str) -> Result<(), String> {
     match method {
         "Trust" => set_bluetooth_device_property(conn, device_path, "Trusted", true).await,
         "Untrust" => set_bluetooth_device_property(conn, device_path, "Trusted", false).await,
         _ => {
             let proxy = nonblock::Proxy::new("org.bluez", device_path, Duration::from_secs(5), conn.clone());
             match proxy.method_call::<(), (), _, _>("org.bluez.Device1", method, ()).await {
-                Ok(_) => debug!("{} successfully", method),
-                Err(e) => error!("Error in {}: {}", method, e),
+                Ok(_) => {
+                    debug!("{} successfully", method);
+                    Ok(())
+                }
+                Err(e) => {
+                    error!("Error in {}: {}", method, e);
+                    Err(e.to_string())
+                }
             }
         }
     }
 }
 
-pub async fn handle_bluetooth_discovery_command(conn: &Arc<SyncConnection>, msg: String) {
+pub async fn handle_bluetooth_discovery_command(conn: &Arc<SyncConnection>, msg: String) -> Result<(), String> {
     let proxy = nonblock::Proxy::new("org.bluez", "/org/bluez/hci0", Duration::from_secs(5), conn.clone());
-    match proxy.method_call::<(), (), _, _>("org.bluez.Adapter1", msg, ()).await {
-        Ok(_) => debug!("Discovery started successfully"),
-        Err(e) => error!("Error starting discovery: {}", e),
-    };
+    match proxy.method_call::<(), (), _, _>("org.bluez.Adapter1", msg.as_str(), ()).await {
+        Ok(_) => {
+            debug!("{} successfully", msg);
+            Ok(())
+        }
+        Err(e) => {
+            error!("Error in {}: {}", msg, e);
+            Err(e.to_string())
+        }
+    }
 }
 
-pub async fn handle_get_all_bluetooth_devices_command(conn: &Arc<SyncConnection>, tx: Sender<WebSocketMessage>) {
+pub async fn handle_get_all_bluetooth_devices_command(conn: &Arc<SyncConnection>, tx: EventBus) -> Result<(), String> {
     let proxy = nonblock::Proxy::new("org.bluez", "/", Duration::from_secs(5), conn.clone());
     match proxy.get_managed_objects().await {
         Ok(objects) => {
@@ -111,39 +130,43 @@ pub async fn handle_get_all_bluetooth_devices_command(conn: &Arc<SyncConnection>
 
                     let notification = WebSocketMessage {
                         t: Some("DEVICE_INFO".to_string()),
-                        op: 2,
+                        op: OpCode::Bluetooth,
                         d: Some(json!(bluetooth_device)),
                     };
 
-                    tx.send(notification).unwrap();
+                    tx.send(notification);
                 }
             }
+            Ok(())
+        }
+        Err(e) => {
+            error!("Error getting managed objects in bluetooth: {}", e);
+            Err(e.to_string())
         }
-        Err(e) => error!("Error getting managed objects in bluetooth: {}", e),
     }
 }
 
-pub fn send_bluetooth_discover_event(tx: &Sender<WebSocketMessage>, variant: &Variant<Box<dyn RefArg>>) {
+pub fn send_bluetooth_discover_event(tx: &EventBus, variant: &Variant<Box<dyn RefArg>>) {
     let discovering = variant.0.as_u64().unwrap_or(0) != 0;
 
     let notif = if discovering {
         WebSocketMessage {
             t: Some("DISCOVERY_STARTED".to_string()),
-            op: 2,
+            op: OpCode::Bluetooth,
             d: Some(json!(discovering)),
         }
     } else {
         WebSocketMessage {
             t: Some("DISCOVERY_STOPPED".to_string()),
-            op: 2,
+            op: OpCode::Bluetooth,
             d: Some(json!(discovering)),
         }
     };
 
-    tx.send(notif).unwrap();
+    tx.send(notif);
 }
 
-pub fn send_bluetooth_device_boned_event(tx: &Sender<WebSocketMessage>, msg: &Message, conn: &Arc<SyncConnection>, variant: &Variant<Box<dyn RefArg>>) {
+pub fn send_bluetooth_device_boned_event(tx: &EventBus, msg: &Message, conn: &Arc<SyncConnection>, variant: &Variant<Box<dyn RefArg>>) {
     let device_path = msg.path().unwrap().to_string();
     let bonded = variant.0.as_u64().unwrap_or(0) != 0;
     let tx = tx.clone();
@@ -155,25 +178,25 @@ pub fn send_bluetooth_device_boned_event(tx: &Sender<WebSocketMessage>, msg: &Me
                 let notif = if bonded {
                     WebSocketMessage {
                         t: Some("DEVICE_BONDED".to_string()),
-                        op: 2,
+                        op: OpCode::Bluetooth,
                         d: Some(json!(device)),
                     }
                 } else {
                     WebSocketMessage {
                         t: Some("DEVICE_UNBONDED".to_string()),
-                        op: 2,
+                        op: OpCode::Bluetooth,
                         d: Some(json!(device)),
                     }
                 };
 
-                tx.send(notif).unwrap();
+                tx.send(notif);
             }
             Err(e) => error!("Error getting device name: {}", e),
         }
     });
 }
 
-pub fn send_bluetooth_device_paired_event(tx: &Sender<WebSocketMessage>, msg: &Message, conn: &Arc<SyncConnection>, variant: &Variant<Box<dyn RefArg>>) {
+pub fn send_bluetooth_device_paired_event(tx: &EventBus, msg: &Message, conn: &Arc<SyncConnection>, variant: &Variant<Box<dyn RefArg>>) {
     let device_path = msg.path().unwrap().to_string();
     let paired = variant.0.as_u64().unwrap_or(0) != 0;
     let tx = tx.clone();
@@ -185,25 +208,25 @@ pub fn send_bluetooth_device_paired_event(tx: &Sender<WebSocketMessage>, msg: &M
                 let notif = if paired {
                     WebSocketMessage {
                         t: Some("DEVICE_PAIRED".to_string()),
-                        op: 2,
+                        op: OpCode::Bluetooth,
                         d: Some(json!(device)),
                     }
                 } else {
                     WebSocketMessage {
                         t: Some("DEVICE_UNPAIRED".to_string()),
-                        op: 2,
+                        op: OpCode::Bluetooth,
                         d: Some(json!(device)),
                     }
                 };
 
-                tx.send(notif).unwrap();
+                tx.send(notif);
             }
             Err(e) => error!("Error getting device name: {}", e),
         }
     });
 }
 
-pub fn send_bluetooth_device_trusted_event(tx: &Sender<WebSocketMessage>, msg: &Message, conn: &Arc<SyncConnection>, variant: &Variant<Box<dyn RefArg>>) {
+pub fn send_bluetooth_device_trusted_event(tx: &EventBus, msg: &Message, conn: &Arc<SyncConnection>, variant: &Variant<Box<dyn RefArg>>) {
     let device_path = msg.path().unwrap().to_string();
     let trusted = variant.0.as_u64().unwrap_or(0) != 0;
     let tx = tx.clone();
@@ -215,25 +238,25 @@ pub fn send_bluetooth_device_trusted_event(tx: &Sender<WebSocketMessage>, msg: &
                 let notif = if trusted {
                     WebSocketMessage {
                         t: Some("DEVICE_TRUSTED".to_string()),
-                        op: 2,
+                        op: OpCode::Bluetooth,
                         d: Some(json!(device)),
                     }
                 } else {
                     WebSocketMessage {
                         t: Some("DEVICE_UNTRUSTED".to_string()),
-                        op: 2,
+                        op: OpCode::Bluetooth,
                         d: Some(json!(device)),
                     }
                 };
 
-                tx.send(notif).unwrap();
+                tx.send(notif);
             }
             Err(e) => error!("Error getting device name: {}", e),
         }
     });
 }
 
-pub fn send_new_bluetooth_device_event(tx: &Sender<WebSocketMessage>, msg: &Message, conn: &Arc<SyncConnection>) {
+pub fn send_new_bluetooth_device_event(tx: &EventBus, msg: &Message, conn: &Arc<SyncConnection>) {
     let device_path = msg.path().unwrap().to_string();
     let conn = conn.clone();
     let tx = tx.clone();
@@ -242,18 +265,18 @@ pub fn send_new_bluetooth_device_event(tx: &Sender<WebSocketMessage>, msg: &Mess
             Ok(device) => {
                 let notif = WebSocketMessage {
                     t: Some("DEVICE_FOUND".to_string()),
-                    op: 2,
+                    op: OpCode::Bluetooth,
                     d: Some(json!(device)),
                 };
 
-                tx.send(notif).unwrap();
+                tx.send(notif);
             }
             Err(e) => error!("Error getting device name: {}", e),
         }
     });
 }
 
-pub fn send_bluetooth_device_connected_event(tx: &Sender<WebSocketMessage>, msg: &Message, conn: &Arc<SyncConnection>, variant: &Variant<Box<dyn RefArg>>) {
+pub fn send_bluetooth_device_connected_event(tx: &EventBus, msg: &Message, conn: &Arc<SyncConnection>, variant: &Variant<Box<dyn RefArg>>) {
     let device_path = msg.path().unwrap().to_string();
     let conn = conn.clone();
     let tx = tx.clone();
@@ -265,18 +288,18 @@ pub fn send_bluetooth_device_connected_event(tx: &Sender<WebSocketMessage>, msg:
                 let notif = if connected {
                     WebSocketMessage {
                         t: Some("DEVICE_CONNECTED".to_string()),
-                        op: 2,
+                        op: OpCode::Bluetooth,
                         d: Some(json!(device)),
                     }
                 } else {
                     WebSocketMessage {
                         t: Some("DEVICE_DISCONNECTED".to_string()),
-                        op: 2,
+                        op: OpCode::Bluetooth,
                         d: Some(json!(device)),
                     }
                 };
 
-                tx.send(notif).unwrap();
+                tx.send(notif);
             }
             Err(e) => error!("Error getting device name: {}", e),
         }
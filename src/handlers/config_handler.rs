@@ -0,0 +1,94 @@
+use std::fs;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use log::{error, info, warn};
+use serde_json::json;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::watch;
+use tokio::time::{Duration, interval};
+
+use crate::common::event_bus::EventBus;
+use crate::config::Config;
+use crate::models::events::ConfigReloaded;
+use crate::models::websocket::{OpCode, WebSocketMessage};
+
+/// How often the config file is checked for changes
+const WATCH_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Reloads the config when the file changes or on SIGHUP. Reloadable settings are applied
+/// right away, other changes are reported and wait for a restart.
+pub async fn config_handler(location: String, config: watch::Sender<Arc<Config>>, tx: EventBus) {
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(hangup) => Some(hangup),
+        Err(e) => {
+            error!("Failed to listen for SIGHUP, only file changes reload the config: {}", e);
+            None
+        }
+    };
+
+    let mut ticker = interval(WATCH_INTERVAL);
+    let mut modified = modified_at(&location);
+
+    loop {
+        tokio::select! {
+            Some(()) = async { hangup.as_mut()?.recv().await } => {
+                info!("Received SIGHUP, reloading {}", location);
+                modified = modified_at(&location);
+            }
+            _ = ticker.tick() => {
+                let current = modified_at(&location);
+                if current == modified {
+                    continue;
+                }
+                modified = current;
+                info!("{} changed, reloading", location);
+            }
+        }
+
+        reload(&location, &config, &tx);
+    }
+}
+
+fn modified_at(location: &str) -> Option<SystemTime> {
+    fs::metadata(location).and_then(|metadata| metadata.modified()).ok()
+}
+
+fn reload(location: &str, config: &watch::Sender<Arc<Config>>, tx: &EventBus) {
+    // A broken file keeps the running config, the next change is picked up again
+    let loaded = match Config::from_file_path(location) {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            error!("Not reloading {}: {}", location, e);
+            return;
+        }
+    };
+
+    let running = config.borrow().clone();
+    // Compared with the running config, so settings waiting for a restart are reported on every reload
+    let (applied, restart_required) = running.split_changes(&loaded);
+
+    if applied.is_empty() && restart_required.is_empty() {
+        info!("Config is unchanged");
+        return;
+    }
+
+    if !applied.is_empty() {
+        let mut updated = (*running).clone();
+        updated.apply_reloadable(&loaded);
+
+        ::log::set_max_level(updated.log.level_filter());
+        config.send_replace(Arc::new(updated));
+        info!("Applied config changes: {}", applied.join(", "));
+    }
+
+    if !restart_required.is_empty() {
+        warn!("Config changes that need a restart: {}", restart_required.join(", "));
+    }
+
+    tx.send(WebSocketMessage {
+        t: Some("CONFIG_RELOADED".to_string()),
+        op: OpCode::System,
+        d: Some(json!(ConfigReloaded { applied, restart_required })),
+    });
+}
@@ -1,154 +1,367 @@
-use std::fs::File;
-use std::sync::Arc;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::sync::atomic::{AtomicU64, Ordering};
 
-use axum::extract::ws::{Message, WebSocket};
+use axum::extract::ws::{CloseFrame, Message, WebSocket};
+use chrono::NaiveDateTime;
 use futures_util::{SinkExt, StreamExt};
-use log::error;
-use serde_derive::{Deserialize, Serialize};
+use futures_util::stream::SplitSink;
+use log::{debug, error};
+use serde_derive::Serialize;
 use serde_json::json;
+use tokio::time::{Duration, Instant, interval, timeout};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
+use tokio::sync::oneshot;
+use utoipa::ToSchema;
 
 use crate::api::AppState;
-use crate::enums::system_command::SystemCommand;
-use crate::hardware;
-use crate::models::websocket::WebSocketMessage;
+use crate::api::error::ApiError;
+use crate::api::permissions::{authorize, authorize_request, Permission};
+use crate::enums::system_command::{Responder, SystemCommand};
+use crate::handlers::request_handler::run_user_request;
+use crate::models::action_runs::Trigger;
+use crate::models::events::DisplayStatus;
+use crate::models::websocket::{Ack, ClientCommand, ClientFrame, CommandError, OpCode, ServerEvent, Subscriptions, WebSocketMessage};
 
-#[derive(Serialize, Deserialize)]
-struct WlanData {
-    ssid: String,
-    password: String
+/// Replies addressed only to the client that sent the command
+type ReplySender = UnboundedSender<WebSocketMessage>;
+type WebSocketSender = SplitSink<WebSocket, Message>;
+
+/// Close codes sent to clients, 4000-4999 are reserved for applications
+pub const CLOSE_NORMAL: u16 = 1000;
+pub const CLOSE_HEARTBEAT_TIMEOUT: u16 = 4000;
+
+/// Connected WebSocket clients, listed by `GET /system/clients`
+#[derive(Default)]
+pub struct ClientRegistry {
+    next_id: AtomicU64,
+    clients: Mutex<HashMap<u64, ClientInfo>>,
+}
+
+#[derive(Serialize, Clone, ToSchema)]
+pub struct ClientInfo {
+    pub id: u64,
+    pub address: String,
+    pub connected_on: NaiveDateTime,
+    pub subscriptions: Subscriptions,
+}
+
+/// Removes the client from the registry when the connection ends, however it ends
+pub struct ClientGuard {
+    id: u64,
+    registry: Arc<ClientRegistry>,
 }
 
-#[derive(Serialize, Deserialize)]
-struct UserDeleteData {
-    id: i32,
+impl ClientRegistry {
+    pub fn register(self: &Arc<Self>, address: SocketAddr) -> ClientGuard {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let client = ClientInfo {
+            id,
+            address: address.to_string(),
+            connected_on: chrono::Utc::now().naive_utc(),
+            subscriptions: Subscriptions::default(),
+        };
+
+        self.lock().insert(id, client);
+        ClientGuard { id, registry: self.clone() }
+    }
+
+    pub fn list(&self) -> Vec<ClientInfo> {
+        let mut clients = self.lock().values().cloned().collect::<Vec<_>>();
+        clients.sort_by_key(|client| client.id);
+        clients
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<u64, ClientInfo>> {
+        self.clients.lock().unwrap_or_else(|e| e.into_inner())
+    }
 }
 
-#[derive(Serialize, Deserialize)]
-struct ConstantData {
-    name: String,
+impl ClientGuard {
+    fn update_subscriptions(&self, subscriptions: &Subscriptions) {
+        if let Some(client) = self.registry.lock().get_mut(&self.id) {
+            client.subscriptions = subscriptions.clone();
+        }
+    }
 }
 
-#[derive(Serialize, Deserialize)]
-struct BluetoothDeviceData {
-    address: String,
+impl Drop for ClientGuard {
+    fn drop(&mut self) {
+        self.registry.lock().remove(&self.id);
+    }
 }
 
-pub async fn handle_connection(stream: WebSocket, state: Arc<AppState>) {
+pub async fn handle_connection(stream: WebSocket, address: SocketAddr, state: Arc<AppState>) {
     let (mut ws_sender, mut ws_receiver) = stream.split();
+    let (reply_tx, mut reply_rx) = unbounded_channel::<WebSocketMessage>();
+    let mut subscriptions = Subscriptions::default();
 
-    let tx_dbus = state.tx_dbus.clone();
-    let tx_dbus2 = tx_dbus.clone();
+    let client = state.clients.register(address);
+    debug!("WebSocket client {} connected from {}", client.id, address);
 
-    tx_dbus2.send(SystemCommand::GetAllBluetoothDevices).await.expect("Failed to send dbus command");
+    // Sends are bounded by the heartbeat timeout as well. A reloaded heartbeat applies to new connections.
+    let conf = state.config.borrow().server.heartbeat;
+    let send_timeout = Duration::from_secs(conf.timeout);
+    let mut heartbeat = interval(Duration::from_secs(conf.interval.max(1)));
+    let mut last_seen = Instant::now();
 
+    if send_message(&mut ws_sender, &ServerEvent::hello().into(), send_timeout).await.is_err() {
+        return;
+    }
+
+    // Subscribe before taking the snapshot so no event falls in between
     let mut rx = state.tx.subscribe();
 
-    loop {
+    if send_message(&mut ws_sender, &state.state_store.snapshot(), send_timeout).await.is_err() {
+        return;
+    }
+
+    let close_reason = loop {
         tokio::select! {
             message = rx.recv() => {
-                if let Ok(received_notification) = message {
-                    if let Ok(json_msg) = serde_json::to_string(&received_notification) {
-                        ws_sender.send(Message::Text(json_msg)).await.unwrap();
+                match message {
+                    Ok(received_notification) => {
+                        if !subscriptions.wants(&received_notification) {
+                            continue;
+                        }
+                        if send_message(&mut ws_sender, &received_notification, send_timeout).await.is_err() {
+                            break None;
+                        }
+                    }
+                    Err(RecvError::Lagged(skipped)) => {
+                        // Missed events can't be replayed, send the current state instead
+                        state.tx.record_lag("WebSocket client", skipped);
+                        if send_message(&mut ws_sender, &state.state_store.snapshot(), send_timeout).await.is_err() {
+                            break None;
+                        }
                     }
+                    Err(RecvError::Closed) => break Some((CLOSE_NORMAL, "Server is shutting down")),
                 }
             }
-            Some(msg) = ws_receiver.next() => {
-                if let Ok(message) = msg {
-                        if let Ok(text) = message.to_text() {
-                            let parsed = serde_json::from_str::<WebSocketMessage>(text);
-
-                            if let Ok(parsed_message) = parsed {
-                                    match parsed_message.op {
-                                        0 => { // Op code 0 for control commands
-                                            if let Some(event) = parsed_message.t {
-                                                match event.as_str() {
-                                                    "DISPLAY" => {
-                                                        let mut bl_power_file = File::create("/sys/class/backlight/10-0045/bl_power").unwrap();
-                                                        hardware::display::set_display_power(&mut bl_power_file, false);
-
-                                                        let notification = WebSocketMessage {
-                                                            t: Some("DISPLAY_STATUS".to_string()),
-                                                            op: 0,
-                                                            d: Some(json!({"status": "off"})),
-                                                        };
-                                                        if let Ok(json_msg) = serde_json::to_string(&notification) {
-                                                            ws_sender.send(Message::Text(json_msg)).await.expect("Failed send websocket message");
-                                                        }
-                                                    },
-                                                    "LISTING_UPDATE" => {
-                                                        tx_dbus.send(SystemCommand::ListingSystemUpdates).await.expect("Failed to send dbus command");
-                                                    },
-                                                    "UPDATE" => {
-                                                        tx_dbus.send(SystemCommand::UpdateSystem).await.expect("Failed to send dbus command");
-                                                    },
-                                                    _ => {}
-                                                }
-                                            }
-                                        },
-                                        2 => { // Op code 2 for bluetooth
-                                            if let Some(event) = parsed_message.t {
-                                                match event.as_str() {
-                                                    "DEVICES" => {
-                                                        tx_dbus.send(SystemCommand::GetAllBluetoothDevices).await.expect("Failed to send dbus command");
-                                                    },
-                                                    "START_DISCOVERING" => {
-                                                            tx_dbus.send(SystemCommand::BluetoothDiscovering("StartDiscovery".to_string())).await.expect("Failed to send dbus command");
-                                                    },
-                                                    "STOP_DISCOVERING" => {
-                                                            tx_dbus.send(SystemCommand::BluetoothDiscovering("StopDiscovery".to_string())).await.expect("Failed to send dbus command");
-                                                    },
-                                                    "CONNECT" => {
-                                                        if let Some(message) = parsed_message.d {
-                                                            if let Ok(device) = serde_json::from_value::<BluetoothDeviceData>(message) {
-                                                                tx_dbus.send(SystemCommand::ConnectBluetoothDevice(device.address)).await.expect("Failed to send dbus command");
-                                                            }
-                                                        }
-                                                    },
-                                                    "DISCONNECT" => {
-                                                        if let Some(message) = parsed_message.d {
-                                                             if let Ok(device) = serde_json::from_value::<BluetoothDeviceData>(message) {
-                                                                tx_dbus.send(SystemCommand::DisconnectBluetoothDevice(device.address)).await.expect("Failed to send dbus command");
-                                                            }
-                                                        }
-                                                    },
-                                                    "PAIR" => {
-                                                        if let Some(message) = parsed_message.d {
-                                                            if let Ok(device) = serde_json::from_value::<BluetoothDeviceData>(message) {
-                                                                tx_dbus.send(SystemCommand::PairBluetoothDevice(device.address)).await.expect("Failed to send dbus command");
-                                                            }
-                                                        }
-                                                    },
-                                                    "UNPAIR" => {
-                                                        if let Some(message) = parsed_message.d {
-                                                            if let Ok(device) = serde_json::from_value::<BluetoothDeviceData>(message) {
-                                                                tx_dbus.send(SystemCommand::UnpairBluetoothDevice(device.address)).await.expect("Failed to send dbus command");
-                                                            }
-                                                        }
-                                                    },
-                                                    "TRUST" => {
-                                                        if let Some(message) = parsed_message.d {
-                                                            if let Ok(device) = serde_json::from_value::<BluetoothDeviceData>(message) {
-                                                                tx_dbus.send(SystemCommand::TrustBluetoothDevice(device.address)).await.expect("Failed to send dbus command");
-                                                            }
-                                                        }
-                                                    },
-                                                    "UNTRUST" => {
-                                                        if let Some(message) = parsed_message.d {
-                                                            if let Ok(device) = serde_json::from_value::<BluetoothDeviceData>(message) {
-                                                                tx_dbus.send(SystemCommand::UntrustBluetoothDevice(device.address)).await.expect("Failed to send dbus command");
-                                                            }
-                                                        }
-                                                    },
-                                                    _ => {}
-                                                }
-                                            }
-                                        },
-                                    _ => {}
-                                }
-                            }
+            Some(reply) = reply_rx.recv() => {
+                if send_message(&mut ws_sender, &reply, send_timeout).await.is_err() {
+                    break None;
+                }
+            }
+            _ = heartbeat.tick() => {
+                if last_seen.elapsed() >= send_timeout {
+                    break Some((CLOSE_HEARTBEAT_TIMEOUT, "Heartbeat timeout"));
+                }
+                if timeout(send_timeout, ws_sender.send(Message::Ping(Vec::new()))).await.map_or(true, |r| r.is_err()) {
+                    break None;
+                }
+            }
+            msg = ws_receiver.next() => {
+                // Any frame, including pongs, proves the client is still there
+                last_seen = Instant::now();
+
+                match msg {
+                    Some(Ok(Message::Text(text))) => {
+                        handle_frame(&state, &text, &reply_tx, &mut subscriptions).await;
+                        client.update_subscriptions(&subscriptions);
                     }
+                    // The close handshake is answered by axum
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break None,
+                    Some(Ok(_)) => {}
                 }
             }
         }
+    };
+
+    if let Some((code, reason)) = close_reason {
+        debug!("Closing WebSocket client {}: {}", client.id, reason);
+        let frame = CloseFrame { code, reason: Cow::from(reason) };
+        let _ = timeout(send_timeout, ws_sender.send(Message::Close(Some(frame)))).await;
+    }
+
+    debug!("WebSocket client {} disconnected", client.id);
+}
+
+async fn send_message(ws_sender: &mut WebSocketSender, message: &WebSocketMessage, send_timeout: Duration) -> Result<(), ()> {
+    let json_msg = serde_json::to_string(message).map_err(|e| error!("Failed to serialize websocket message: {}", e))?;
+
+    // A half-open connection would otherwise block the client's task forever
+    match timeout(send_timeout, ws_sender.send(Message::Text(json_msg))).await {
+        Ok(Ok(())) => Ok(()),
+        _ => {
+            debug!("Failed to send websocket message");
+            Err(())
+        }
     }
 }
+
+async fn handle_frame(state: &Arc<AppState>, text: &str, reply_tx: &ReplySender, subscriptions: &mut Subscriptions) {
+    let frame = match ClientFrame::parse(text) {
+        Ok(frame) => frame,
+        Err(e) => {
+            reply(reply_tx, error_event(None, None, e.code(), e.message()));
+            return;
+        }
+    };
+
+    let command = match frame.command() {
+        Ok(command) => command,
+        Err(e) => {
+            reply(reply_tx, error_event(frame.nonce, frame.t, e.code(), e.message()));
+            return;
+        }
+    };
+
+    let name = frame.t.unwrap_or_default();
+    let reply = Reply { tx: reply_tx.clone(), nonce: frame.nonce, command: name };
+
+    match command {
+        ClientCommand::Subscribe { ops, events } => {
+            subscriptions.subscribe(ops, events);
+            reply.ack(serde_json::to_value(&*subscriptions).ok());
+        }
+        ClientCommand::Unsubscribe { ops, events } => {
+            subscriptions.unsubscribe(ops, events);
+            reply.ack(serde_json::to_value(&*subscriptions).ok());
+        }
+        command => handle_command(state, command, reply).await,
+    }
+}
+
+/// Where the outcome of a single command is reported
+struct Reply {
+    tx: ReplySender,
+    nonce: Option<String>,
+    command: String,
+}
+
+impl Reply {
+    fn ack(self, result: Option<serde_json::Value>) {
+        reply(&self.tx, ServerEvent::Ack(Ack { nonce: self.nonce, command: self.command, result }));
+    }
+
+    fn error(self, code: &str, message: String) {
+        reply(&self.tx, error_event(self.nonce, Some(self.command), code, message));
+    }
+
+    fn result(self, result: Result<(), String>) {
+        match result {
+            Ok(()) => self.ack(None),
+            Err(e) => self.error("COMMAND_FAILED", e),
+        }
+    }
+}
+
+async fn handle_command(state: &Arc<AppState>, command: ClientCommand, reply: Reply) {
+    let authorized = match (&command, required_permission(&command)) {
+        (ClientCommand::ExecuteRequest { id }, _) => authorize_request(state, *id).map(|_| ()),
+        (_, Some(permission)) => authorize(state, permission).map(|_| ()),
+        (_, None) => Ok(()),
+    };
+
+    if let Err(error) = authorized {
+        let code = match error {
+            ApiError::Unauthorized(_) | ApiError::Forbidden(_) | ApiError::NotFound(_) => error.code(),
+            _ => "COMMAND_FAILED",
+        };
+        reply.error(code, error.to_string());
+        return;
+    }
+
+    match command {
+        ClientCommand::Display => {
+            state.backlight.set_power(false);
+
+            // Broadcast so the state store and other clients see the change
+            state.tx.send(WebSocketMessage {
+                t: Some("DISPLAY_STATUS".to_string()),
+                op: OpCode::System,
+                d: Some(json!(DisplayStatus::new(false))),
+            });
+            reply.ack(None);
+        }
+        ClientCommand::ListingUpdate => queue_command(state, SystemCommand::ListingSystemUpdates, reply).await,
+        ClientCommand::Update => queue_command(state, SystemCommand::UpdateSystem, reply).await,
+        ClientCommand::SimulateRfid { uid } => match &state.simulator {
+            Some(simulator) => reply.result(simulator.scan_rfid(uid)),
+            None => reply.error("NOT_AVAILABLE", "Simulated hardware backend is disabled".to_string()),
+        },
+        ClientCommand::SimulateTouch => match &state.simulator {
+            Some(simulator) => reply.result(simulator.touch()),
+            None => reply.error("NOT_AVAILABLE", "Simulated hardware backend is disabled".to_string()),
+        },
+        ClientCommand::ExecuteRequest { id } => {
+            // The outcome is also broadcast to every client
+            let state = state.clone();
+            let runner = state.session.current().map(|session| session.user.id);
+            tokio::spawn(async move {
+                match run_user_request(&state, id, Trigger::WebSocket, runner).await {
+                    Ok(result) => reply.ack(Some(json!({ "status": result.status }))),
+                    Err(e) => reply.error("COMMAND_FAILED", e.to_string()),
+                }
+            });
+        }
+        ClientCommand::Devices => run_command(state, SystemCommand::GetAllBluetoothDevices, reply).await,
+        ClientCommand::StartDiscovering => run_command(state, |r| SystemCommand::BluetoothDiscovering("StartDiscovery".to_string(), r), reply).await,
+        ClientCommand::StopDiscovering => run_command(state, |r| SystemCommand::BluetoothDiscovering("StopDiscovery".to_string(), r), reply).await,
+        ClientCommand::Connect { address } => run_command(state, |r| SystemCommand::ConnectBluetoothDevice(address, r), reply).await,
+        ClientCommand::Disconnect { address } => run_command(state, |r| SystemCommand::DisconnectBluetoothDevice(address, r), reply).await,
+        ClientCommand::Pair { address } => run_command(state, |r| SystemCommand::PairBluetoothDevice(address, r), reply).await,
+        ClientCommand::Unpair { address } => run_command(state, |r| SystemCommand::UnpairBluetoothDevice(address, r), reply).await,
+        ClientCommand::Trust { address } => run_command(state, |r| SystemCommand::TrustBluetoothDevice(address, r), reply).await,
+        ClientCommand::Untrust { address } => run_command(state, |r| SystemCommand::UntrustBluetoothDevice(address, r), reply).await,
+        // Handled per connection in handle_frame
+        ClientCommand::Subscribe { .. } | ClientCommand::Unsubscribe { .. } => {}
+    }
+}
+
+/// What the logged in user must be allowed to do, `None` for commands anyone may send
+fn required_permission(command: &ClientCommand) -> Option<Permission> {
+    match command {
+        ClientCommand::ListingUpdate | ClientCommand::Update => Some(Permission::ManageSystem),
+        ClientCommand::StartDiscovering
+        | ClientCommand::StopDiscovering
+        | ClientCommand::Connect { .. }
+        | ClientCommand::Disconnect { .. }
+        | ClientCommand::Pair { .. }
+        | ClientCommand::Unpair { .. }
+        | ClientCommand::Trust { .. }
+        | ClientCommand::Untrust { .. } => Some(Permission::ManageDevices),
+        _ => None,
+    }
+}
+
+/// Hands a long running command to the system handler, acknowledged once it is queued.
+/// Progress is reported through the regular events.
+async fn queue_command(state: &Arc<AppState>, command: SystemCommand, reply: Reply) {
+    match state.tx_dbus.send(command).await {
+        Ok(()) => reply.ack(None),
+        Err(_) => reply.error("NOT_AVAILABLE", "System handler is not running".to_string()),
+    }
+}
+
+/// Hands a command to the system handler and acknowledges it once BlueZ answered
+async fn run_command<F>(state: &Arc<AppState>, command: F, reply: Reply)
+    where F: FnOnce(Responder) -> SystemCommand
+{
+    let (responder, result) = oneshot::channel();
+
+    if state.tx_dbus.send(command(Some(responder))).await.is_err() {
+        reply.error("NOT_AVAILABLE", "System handler is not running".to_string());
+        return;
+    }
+
+    tokio::spawn(async move {
+        match result.await {
+            Ok(result) => reply.result(result),
+            Err(_) => reply.error("NOT_AVAILABLE", "System handler is not running".to_string()),
+        }
+    });
+}
+
+fn error_event(nonce: Option<String>, command: Option<String>, code: &str, message: String) -> ServerEvent {
+    ServerEvent::Error(CommandError { nonce, command, code: code.to_string(), message })
+}
+
+fn reply(reply_tx: &ReplySender, event: ServerEvent) {
+    // The connection may already be closed
+    let _ = reply_tx.send(event.into());
+}
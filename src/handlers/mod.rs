@@ -2,4 +2,10 @@ pub mod connection_handler;
 pub mod system_handler;
 pub mod bluetooth_handler;
 pub mod network_handler;
-pub mod update_handler;
\ No newline at end of file
+pub mod update_handler;
+pub mod action_handler;
+pub mod request_handler;
+pub mod state_handler;
+pub mod session_handler;
+pub mod backup_handler;
+pub mod config_handler;
@@ -1,24 +1,15 @@
 use std::error::Error;
-use std::fs;
-use serde_derive::Serialize;
 
 use serde_json::json;
 use tokio::process::Command;
-use tokio::sync::broadcast::Sender;
 
-use crate::models::websocket::WebSocketMessage;
+use crate::common::event_bus::EventBus;
+use crate::models::events::NetworkStatus;
+use crate::models::interface::ScanResult;
+use crate::models::websocket::{OpCode, WebSocketMessage};
 use crate::network::interfaces::get_interfaces;
 
-#[derive(Serialize)]
-pub struct ScanResult {
-    bssid: String,
-    frequency: String,
-    signal_level: String,
-    flags: String,
-    ssid: String,
-}
-
-pub async fn get_network_interfaces(tx: Sender<WebSocketMessage>) -> Result<(), Box<dyn Error>>{
+pub async fn get_network_interfaces(tx: EventBus) -> Result<(), Box<dyn Error>>{
     let interfaces = get_interfaces();
 
     if interfaces.is_err() {
@@ -26,21 +17,21 @@ pub async fn get_network_interfaces(tx: Sender<WebSocketMessage>) -> Result<(),
     }
 
     let notification = WebSocketMessage {
-        op: 0,
+        op: OpCode::System,
         t: Some("NETWORK_INTERFACES".to_string()),
         d: Some(json!(interfaces.unwrap())),
     };
 
-    tx.send(notification).expect("Failed to send notification");
+    tx.send(notification);
 
     Ok(())
 }
 
-pub async fn get_current_network_status(tx: Sender<WebSocketMessage>) -> Result<(), Box<dyn Error>> {
+pub async fn get_current_network_status(tx: EventBus, interface: &str) -> Result<(), Box<dyn Error>> {
     let output_result = Command::new("wpa_cli")
         .arg("status")
         .arg("-i")
-        .arg("wlan0")
+        .arg(interface)
         .output().await;
 
     match output_result {
@@ -64,52 +55,48 @@ pub async fn get_current_network_status(tx: Sender<WebSocketMessage>) -> Result<
                 }
 
                 let notification = WebSocketMessage {
-                    op: 0,
+                    op: OpCode::System,
                     t: Some("NETWORK_STATUS".to_string()),
-                    d: Some(json!({
-                        "ssid": ssid,
-                        "status": status,
-                        "ip_address": ip_address,
+                    d: Some(json!(NetworkStatus {
+                        ssid: Some(ssid),
+                        status,
+                        ip_address: Some(ip_address),
                     })),
                 };
 
-                tx.send(notification).expect("Failed to send notification");
+                tx.send(notification);
                 Ok(())
             } else {
                 let notification = WebSocketMessage {
-                    op: 0,
+                    op: OpCode::System,
                     t: Some("NETWORK_STATUS".to_string()),
-                    d: Some(json!({
-                        "status": "DEACTIVATED",
-                    })),
+                    d: Some(json!(NetworkStatus::deactivated())),
                 };
 
-                tx.send(notification)?;
+                tx.send(notification);
 
                 Ok(())
             }
         }
         Err(_) => {
             let notification = WebSocketMessage {
-                op: 0,
+                op: OpCode::System,
                 t: Some("NETWORK_STATUS".to_string()),
-                d: Some(json!({
-                        "status": "DEACTIVATED",
-                    })),
+                d: Some(json!(NetworkStatus::deactivated())),
             };
 
-            tx.send(notification)?;
+            tx.send(notification);
             
             Ok(())
         },
     }
 }
 
-pub async fn get_scan_results(tx: Sender<WebSocketMessage>) -> Result<(), Box<dyn Error>> {
+pub async fn get_scan_results(tx: EventBus, interface: &str) -> Result<(), Box<dyn Error>> {
     let output_result = Command::new("wpa_cli")
         .arg("scan_results")
         .arg("-i")
-        .arg("wlan0")
+        .arg(interface)
         .output()
         .await;
 
@@ -133,34 +120,34 @@ pub async fn get_scan_results(tx: Sender<WebSocketMessage>) -> Result<(), Box<dy
                 }
 
                 let notification = WebSocketMessage {
-                    op: 0,
+                    op: OpCode::System,
                     t: Some("SCAN_RESULTS".to_string()),
                     d: Some(json!(results)),
                 };
 
-                tx.send(notification)?;
+                tx.send(notification);
                 
                 Ok(())
             } else {
                 let notification = WebSocketMessage {
-                    op: 0,
+                    op: OpCode::System,
                     t: Some("SCAN_RESULTS".to_string()),
                     d: Some(json!([])),
                 };
 
-                tx.send(notification)?;
+                tx.send(notification);
 
                 Ok(())
             }
         }
         Err(_) => {
             let notification = WebSocketMessage {
-                op: 0,
+                op: OpCode::System,
                 t: Some("SCAN_RESULTS".to_string()),
                 d: Some(json!([])),
             };
 
-            tx.send(notification)?;
+            tx.send(notification);
 
             Ok(())
         },
@@ -0,0 +1,293 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use diesel::result::Error as DieselError;
+use log::{debug, error};
+use reqwest::Client;
+use serde_derive::Serialize;
+use serde_json::{json, Value};
+use thiserror::Error;
+use utoipa::ToSchema;
+
+use crate::api::AppState;
+use crate::api::permissions::Permission;
+use crate::handlers::action_handler::record_run;
+use crate::models::action_runs::{NewActionRun, Trigger};
+use crate::models::constants::{Constant, ConstantKind};
+use crate::models::events::{RequestExecuted, RequestFailed};
+use crate::models::user::User;
+use crate::models::user_requests::{parse_method, parse_parameters, UserRequest};
+use crate::models::websocket::{OpCode, WebSocketMessage};
+
+const DEFAULT_TIMEOUT_SECS: u64 = 10;
+const MAX_TIMEOUT_SECS: u64 = 60;
+
+#[derive(Serialize, Clone, Debug, ToSchema)]
+pub struct RequestResult {
+    pub status: u16,
+    pub headers: HashMap<String, String>,
+    pub body: String,
+    #[schema(value_type = u64)]
+    pub duration_ms: u128,
+}
+
+#[derive(Error, Debug)]
+pub enum RequestError {
+    #[error("Request not found")]
+    NotFound,
+    #[error("Invalid request parameters: {0}")]
+    InvalidParameters(String),
+    #[error("Request timed out")]
+    Timeout,
+    #[error("Request failed: {0}")]
+    Failed(String),
+    #[error("Database error: {0}")]
+    Database(String),
+    #[error("Secret constant {0} could not be decrypted")]
+    Secret(String),
+    #[error("Secret constant {0} is not available to the user running the request")]
+    SecretWithheld(String),
+}
+
+/// Loads a saved request, fills in the owner's resolved constants, executes it, records the run and
+/// broadcasts the outcome. Anyone allowed to edit a request could send its secrets elsewhere, so
+/// secrets of the owner are only filled in when the owner runs it (`runner`) and global secrets only
+/// when the runner may manage the system. A request using a withheld secret isn't sent.
+pub async fn run_user_request(state: &AppState, request_id: i32, trigger: Trigger, runner: Option<i32>) -> Result<RequestResult, RequestError> {
+    let (user_request, constants) = {
+        let mut conn = state.db_pool.get().map_err(|e| RequestError::Database(e.to_string()))?;
+
+        let user_request = UserRequest::get_by_id(request_id, &mut conn).map_err(|e| match e {
+            DieselError::NotFound => RequestError::NotFound,
+            e => RequestError::Database(e.to_string()),
+        })?;
+
+        let runner_manages_system = runner
+            .and_then(|runner| User::get_by_id(runner, &mut conn).ok())
+            .is_some_and(|user| Permission::ManageSystem.allowed(user.role, user.id));
+
+        let (readable, withheld): (Vec<Constant>, Vec<Constant>) = Constant::get_resolved(user_request.user_id, &mut conn)
+            .map_err(|e| RequestError::Database(e.to_string()))?
+            .into_iter()
+            .partition(|constant| match (constant.kind, constant.user_id) {
+                (ConstantKind::Secret, None) => runner_manages_system,
+                (ConstantKind::Secret, Some(_)) => runner == Some(user_request.user_id),
+                _ => true,
+            });
+
+        let used = placeholders(&user_request.endpoint).into_iter()
+            .chain(placeholders(&user_request.parameters))
+            .collect::<Vec<&str>>();
+        if let Some(constant) = withheld.into_iter().find(|constant| used.contains(&constant.name.as_str())) {
+            return Err(RequestError::SecretWithheld(constant.name));
+        }
+
+        let constants = readable
+            .into_iter()
+            .map(|constant| match constant.plain_value(&state.secrets) {
+                Ok(plain) => Ok((constant.name, plain)),
+                Err(_) => Err(RequestError::Secret(constant.name)),
+            })
+            .collect::<Result<HashMap<String, String>, RequestError>>()?;
+
+        (user_request, constants)
+    };
+
+    let started = chrono::Utc::now().naive_utc();
+    let result = execute_user_request(&state.client, &user_request, &constants).await;
+
+    let mut run = NewActionRun::new(user_request.user_id, &trigger, started, match &result {
+        Ok(response) => Ok(Some(response.body.clone())),
+        Err(e) => Err(e.to_string()),
+    });
+    run.request_id = Some(user_request.id);
+    run.status_code = result.as_ref().ok().map(|response| response.status as i32);
+    record_run(state, run);
+
+    let notification = match &result {
+        Ok(response) => WebSocketMessage {
+            t: Some("REQUEST_EXECUTED".to_string()),
+            op: OpCode::Actions,
+            d: Some(json!(RequestExecuted {
+                request_id: user_request.id,
+                name: user_request.name.clone(),
+                result: response.clone(),
+            })),
+        },
+        Err(e) => {
+            error!("Request {} ({}) failed: {}", user_request.id, user_request.name, e);
+            WebSocketMessage {
+                t: Some("REQUEST_FAILED".to_string()),
+                op: OpCode::Actions,
+                d: Some(json!(RequestFailed {
+                    request_id: user_request.id,
+                    name: user_request.name.clone(),
+                    error: e.to_string(),
+                })),
+            }
+        }
+    };
+
+    state.tx.send(notification);
+
+    result
+}
+
+pub async fn execute_user_request(client: &Client, user_request: &UserRequest, constants: &HashMap<String, String>) -> Result<RequestResult, RequestError> {
+    let parameters = parse_parameters(&user_request.parameters).map_err(RequestError::InvalidParameters)?;
+    let method = parse_method(&parameters).map_err(RequestError::InvalidParameters)?;
+
+    let timeout = Duration::from_secs(parameters.timeout.unwrap_or(DEFAULT_TIMEOUT_SECS).min(MAX_TIMEOUT_SECS));
+    let endpoint = apply_constants(&user_request.endpoint, constants);
+
+    // The rendered endpoint may contain secrets, so log the template
+    debug!("Executing request {} ({} {})", user_request.name, method, user_request.endpoint);
+
+    let mut builder = client.request(method, endpoint).timeout(timeout);
+
+    for (name, value) in parameters.headers {
+        builder = builder.header(name, apply_constants(&value, constants));
+    }
+
+    builder = match parameters.body.map(|body| apply_constants_to_value(body, constants)) {
+        Some(Value::String(body)) => builder.body(body),
+        Some(body) => builder.json(&body),
+        None => builder,
+    };
+
+    let started = Instant::now();
+    let response = builder.send().await.map_err(map_reqwest_error)?;
+
+    let status = response.status().as_u16();
+    let headers = response.headers().iter()
+        .map(|(name, value)| (name.to_string(), value.to_str().unwrap_or_default().to_string()))
+        .collect();
+    let body = response.text().await.map_err(map_reqwest_error)?;
+
+    Ok(RequestResult { status, headers, body, duration_ms: started.elapsed().as_millis() })
+}
+
+/// Names of the `{{constant_name}}` placeholders in the text
+fn placeholders(text: &str) -> Vec<&str> {
+    let mut names = Vec::new();
+    let mut rest = text;
+
+    while let Some(start) = rest.find("{{") {
+        let after_open = &rest[start + 2..];
+        let Some(end) = after_open.find("}}") else {
+            break;
+        };
+        names.push(after_open[..end].trim());
+        rest = &after_open[end + 2..];
+    }
+
+    names
+}
+
+/// Replaces `{{constant_name}}` placeholders with the value of the constant.
+/// Placeholders without a matching constant are left untouched.
+pub fn apply_constants(text: &str, constants: &HashMap<String, String>) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find("{{") {
+        result.push_str(&rest[..start]);
+        let after_open = &rest[start + 2..];
+
+        match after_open.find("}}") {
+            Some(end) => {
+                let name = after_open[..end].trim();
+                match constants.get(name) {
+                    Some(value) => result.push_str(value),
+                    None => result.push_str(&rest[start..start + 2 + end + 2]),
+                }
+                rest = &after_open[end + 2..];
+            }
+            None => {
+                result.push_str(&rest[start..]);
+                rest = "";
+            }
+        }
+    }
+
+    result.push_str(rest);
+    result
+}
+
+fn apply_constants_to_value(value: Value, constants: &HashMap<String, String>) -> Value {
+    match value {
+        Value::String(text) => Value::String(apply_constants(&text, constants)),
+        Value::Array(items) => Value::Array(items.into_iter().map(|item| apply_constants_to_value(item, constants)).collect()),
+        Value::Object(fields) => Value::Object(fields.into_iter().map(|(key, item)| (key, apply_constants_to_value(item, constants))).collect()),
+        other => other,
+    }
+}
+
+/// Drops the URL from the error, it may contain secrets and ends up in logs and events
+fn map_reqwest_error(error: reqwest::Error) -> RequestError {
+    let error = error.without_url();
+    if error.is_timeout() {
+        RequestError::Timeout
+    } else if error.is_builder() {
+        RequestError::InvalidParameters(error.to_string())
+    } else {
+        RequestError::Failed(error.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn constants() -> HashMap<String, String> {
+        HashMap::from([
+            ("host".to_string(), "http://hub.local".to_string()),
+            ("token".to_string(), "abc".to_string()),
+        ])
+    }
+
+    #[test]
+    fn replaces_known_placeholders() {
+        assert_eq!(apply_constants("{{host}}/api?token={{ token }}", &constants()), "http://hub.local/api?token=abc");
+    }
+
+    #[test]
+    fn keeps_missing_placeholders() {
+        assert_eq!(apply_constants("{{host}}/{{missing}}", &constants()), "http://hub.local/{{missing}}");
+    }
+
+    #[test]
+    fn keeps_unterminated_placeholders() {
+        assert_eq!(apply_constants("{{host}}/{{token", &constants()), "http://hub.local/{{token");
+        assert_eq!(apply_constants("{{", &constants()), "{{");
+    }
+
+    #[test]
+    fn does_not_resolve_nested_placeholders() {
+        // The name ends at the first `}}`, so the outer braces stay as they are
+        assert_eq!(apply_constants("{{ {{token}} }}", &constants()), "{{ {{token}} }}");
+        assert_eq!(apply_constants("{{{{token}}}}", &constants()), "{{{{token}}}}");
+    }
+
+    #[test]
+    fn finds_placeholders() {
+        assert_eq!(placeholders("{{host}}/api?token={{ token }}&x={{"), vec!["host", "token"]);
+        assert_eq!(placeholders(r#"{"headers":{"Authorization":"Bearer {{token}}"}}"#), vec!["token"]);
+        assert!(placeholders("no placeholders }}").is_empty());
+    }
+
+    #[test]
+    fn replaces_placeholders_in_nested_values() {
+        let body = json!({
+            "url": "{{host}}",
+            "items": ["{{token}}", 1, {"auth": "Bearer {{token}}", "missing": "{{missing}}"}],
+            "enabled": true,
+        });
+
+        assert_eq!(apply_constants_to_value(body, &constants()), json!({
+            "url": "http://hub.local",
+            "items": ["abc", 1, {"auth": "Bearer abc", "missing": "{{missing}}"}],
+            "enabled": true,
+        }));
+    }
+}
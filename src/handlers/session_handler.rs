@@ -0,0 +1,264 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use argon2::{Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
+use argon2::password_hash::SaltString;
+use chrono::NaiveDateTime;
+use log::{debug, error, info, warn};
+use serde_derive::Serialize;
+use serde_json::json;
+use thiserror::Error;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::broadcast::Receiver;
+use utoipa::ToSchema;
+
+use crate::api::AppState;
+use crate::common::event_bus::EventBus;
+use crate::models::events::SessionChanged;
+use crate::models::rfid_tags::RfidTag;
+use crate::models::user::User;
+use crate::models::websocket::{OpCode, WebSocketMessage};
+
+/// Wrong PINs in a row before a user is locked out
+const MAX_PIN_ATTEMPTS: u32 = 5;
+/// First lockout, doubled on every further one up to `MAX_PIN_LOCKOUT`
+const PIN_LOCKOUT: Duration = Duration::from_secs(60);
+const MAX_PIN_LOCKOUT: Duration = Duration::from_secs(24 * 3600);
+
+/// The user currently standing at the hub
+#[derive(Serialize, Clone, Debug, ToSchema)]
+pub struct ActiveSession {
+    pub user: User,
+    pub method: LoginMethod,
+    pub started_on: NaiveDateTime,
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum LoginMethod {
+    Pin,
+    Rfid,
+    Select,
+    Action,
+}
+
+#[derive(Default)]
+pub struct SessionStore {
+    current: Mutex<Option<ActiveSession>>,
+    failed_pins: Mutex<HashMap<i32, FailedPins>>,
+}
+
+#[derive(Default)]
+struct FailedPins {
+    count: u32,
+    locked_until: Option<Instant>,
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum PinError {
+    #[error("Invalid PIN")]
+    Invalid,
+    #[error("Too many invalid PINs, try again in {} seconds", .0.as_secs().max(1))]
+    LockedOut(Duration),
+}
+
+impl SessionStore {
+    pub fn current(&self) -> Option<ActiveSession> {
+        self.lock().clone()
+    }
+
+    pub fn login(&self, tx: &EventBus, user: User, method: LoginMethod) -> ActiveSession {
+        let session = ActiveSession { user, method, started_on: chrono::Utc::now().naive_utc() };
+        info!("User {} logged in ({:?})", session.user.username, method);
+
+        *self.lock() = Some(session.clone());
+        notify(tx, Some(&session), "login");
+
+        session
+    }
+
+    pub fn logout(&self, tx: &EventBus, reason: &str) {
+        if let Some(session) = self.lock().take() {
+            info!("User {} logged out ({})", session.user.username, reason);
+            notify(tx, None, reason);
+        }
+    }
+
+    /// Ends the session only if it belongs to the given user
+    pub fn logout_user(&self, tx: &EventBus, user_id: i32, reason: &str) {
+        let is_current = self.lock().as_ref().is_some_and(|session| session.user.id == user_id);
+
+        if is_current {
+            self.logout(tx, reason);
+        }
+    }
+
+    /// Checks the PIN of a user, locking the user out after `MAX_PIN_ATTEMPTS` wrong ones in a row.
+    /// RFID tags still log a locked out user in.
+    pub fn check_pin(&self, user_id: i32, pin: &str, pin_hash: &str) -> Result<(), PinError> {
+        if let Some(remaining) = self.lockout(user_id) {
+            return Err(PinError::LockedOut(remaining));
+        }
+
+        if verify_pin(pin, pin_hash) {
+            self.failed_pins().remove(&user_id);
+            return Ok(());
+        }
+
+        let mut failed_pins = self.failed_pins();
+        let failed = failed_pins.entry(user_id).or_default();
+        failed.count += 1;
+
+        if !failed.count.is_multiple_of(MAX_PIN_ATTEMPTS) {
+            return Err(PinError::Invalid);
+        }
+
+        let lockouts = failed.count / MAX_PIN_ATTEMPTS - 1;
+        let duration = PIN_LOCKOUT.saturating_mul(2u32.saturating_pow(lockouts)).min(MAX_PIN_LOCKOUT);
+        failed.locked_until = Some(Instant::now() + duration);
+        warn!("User {} is locked out for {} seconds after {} invalid PINs", user_id, duration.as_secs(), failed.count);
+
+        Err(PinError::LockedOut(duration))
+    }
+
+    fn lockout(&self, user_id: i32) -> Option<Duration> {
+        self.failed_pins().get(&user_id)
+            .and_then(|failed| failed.locked_until)
+            .and_then(|until| until.checked_duration_since(Instant::now()))
+            .filter(|remaining| !remaining.is_zero())
+    }
+
+    fn failed_pins(&self) -> MutexGuard<'_, HashMap<i32, FailedPins>> {
+        self.failed_pins.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Option<ActiveSession>> {
+        self.current.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Logs users in when one of their RFID tags is scanned and out when the display goes to sleep
+pub async fn session_handler(state: Arc<AppState>, mut rx: Receiver<WebSocketMessage>) {
+    loop {
+        let message = match rx.recv().await {
+            Ok(message) => message,
+            Err(RecvError::Lagged(skipped)) => {
+                state.tx.record_lag("Session handler", skipped);
+                continue;
+            }
+            Err(RecvError::Closed) => break,
+        };
+
+        match (message.op, message.t.as_deref()) {
+            (OpCode::Rfid, Some("RFID_DETECT")) => {
+                let uid = message.d.as_ref().and_then(|d| d.get("rfid_uid")).and_then(|uid| uid.as_str());
+                if let Some(uid) = uid {
+                    login_by_rfid(&state, uid);
+                }
+            }
+            (OpCode::System, Some("DISPLAY_STATUS")) => {
+                let status = message.d.as_ref().and_then(|d| d.get("status")).and_then(|status| status.as_str());
+                if status == Some("off") {
+                    state.session.logout(&state.tx, "display_sleep");
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn login_by_rfid(state: &AppState, uid: &str) {
+    let mut conn = match state.db_pool.get() {
+        Ok(conn) => conn,
+        Err(e) => {
+            error!("Failed to get database connection: {}", e);
+            return;
+        }
+    };
+
+    let tag = match RfidTag::get_by_uid(uid, &mut conn) {
+        Ok(tag) => tag,
+        Err(_) => {
+            debug!("RFID tag {} is not bound to a user", uid);
+            return;
+        }
+    };
+
+    match User::get_by_id(tag.user_id, &mut conn) {
+        Ok(user) => {
+            state.session.login(&state.tx, user, LoginMethod::Rfid);
+        }
+        Err(e) => error!("Failed to load user {} of RFID tag {}: {}", tag.user_id, uid, e),
+    }
+}
+
+pub fn hash_pin(pin: &str) -> Result<String, String> {
+    let salt = SaltString::generate(&mut rand::rngs::OsRng);
+
+    Argon2::default()
+        .hash_password(pin.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|e| e.to_string())
+}
+
+pub fn verify_pin(pin: &str, pin_hash: &str) -> bool {
+    PasswordHash::new(pin_hash)
+        .is_ok_and(|hash| Argon2::default().verify_password(pin.as_bytes(), &hash).is_ok())
+}
+
+fn notify(tx: &EventBus, session: Option<&ActiveSession>, reason: &str) {
+    tx.send(WebSocketMessage {
+        t: Some("SESSION_CHANGED".to_string()),
+        op: OpCode::System,
+        d: Some(json!(SessionChanged { session: session.cloned(), reason: reason.to_string() })),
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store_with_pin(pin: &str) -> (SessionStore, String) {
+        (SessionStore::default(), hash_pin(pin).unwrap())
+    }
+
+    #[test]
+    fn accepts_the_right_pin() {
+        let (store, hash) = store_with_pin("1234");
+        assert_eq!(store.check_pin(1, "1234", &hash), Ok(()));
+    }
+
+    #[test]
+    fn locks_out_after_too_many_invalid_pins() {
+        let (store, hash) = store_with_pin("1234");
+
+        for _ in 1..MAX_PIN_ATTEMPTS {
+            assert_eq!(store.check_pin(1, "0000", &hash), Err(PinError::Invalid));
+        }
+        assert_eq!(store.check_pin(1, "0000", &hash), Err(PinError::LockedOut(PIN_LOCKOUT)));
+
+        // Not even the right PIN gets through, other users aren't affected
+        assert!(matches!(store.check_pin(1, "1234", &hash), Err(PinError::LockedOut(_))));
+        assert_eq!(store.check_pin(2, "1234", &hash), Ok(()));
+    }
+
+    #[test]
+    fn doubles_the_lockout() {
+        let (store, hash) = store_with_pin("1234");
+        store.failed_pins().insert(1, FailedPins { count: 2 * MAX_PIN_ATTEMPTS - 1, locked_until: None });
+
+        assert_eq!(store.check_pin(1, "0000", &hash), Err(PinError::LockedOut(PIN_LOCKOUT * 2)));
+    }
+
+    #[test]
+    fn right_pin_resets_the_count() {
+        let (store, hash) = store_with_pin("1234");
+
+        for _ in 1..MAX_PIN_ATTEMPTS {
+            assert_eq!(store.check_pin(1, "0000", &hash), Err(PinError::Invalid));
+        }
+        assert_eq!(store.check_pin(1, "1234", &hash), Ok(()));
+        assert_eq!(store.check_pin(1, "0000", &hash), Err(PinError::Invalid));
+    }
+}
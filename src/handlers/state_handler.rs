@@ -0,0 +1,121 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock};
+
+use serde_json::{json, Value};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::broadcast::Receiver;
+
+use crate::common::event_bus::EventBus;
+use crate::models::events::{DisplayStatus, Ready, ReadyBluetooth, ReadyNetwork, ReadyUpdates};
+use crate::models::websocket::{OpCode, WebSocketMessage};
+
+/// Latest known value of every stateful event, sent to new clients as `READY`
+pub struct StateStore {
+    state: RwLock<SystemState>,
+}
+
+#[derive(Default)]
+struct SystemState {
+    display: Option<Value>,
+    network_status: Option<Value>,
+    network_interfaces: Option<Value>,
+    scan_results: Option<Value>,
+    session: Option<Value>,
+    bluetooth_discovering: Option<bool>,
+    bluetooth_devices: BTreeMap<String, Value>,
+    update_status: Option<&'static str>,
+    available_updates: Vec<Value>,
+}
+
+impl StateStore {
+    pub fn new(display_on: bool) -> Self {
+        let state = SystemState {
+            display: Some(display_status(display_on)),
+            ..Default::default()
+        };
+
+        StateStore { state: RwLock::new(state) }
+    }
+
+    /// Updates the cached state from a broadcast event. Events that don't describe state are ignored.
+    pub fn apply(&self, message: &WebSocketMessage) {
+        let (Some(event), Some(data)) = (message.t.as_deref(), message.d.as_ref()) else {
+            return;
+        };
+        let mut state = self.state.write().unwrap_or_else(|e| e.into_inner());
+
+        match (message.op, event) {
+            (OpCode::System, "DISPLAY_STATUS") => state.display = Some(data.clone()),
+            (OpCode::System, "NETWORK_STATUS") => state.network_status = Some(data.clone()),
+            (OpCode::System, "NETWORK_INTERFACES") => state.network_interfaces = Some(data.clone()),
+            (OpCode::System, "SCAN_RESULTS") => state.scan_results = Some(data.clone()),
+            (OpCode::System, "SESSION_CHANGED") => state.session = data.get("session").cloned(),
+            (OpCode::Bluetooth, "DISCOVERY_STARTED") => state.bluetooth_discovering = Some(true),
+            (OpCode::Bluetooth, "DISCOVERY_STOPPED") => state.bluetooth_discovering = Some(false),
+            (OpCode::Bluetooth, _) => {
+                // Every device event carries the full device
+                if let Some(address) = data.get("address").and_then(|a| a.as_str()) {
+                    state.bluetooth_devices.insert(address.to_string(), data.clone());
+                }
+            }
+            (OpCode::Updates, "START_LISTING_UPDATE") => {
+                state.update_status = Some("listing");
+                state.available_updates.clear();
+            }
+            (OpCode::Updates, "UPDATE_AVAILABLE") => {
+                if let Some(line) = data.get("message") {
+                    state.available_updates.push(line.clone());
+                }
+            }
+            (OpCode::Updates, "FINISHED_LISTING_UPDATE") => state.update_status = Some("listed"),
+            (OpCode::Updates, "START_UPDATE_PROCESS") => state.update_status = Some("updating"),
+            (OpCode::Updates, "UPDATE_SUCCESS") => {
+                state.update_status = Some("succeeded");
+                state.available_updates.clear();
+            }
+            (OpCode::Updates, "UPDATE_FAILURE") => state.update_status = Some("failed"),
+            _ => {}
+        }
+    }
+
+    pub fn snapshot(&self) -> WebSocketMessage {
+        let state = self.state.read().unwrap_or_else(|e| e.into_inner());
+
+        WebSocketMessage {
+            t: Some("READY".to_string()),
+            op: OpCode::Protocol,
+            d: Some(json!(Ready {
+                display: state.display.clone(),
+                session: state.session.clone(),
+                network: ReadyNetwork {
+                    status: state.network_status.clone(),
+                    interfaces: state.network_interfaces.clone(),
+                    scan_results: state.scan_results.clone(),
+                },
+                bluetooth: ReadyBluetooth {
+                    discovering: state.bluetooth_discovering,
+                    devices: state.bluetooth_devices.values().cloned().collect(),
+                },
+                updates: ReadyUpdates {
+                    status: state.update_status,
+                    available: state.available_updates.clone(),
+                },
+            })),
+        }
+    }
+}
+
+pub async fn state_handler(store: Arc<StateStore>, bus: EventBus, mut rx: Receiver<WebSocketMessage>) {
+    loop {
+        match rx.recv().await {
+            Ok(message) => store.apply(&message),
+            // The next poll or device event brings the store up to date again
+            Err(RecvError::Lagged(skipped)) => bus.record_lag("State store", skipped),
+            Err(RecvError::Closed) => break,
+        }
+    }
+}
+
+fn display_status(on: bool) -> Value {
+    json!(DisplayStatus::new(on))
+}
@@ -5,20 +5,21 @@ use log::debug;
 use tokio::process::Command;
 
 use serde_json::json;
-use tokio::sync::broadcast::Sender;
-use crate::models::websocket::WebSocketMessage;
+use crate::common::event_bus::EventBus;
+use crate::models::events::UpdateMessage;
+use crate::models::websocket::{OpCode, WebSocketMessage};
 
-pub async fn get_available_updates(tx: Sender<WebSocketMessage>) -> Result<(), Box<dyn Error>> {
+pub async fn get_available_updates(tx: EventBus) -> Result<(), Box<dyn Error>> {
     let notification = WebSocketMessage {
-        op: 4,
+        op: OpCode::Updates,
         t: Some("START_LISTING_UPDATE".to_string()),
-        d: Some(json!({"message": "Start listing updates"})),
+        d: Some(json!(UpdateMessage::new("Start listing updates"))),
     };
 
-    tx.send(notification).expect("Failed to send notification");
+    tx.send(notification);
 
     let update_status = Command::new("sh")
-        .args(&["-c", "apt update"])
+        .args(["-c", "apt update"])
         .stdout(Stdio::null())
         .stderr(Stdio::null())
         .status().await;
@@ -26,7 +27,7 @@ pub async fn get_available_updates(tx: Sender<WebSocketMessage>) -> Result<(), B
     debug!("apt update finished with status: {:?}", update_status);
 
     let mut child = Command::new("sh")
-        .args(&["-c", "apt list --upgradable -a"])
+        .args(["-c", "apt list --upgradable -a"])
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
         .spawn().expect("Failed to spawn apt list command");
@@ -39,37 +40,37 @@ pub async fn get_available_updates(tx: Sender<WebSocketMessage>) -> Result<(), B
         }
 
         let notification = WebSocketMessage {
-            op: 4,
+            op: OpCode::Updates,
             t: Some("UPDATE_AVAILABLE".to_string()),
-            d: Some(json!({"message": line})),
+            d: Some(json!(UpdateMessage::new(line))),
         };
 
-        tx.send(notification).expect("Failed to send notification");
+        tx.send(notification);
     }
 
     let notification = WebSocketMessage {
-        op: 4,
+        op: OpCode::Updates,
         t: Some("FINISHED_LISTING_UPDATE".to_string()),
-        d: Some(json!({"message": "Finished listing updates"})),
+        d: Some(json!(UpdateMessage::new("Finished listing updates"))),
     };
 
-    tx.send(notification).expect("Failed to send notification");
+    tx.send(notification);
 
     Ok(())
 }
 
-pub async fn perform_system_update(tx: Sender<WebSocketMessage>) -> Result<(), Box<dyn Error>> {
+pub async fn perform_system_update(tx: EventBus) -> Result<(), Box<dyn Error>> {
     let notification = WebSocketMessage {
-        op: 4,
+        op: OpCode::Updates,
         t: Some("START_UPDATE_PROCESS".to_string()),
-        d: Some(json!({"message": "Starting system update process"})),
+        d: Some(json!(UpdateMessage::new("Starting system update process"))),
     };
-    tx.send(notification).expect("Failed to send notification");
+    tx.send(notification);
 
 
     // Execute the update command
     let update_status = Command::new("sh")
-        .args(&["-c", "apt-get upgrade -y"])
+        .args(["-c", "apt-get upgrade -y"])
         .stdout(Stdio::null())
         .stderr(Stdio::null())
         .status().await;
@@ -77,19 +78,19 @@ pub async fn perform_system_update(tx: Sender<WebSocketMessage>) -> Result<(), B
     match update_status {
         Ok(status) if status.success() => {
             let success_notification = WebSocketMessage {
-                op: 4,
+                op: OpCode::Updates,
                 t: Some("UPDATE_SUCCESS".to_string()),
-                d: Some(json!({"message": "System update completed successfully"})),
+                d: Some(json!(UpdateMessage::new("System update completed successfully"))),
             };
-            tx.send(success_notification).expect("Failed to send notification");
+            tx.send(success_notification);
         }
         _ => {
             let fail_notification = WebSocketMessage {
-                op: 4,
+                op: OpCode::Updates,
                 t: Some("UPDATE_FAILURE".to_string()),
-                d: Some(json!({"message": "System update failed"})),
+                d: Some(json!(UpdateMessage::new("System update failed"))),
             };
-            tx.send(fail_notification).expect("Failed to send notification");
+            tx.send(fail_notification);
         }
     }
 
@@ -1,104 +1,149 @@
 use std::fs;
-use std::fs::File;
-use std::io::Write;
 use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 
-use evdev::{Device, EventType};
-use futures_util::StreamExt;
+use evdev::{Device, EventStream, EventType};
 use log::{debug, error};
 use serde_json::json;
 use tokio::time::interval;
 
+use crate::common::event_bus::EventBus;
+use crate::config::SharedConfig;
 use crate::common::utils;
-use crate::models::websocket::WebSocketMessage;
+use crate::hardware::traits::{Backlight, TouchInput};
+use crate::models::events::DisplayStatus;
+use crate::models::websocket::{OpCode, WebSocketMessage};
 
-#[tokio::main]
-pub async fn display_handler_sleep(tx: tokio::sync::broadcast::Sender<WebSocketMessage>, last_event_time: Arc<Mutex<Instant>>) -> Result<(), String> {
-    if !utils::is_raspberry_pi_4b() {
-        return Err("It is only compatible with Raspberry Pi 4 Model B".to_string());
-    }
-    // Open the bl_power file for controlling the display power
-    let mut bl_power_file = File::create("/sys/class/backlight/10-0045/bl_power").unwrap();
-
-    // Create a new Device from the file
-    let device_path = "/dev/input/by-path/platform-fe205000.i2c-event";
-
-    while !std::path::Path::new(device_path).exists() {
-        debug!("Waiting for {} to become available...", device_path);
-        tokio::time::sleep(Duration::from_secs(1)).await;
-    }
-    
-    let device = Device::open(device_path).unwrap();
-
-    // Print device information
-    debug!("Device: {}", device.name().unwrap_or("Unknown device"));
+/// Backlight controlled through the sysfs `bl_power` file, where `0` means on.
+pub struct SysfsBacklight {
+    path: String,
+}
 
-    // Track the last time an event occurred
-    let mut last_event_time2 = last_event_time.lock().unwrap();
+/// Touch panel read through evdev. The device is opened on first use, since it
+/// may only appear some time after boot.
+pub struct EvdevTouchInput {
+    path: String,
+    events: Option<EventStream>,
+}
 
-    let mut events = device.into_event_stream().unwrap();
+#[tokio::main]
+pub async fn display_handler_sleep<T: TouchInput>(tx: EventBus, mut touch_input: T, backlight: Arc<dyn Backlight>, last_event_time: Arc<Mutex<Instant>>, config: SharedConfig) -> Result<(), String> {
     let mut timer = interval(Duration::from_secs(10));
 
     loop {
         tokio::select! {
-            Some(msg) = events.next() => {
-                if let Ok(event) = msg {
-                    if event.event_type() == EventType::ABSOLUTE {
-                        if get_display_power().contains("1") {
-                            set_display_power(&mut bl_power_file, true);
-
-                            let notification = WebSocketMessage {
-                                t: Some("DISPLAY_STATUS".to_string()),
-                                op: 0,
-                                d: Some(json!({"status": "on"})),
-                            };
-
-                            tx.send(notification).unwrap();
-                        }
-
-                        *last_event_time2 = Instant::now();
-                    }
+            Some(()) = touch_input.next_touch() => {
+                if !backlight.is_on() {
+                    backlight.set_power(true);
+
+                    let notification = WebSocketMessage {
+                        t: Some("DISPLAY_STATUS".to_string()),
+                        op: OpCode::System,
+                        d: Some(json!(DisplayStatus::new(true))),
+                    };
+
+                    tx.send(notification);
                 }
+
+                *last_event_time.lock().unwrap() = Instant::now();
             }
 
-            // Every second
+            // Every ten seconds
             _ = timer.tick() => {
-                let elapsed_time = Instant::now() - *last_event_time2;
-                if elapsed_time >= Duration::from_secs(300) && get_display_power().contains("0") {
+                let elapsed_time = last_event_time.lock().unwrap().elapsed();
+                let timeout = Duration::from_secs(config.borrow().display.timeout);
+                if elapsed_time >= timeout && backlight.is_on() {
                     let notification = WebSocketMessage {
                         t: Some("DISPLAY_STATUS".to_string()),
-                        op: 0,
-                        d: Some(json!({"status": "off"})),
+                        op: OpCode::System,
+                        d: Some(json!(DisplayStatus::new(false))),
                     };
 
-                    tx.send(notification).unwrap();
-                    set_display_power(&mut bl_power_file, false);
+                    tx.send(notification);
+                    backlight.set_power(false);
                 }
             }
         }
     }
 }
 
+impl SysfsBacklight {
+    pub fn new(path: &str) -> Self {
+        SysfsBacklight { path: path.to_string() }
+    }
+}
+
+impl Backlight for SysfsBacklight {
+    fn set_power(&self, on: bool) {
+        let power_value = if on { "0" } else { "1" };
+        if let Err(err) = fs::write(&self.path, power_value) {
+            error!("Error setting display power: {}", err);
+        }
+    }
 
-pub fn set_display_power(bl_power_file: &mut File, activate: bool) {
-    let power_value = if activate { "0" } else { "1" };
-    if let Err(err) = bl_power_file.write_all(power_value.as_bytes()) {
-        eprintln!("Error setting display power: {}", err);
+    fn is_on(&self) -> bool {
+        match fs::read_to_string(&self.path) {
+            Err(err) => {
+                error!("Error reading bl_power file: {}", err);
+                true
+            }
+            Ok(content) => !content.trim().contains('1'),
+        }
     }
 }
 
+impl EvdevTouchInput {
+    pub fn open(path: &str) -> Result<Self, String> {
+        if !utils::is_raspberry_pi_4b() {
+            return Err("It is only compatible with Raspberry Pi 4 Model B".to_string());
+        }
+
+        Ok(EvdevTouchInput { path: path.to_string(), events: None })
+    }
+
+    async fn event_stream(&mut self) -> Option<&mut EventStream> {
+        if self.events.is_none() {
+            while !std::path::Path::new(&self.path).exists() {
+                debug!("Waiting for {} to become available...", self.path);
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+
+            let device = match Device::open(&self.path) {
+                Ok(device) => device,
+                Err(e) => {
+                    error!("Failed to open touch device {}: {}", self.path, e);
+                    return None;
+                }
+            };
 
-pub fn get_display_power() -> String {
-    let read = fs::read_to_string("/sys/class/backlight/10-0045/bl_power");
+            debug!("Device: {}", device.name().unwrap_or("Unknown device"));
 
-    return match read {
-        Err(err) => {
-            error!("Error reading bl_power file: {}", err);
-            return "0".to_string()
+            match device.into_event_stream() {
+                Ok(events) => self.events = Some(events),
+                Err(e) => {
+                    error!("Failed to read touch device {}: {}", self.path, e);
+                    return None;
+                }
+            }
         }
-        Ok(content) => {
-            content.trim().to_string()
+
+        self.events.as_mut()
+    }
+}
+
+impl TouchInput for EvdevTouchInput {
+    async fn next_touch(&mut self) -> Option<()> {
+        let events = self.event_stream().await?;
+
+        loop {
+            match events.next_event().await {
+                Ok(event) if event.event_type() == EventType::ABSOLUTE => return Some(()),
+                Ok(_) => {}
+                Err(e) => {
+                    error!("Error reading touch event: {}", e);
+                    return None;
+                }
+            }
         }
     }
-}
\ No newline at end of file
+}
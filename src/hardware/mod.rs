@@ -1,2 +1,4 @@
 pub mod rfid;
-pub mod display;
\ No newline at end of file
+pub mod display;
+pub mod simulated;
+pub mod traits;
\ No newline at end of file
@@ -1,4 +1,3 @@
-use std::fs::File;
 use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 
@@ -7,97 +6,124 @@ use embedded_hal_bus::spi::ExclusiveDevice;
 use linux_embedded_hal::{Delay, SpidevBus, SysfsPin};
 use linux_embedded_hal::spidev::{SpidevOptions, SpiModeFlags};
 use linux_embedded_hal::sysfs_gpio::Direction;
-use mfrc522::comm::blocking::spi::SpiInterface;
-use mfrc522::Mfrc522;
+use mfrc522::comm::blocking::spi::{DummyDelay, SpiInterface};
+use mfrc522::{Initialized, Mfrc522};
 use serde_json::json;
-use tokio::sync::broadcast::Sender;
-use tokio::sync::oneshot;
+use tokio::sync::{mpsc, oneshot};
 
+use crate::common::event_bus::EventBus;
 use crate::common::db::DatabasePool;
 use crate::common::utils;
-use crate::hardware::display::{get_display_power, set_display_power};
+use crate::hardware::traits::{Backlight, RfidReader};
+use crate::models::events::{DisplayStatus, RfidDetected};
 use crate::models::user_actions::UserAction;
-use crate::models::websocket::WebSocketMessage;
+use crate::models::websocket::{OpCode, WebSocketMessage};
 
-#[tokio::main]
-pub async fn control_rfid(tx: Sender<WebSocketMessage>, mut shutdown_rx: oneshot::Receiver<()>, last_event_time: Arc<Mutex<Instant>>, db_pool: DatabasePool) -> Result<(), String> {
-    if !utils::is_raspberry_pi_4b() {
-        return Err("It is only compatible with Raspberry Pi 4 Model B".to_string());
-    }
-    let mut conn = db_pool.get().expect("Failed to connect to the database in rfid controller");
+type Mfrc522Device = Mfrc522<SpiInterface<ExclusiveDevice<SpidevBus, SysfsPin, Delay>, DummyDelay>, Initialized>;
+
+/// MFRC522 reader connected over SPI.
+pub struct Mfrc522Reader {
+    mfrc522: Mfrc522Device,
+}
+
+impl Mfrc522Reader {
+    pub fn open(spi_device: &str, reset_pin: u64) -> Result<Self, String> {
+        if !utils::is_raspberry_pi_4b() {
+            return Err("It is only compatible with Raspberry Pi 4 Model B".to_string());
+        }
 
-    let mut bl_power_file = File::create("/sys/class/backlight/10-0045/bl_power").unwrap();
+        let mut delay = Delay;
 
-    let mut delay = Delay;
-    
-    let mut spi = SpidevBus::open("/dev/spidev0.0").unwrap();
-    let options = SpidevOptions::new()
-        .max_speed_hz(1_000_000)
-        .mode(SpiModeFlags::SPI_MODE_0 | SpiModeFlags::SPI_NO_CS)
-        .build();
-    spi.configure(&options).unwrap();
+        let mut spi = SpidevBus::open(spi_device).map_err(|e| e.to_string())?;
+        let options = SpidevOptions::new()
+            .max_speed_hz(1_000_000)
+            .mode(SpiModeFlags::SPI_MODE_0 | SpiModeFlags::SPI_NO_CS)
+            .build();
+        spi.configure(&options).map_err(|e| e.to_string())?;
 
-    let pin = SysfsPin::new(22);
-    pin.export().unwrap();
-    while !pin.is_exported() {}
-    delay.delay_ms(500u32);
-    
-    let pin = pin.into_output_pin(embedded_hal::digital::PinState::High).unwrap();
-    
-    pin.set_direction(Direction::Out).unwrap();
-    pin.set_value(1).unwrap();
+        let pin = SysfsPin::new(reset_pin);
+        pin.export().map_err(|e| e.to_string())?;
+        while !pin.is_exported() {}
+        delay.delay_ms(500u32);
 
-    let spi = ExclusiveDevice::new(spi, pin, Delay);
-    let itf = SpiInterface::new(spi);
-    let mut mfrc522 = Mfrc522::new(itf).init().unwrap();
+        let pin = pin.into_output_pin(embedded_hal::digital::PinState::High).map_err(|e| e.to_string())?;
 
-    let vers = mfrc522.version().unwrap();
+        pin.set_direction(Direction::Out).map_err(|e| e.to_string())?;
+        pin.set_value(1).map_err(|e| e.to_string())?;
 
-    log::debug!("VERSION: 0x{:x}", vers);
+        let spi = ExclusiveDevice::new(spi, pin, Delay);
+        let itf = SpiInterface::new(spi);
+        let mut mfrc522 = Mfrc522::new(itf).init().map_err(|e| format!("{:?}", e))?;
+
+        let vers = mfrc522.version().map_err(|e| format!("{:?}", e))?;
+
+        log::debug!("VERSION: 0x{:x}", vers);
+
+        Ok(Mfrc522Reader { mfrc522 })
+    }
+}
+
+impl RfidReader for Mfrc522Reader {
+    fn poll_uid(&mut self) -> Option<String> {
+        let atqa = self.mfrc522.reqa().ok()?;
+        let uid = self.mfrc522.select(&atqa).ok()?;
+
+        Some(format!("{:?}", uid.as_bytes()))
+    }
+}
+
+#[tokio::main]
+pub async fn control_rfid<R: RfidReader>(mut reader: R, backlight: Arc<dyn Backlight>, tx: EventBus, tx_actions: mpsc::Sender<UserAction>, mut shutdown_rx: oneshot::Receiver<()>, last_event_time: Arc<Mutex<Instant>>, db_pool: DatabasePool) -> Result<(), String> {
+    let mut conn = db_pool.get().expect("Failed to connect to the database in rfid controller");
 
     let mut last_sent = Instant::now();
     let mut last_uid = None;
 
     loop {
-        if let Ok(atqa) = mfrc522.reqa() {
-            if let Ok(uid) = mfrc522.select(&atqa) {
-                let uid_str = format!("{:?}", uid.as_bytes());
+        if let Some(uid_str) = reader.poll_uid() {
+            // Check if the UID is different from the last sent or if 5 seconds have passed
+            if last_uid.as_ref() != Some(&uid_str) || last_sent.elapsed() >= Duration::from_secs(5) {
+                let action_result = UserAction::get_by_rfid_id(&uid_str, &mut conn);
 
-                // Check if the UID is different from the last sent or if 5 seconds have passed
-                if last_uid.as_ref() != Some(&uid_str) || last_sent.elapsed() >= Duration::from_secs(5) {
-                    let action_result = UserAction::get_by_rfid_id(&uid_str, &mut conn);
+                let action = action_result.ok().flatten();
 
-                    let response = match action_result {
-                        Ok(Some(action)) => json!(action),
-                        _ => json!({ "rfid_uid": uid_str }), // Default response if action is not found
-                    };
+                let response = match &action {
+                    Some(action) => RfidDetected::Action(action.clone()),
+                    None => RfidDetected::Unknown { rfid_uid: uid_str.clone() }, // Default response if action is not found
+                };
 
-                    let rfid_notification = WebSocketMessage {
-                        t: Some("RFID_DETECT".to_string()),
-                        op: 1,
-                        d: Some(response),
-                    };
+                let rfid_notification = WebSocketMessage {
+                    t: Some("RFID_DETECT".to_string()),
+                    op: OpCode::Rfid,
+                    d: Some(json!(response)),
+                };
 
-                    tx.send(rfid_notification).unwrap();
+                tx.send(rfid_notification);
 
-                    if get_display_power().contains("1") {
-                        set_display_power(&mut bl_power_file, true);
+                if !backlight.is_on() {
+                    backlight.set_power(true);
 
-                        let notification = WebSocketMessage {
-                            t: Some("DISPLAY_STATUS".to_string()),
-                            op: 0,
-                            d: Some(json!({"status": "on"})),
-                        };
+                    let notification = WebSocketMessage {
+                        t: Some("DISPLAY_STATUS".to_string()),
+                        op: OpCode::System,
+                        d: Some(json!(DisplayStatus::new(true))),
+                    };
 
-                        tx.send(notification).unwrap();
+                    tx.send(notification);
 
-                        let mut guard = last_event_time.lock().unwrap();
-                        *guard = Instant::now();
-                    }
+                    let mut guard = last_event_time.lock().unwrap();
+                    *guard = Instant::now();
+                }
 
-                    last_uid = Some(uid_str);
-                    last_sent = Instant::now();
+                // Queue after waking the display, so actions controlling it are not overridden
+                if let Some(action) = action {
+                    if let Err(e) = tx_actions.try_send(action) {
+                        log::error!("Failed to queue action for {}: {}", uid_str, e);
+                    }
                 }
+
+                last_uid = Some(uid_str);
+                last_sent = Instant::now();
             }
         }
 
@@ -109,4 +135,4 @@ pub async fn control_rfid(tx: Sender<WebSocketMessage>, mut shutdown_rx: oneshot
     }
 
     Ok(())
-}
\ No newline at end of file
+}
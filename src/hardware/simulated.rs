@@ -0,0 +1,76 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use log::debug;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+
+use crate::hardware::traits::{Backlight, RfidReader, TouchInput};
+
+/// Handle used by the debug endpoints to inject RFID scans and touch events
+/// into the simulated hardware.
+#[derive(Clone)]
+pub struct Simulator {
+    rfid_tx: UnboundedSender<String>,
+    touch_tx: UnboundedSender<()>,
+}
+
+pub struct SimulatedRfidReader {
+    rfid_rx: UnboundedReceiver<String>,
+}
+
+pub struct SimulatedTouchInput {
+    touch_rx: UnboundedReceiver<()>,
+}
+
+pub struct SimulatedBacklight {
+    on: AtomicBool,
+}
+
+pub fn create() -> (Simulator, SimulatedRfidReader, SimulatedTouchInput) {
+    let (rfid_tx, rfid_rx) = unbounded_channel();
+    let (touch_tx, touch_rx) = unbounded_channel();
+
+    (
+        Simulator { rfid_tx, touch_tx },
+        SimulatedRfidReader { rfid_rx },
+        SimulatedTouchInput { touch_rx },
+    )
+}
+
+impl Simulator {
+    pub fn scan_rfid(&self, uid: String) -> Result<(), String> {
+        self.rfid_tx.send(uid).map_err(|_| "Simulated RFID reader is not running".to_string())
+    }
+
+    pub fn touch(&self) -> Result<(), String> {
+        self.touch_tx.send(()).map_err(|_| "Simulated touch input is not running".to_string())
+    }
+}
+
+impl RfidReader for SimulatedRfidReader {
+    fn poll_uid(&mut self) -> Option<String> {
+        self.rfid_rx.try_recv().ok()
+    }
+}
+
+impl TouchInput for SimulatedTouchInput {
+    async fn next_touch(&mut self) -> Option<()> {
+        self.touch_rx.recv().await
+    }
+}
+
+impl Default for SimulatedBacklight {
+    fn default() -> Self {
+        SimulatedBacklight { on: AtomicBool::new(true) }
+    }
+}
+
+impl Backlight for SimulatedBacklight {
+    fn set_power(&self, on: bool) {
+        debug!("Simulated display power: {}", if on { "on" } else { "off" });
+        self.on.store(on, Ordering::SeqCst);
+    }
+
+    fn is_on(&self) -> bool {
+        self.on.load(Ordering::SeqCst)
+    }
+}
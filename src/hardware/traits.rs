@@ -0,0 +1,18 @@
+/// A reader that detects RFID tags in its field.
+pub trait RfidReader {
+    /// Polls the reader once and returns the UID of the tag in the field, if any.
+    fn poll_uid(&mut self) -> Option<String>;
+}
+
+/// The display backlight. Shared between the RFID, display and WebSocket handlers.
+pub trait Backlight: Send + Sync {
+    fn set_power(&self, on: bool);
+
+    fn is_on(&self) -> bool;
+}
+
+/// A source of touch events from the display panel.
+pub trait TouchInput {
+    /// Waits for the next touch. Returns `None` once the input source is gone.
+    async fn next_touch(&mut self) -> Option<()>;
+}
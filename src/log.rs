@@ -5,18 +5,21 @@ use simplelog::{ColorChoice, CombinedLogger, Config, TerminalMode, TermLogger, W
 
 use crate::config::LogConf;
 
+/// The loggers let everything through, the level is set globally so it can be changed on a reload
 pub fn setup(conf: &LogConf) {
     CombinedLogger::init(vec![
         TermLogger::new(
-            LevelFilter::Debug,
+            LevelFilter::Trace,
             Config::default(),
             TerminalMode::Mixed,
             ColorChoice::Auto,
         ),
         WriteLogger::new(
-            LevelFilter::Debug,
+            LevelFilter::Trace,
             Config::default(),
             File::create(&conf.file).unwrap(),
         ),
     ]).unwrap();
+
+    ::log::set_max_level(conf.level_filter());
 }
\ No newline at end of file
@@ -1,6 +1,8 @@
 extern crate dbus;
 extern crate diesel;
 
+use std::process::ExitCode;
+
 use config::Config;
 
 mod app;
@@ -15,11 +17,27 @@ mod network;
 mod schema;
 mod api;
 
-fn main() {
-    let conf = Config::from_any().unwrap();
+fn main() -> ExitCode {
+    let check_only = std::env::args().skip(1).any(|arg| arg == "--check-config");
+    let location = Config::location();
+
+    // Logging is configured by the config, so problems with it can only go to stderr
+    let conf = match Config::from_file_path(&location) {
+        Ok(conf) => conf,
+        Err(e) => {
+            eprintln!("Could not load {}: {}", location, e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    if check_only {
+        println!("{} is valid", location);
+        return ExitCode::SUCCESS;
+    }
 
     // Setup simplelog
     log::setup(&conf.log);
 
-    app::launch(&conf);
+    app::launch(&conf, &location);
+    ExitCode::SUCCESS
 }
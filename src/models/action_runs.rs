@@ -0,0 +1,153 @@
+use chrono::NaiveDateTime;
+use diesel::{RunQueryDsl, SqliteConnection};
+use diesel::prelude::*;
+use diesel::r2d2::{ConnectionManager, PooledConnection};
+use serde_derive::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::schema::action_runs::dsl::*;
+
+const MAX_RESPONSE_LENGTH: usize = 2048;
+
+#[derive(Queryable, Identifiable, Serialize, Deserialize, Debug, ToSchema)]
+#[diesel(table_name = crate::schema::action_runs)]
+pub struct ActionRun {
+    pub id: i32,
+    pub user_id: i32,
+    pub action_id: Option<i32>,
+    pub request_id: Option<i32>,
+    pub trigger_source: String,
+    pub trigger_detail: Option<String>,
+    pub started_on: NaiveDateTime,
+    pub finished_on: NaiveDateTime,
+    pub outcome: String,
+    pub status_code: Option<i32>,
+    pub response: Option<String>,
+    pub error: Option<String>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::action_runs)]
+pub struct NewActionRun {
+    pub user_id: i32,
+    pub action_id: Option<i32>,
+    pub request_id: Option<i32>,
+    pub trigger_source: String,
+    pub trigger_detail: Option<String>,
+    pub started_on: NaiveDateTime,
+    pub finished_on: NaiveDateTime,
+    pub outcome: String,
+    pub status_code: Option<i32>,
+    pub response: Option<String>,
+    pub error: Option<String>,
+}
+
+#[derive(Default)]
+pub struct ActionRunFilter {
+    pub source: Option<String>,
+    pub outcome: Option<String>,
+    pub action_id: Option<i32>,
+    pub request_id: Option<i32>,
+    pub since: Option<NaiveDateTime>,
+    pub until: Option<NaiveDateTime>,
+}
+
+/// What caused an action or request to run. Nothing runs actions on a schedule yet, a `Schedule`
+/// trigger comes with the scheduler.
+#[derive(Clone, Debug)]
+pub enum Trigger {
+    Rfid(String),
+    Api,
+    WebSocket,
+    Action(i32),
+}
+
+impl Trigger {
+    /// Every value of `trigger_source`
+    pub const SOURCES: &'static [&'static str] = &["rfid", "api", "websocket", "action"];
+
+    pub fn source(&self) -> &'static str {
+        match self {
+            Trigger::Rfid(_) => "rfid",
+            Trigger::Api => "api",
+            Trigger::WebSocket => "websocket",
+            Trigger::Action(_) => "action",
+        }
+    }
+
+    pub fn detail(&self) -> Option<String> {
+        match self {
+            Trigger::Rfid(uid) => Some(uid.clone()),
+            Trigger::Action(aid) => Some(aid.to_string()),
+            _ => None,
+        }
+    }
+}
+
+impl NewActionRun {
+    pub fn new(uid: i32, trigger: &Trigger, started: NaiveDateTime, result: Result<Option<String>, String>) -> Self {
+        let (run_outcome, run_response, run_error) = match result {
+            Ok(run_response) => ("succeeded", run_response.map(|r| truncate(&r)), None),
+            Err(run_error) => ("failed", None, Some(truncate(&run_error))),
+        };
+
+        NewActionRun {
+            user_id: uid,
+            action_id: None,
+            request_id: None,
+            trigger_source: trigger.source().to_string(),
+            trigger_detail: trigger.detail(),
+            started_on: started,
+            finished_on: chrono::Utc::now().naive_utc(),
+            outcome: run_outcome.to_string(),
+            status_code: None,
+            response: run_response,
+            error: run_error,
+        }
+    }
+}
+
+impl ActionRun {
+    pub fn create(new_run: NewActionRun, conn: &mut PooledConnection<ConnectionManager<SqliteConnection>>) -> Result<usize, diesel::result::Error> {
+        diesel::insert_into(action_runs)
+            .values(&new_run)
+            .execute(conn)
+    }
+
+    // Method to get the newest runs of a user, optionally filtered
+    pub fn get_all_by_user_id(uid: i32, filter: &ActionRunFilter, limit: i64, offset: i64, conn: &mut PooledConnection<ConnectionManager<SqliteConnection>>) -> Result<Vec<ActionRun>, diesel::result::Error> {
+        let mut query = action_runs.filter(user_id.eq(uid)).into_boxed();
+
+        if let Some(ref source) = filter.source {
+            query = query.filter(trigger_source.eq(source));
+        }
+        if let Some(ref run_outcome) = filter.outcome {
+            query = query.filter(outcome.eq(run_outcome));
+        }
+        if let Some(aid) = filter.action_id {
+            query = query.filter(action_id.eq(aid));
+        }
+        if let Some(rid) = filter.request_id {
+            query = query.filter(request_id.eq(rid));
+        }
+        if let Some(since) = filter.since {
+            query = query.filter(started_on.ge(since));
+        }
+        if let Some(until) = filter.until {
+            query = query.filter(started_on.le(until));
+        }
+
+        query
+            .order((started_on.desc(), id.desc()))
+            .limit(limit)
+            .offset(offset)
+            .load::<ActionRun>(conn)
+    }
+}
+
+fn truncate(text: &str) -> String {
+    match text.char_indices().nth(MAX_RESPONSE_LENGTH) {
+        Some((index, _)) => format!("{}...", &text[..index]),
+        None => text.to_string(),
+    }
+}
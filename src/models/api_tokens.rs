@@ -0,0 +1,62 @@
+use chrono::NaiveDateTime;
+use diesel::{RunQueryDsl, SqliteConnection};
+use diesel::prelude::*;
+use diesel::r2d2::{ConnectionManager, PooledConnection};
+use serde_derive::Serialize;
+use utoipa::ToSchema;
+
+use crate::schema::api_tokens::dsl::*;
+
+#[derive(Queryable, Identifiable, Serialize, Debug, ToSchema)]
+#[diesel(table_name = crate::schema::api_tokens)]
+pub struct ApiToken {
+    pub id: i32,
+    pub name: String,
+    #[serde(skip_serializing)]
+    pub token_hash: String,
+    pub created_on: NaiveDateTime,
+    pub last_used_on: Option<NaiveDateTime>,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = crate::schema::api_tokens)]
+pub struct NewApiToken {
+    pub name: String,
+    pub token_hash: String,
+}
+
+impl ApiToken {
+    pub fn all(conn: &mut PooledConnection<ConnectionManager<SqliteConnection>>) -> Result<Vec<ApiToken>, diesel::result::Error> {
+        api_tokens.order(id.asc()).load::<ApiToken>(conn)
+    }
+
+    pub fn get_by_hash(hash: &str, conn: &mut PooledConnection<ConnectionManager<SqliteConnection>>) -> Result<ApiToken, diesel::result::Error> {
+        api_tokens
+            .filter(token_hash.eq(hash))
+            .first::<ApiToken>(conn)
+    }
+
+    pub fn create(new_token: NewApiToken, conn: &mut PooledConnection<ConnectionManager<SqliteConnection>>) -> Result<ApiToken, diesel::result::Error> {
+        diesel::insert_into(api_tokens)
+            .values(&new_token)
+            .execute(conn)?;
+
+        Self::get_by_hash(&new_token.token_hash, conn)
+    }
+
+    pub fn mark_used(token_id: i32, conn: &mut PooledConnection<ConnectionManager<SqliteConnection>>) -> Result<usize, diesel::result::Error> {
+        diesel::update(api_tokens.filter(id.eq(token_id)))
+            .set(last_used_on.eq(chrono::Utc::now().naive_utc()))
+            .execute(conn)
+    }
+
+    pub fn delete(token_id: i32, conn: &mut PooledConnection<ConnectionManager<SqliteConnection>>) -> Result<usize, diesel::result::Error> {
+        diesel::delete(api_tokens.filter(id.eq(token_id)))
+            .execute(conn)
+    }
+
+    pub fn delete_all(conn: &mut PooledConnection<ConnectionManager<SqliteConnection>>) -> Result<usize, diesel::result::Error> {
+        diesel::delete(api_tokens)
+            .execute(conn)
+    }
+}
@@ -1,30 +1,129 @@
-use diesel::{RunQueryDsl, SqliteConnection};
+use std::collections::BTreeMap;
+
+use diesel::{AsExpression, FromSqlRow, RunQueryDsl, SqliteConnection};
+use diesel::backend::Backend;
+use diesel::deserialize::{self, FromSql};
 use diesel::prelude::*;
 use diesel::r2d2::{ConnectionManager, PooledConnection};
+use diesel::serialize::{self, IsNull, Output, ToSql};
+use diesel::sql_types::Text;
+use diesel::sqlite::Sqlite;
 use serde_derive::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use validator::Validate;
 
+use crate::common::secrets::{SecretBox, SecretError};
+use crate::models::listing::Listing;
+use crate::models::validation::constant_name;
 use crate::schema::constants::dsl::*;
 
-#[derive(Queryable, Identifiable, Serialize, Deserialize, Debug)]
+/// Shown instead of the value of secret constants
+pub const SECRET_MASK: &str = "********";
+
+#[derive(Queryable, Identifiable, Serialize, Deserialize, Debug, ToSchema)]
 #[diesel(table_name = crate::schema::constants)]
 pub struct Constant {
     pub id: i32,
     pub name: String,
-    pub user_id: i32,
+    /// None for global constants, shared by every user
+    pub user_id: Option<i32>,
+    /// Encrypted for secrets, see `common::secrets`
     pub value: String,
+    pub kind: ConstantKind,
 }
 
-#[derive(Insertable, Serialize, Deserialize)]
+#[derive(Insertable, Serialize, Deserialize, ToSchema, Validate)]
 #[diesel(table_name = crate::schema::constants)]
 pub struct NewConstant {
+    #[validate(length(max = 128), custom(function = "constant_name"))]
     pub name: String,
     pub user_id: i32,
+    #[validate(length(max = 65536))]
+    pub value: String,
+    #[serde(default)]
+    pub kind: ConstantKind,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Validate)]
+pub struct NewGlobalConstant {
+    #[validate(length(max = 128), custom(function = "constant_name"))]
+    pub name: String,
+    #[validate(length(max = 65536))]
     pub value: String,
+    #[serde(default)]
+    pub kind: ConstantKind,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, ToSchema, Validate)]
 pub struct UpdateConstant {
+    #[validate(length(max = 65536))]
     pub value: String,
+    /// Keeps the current kind when left out
+    pub kind: Option<ConstantKind>,
+}
+
+#[derive(AsExpression, FromSqlRow, Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, ToSchema)]
+#[diesel(sql_type = Text)]
+#[serde(rename_all = "snake_case")]
+pub enum ConstantKind {
+    #[default]
+    String,
+    Number,
+    Bool,
+    Json,
+    Secret,
+}
+
+impl ConstantKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ConstantKind::String => "string",
+            ConstantKind::Number => "number",
+            ConstantKind::Bool => "bool",
+            ConstantKind::Json => "json",
+            ConstantKind::Secret => "secret",
+        }
+    }
+
+    /// Checks that a plain text value fits the kind
+    pub fn validate(&self, constant_value: &str) -> Result<(), String> {
+        match self {
+            ConstantKind::String => Ok(()),
+            ConstantKind::Number => match constant_value.trim().parse::<f64>() {
+                Ok(number) if number.is_finite() => Ok(()),
+                _ => Err(format!("{} is not a number", constant_value)),
+            },
+            ConstantKind::Bool => match constant_value {
+                "true" | "false" => Ok(()),
+                _ => Err("Value must be true or false".to_string()),
+            },
+            ConstantKind::Json => serde_json::from_str::<serde_json::Value>(constant_value)
+                .map(|_| ())
+                .map_err(|e| format!("Invalid JSON: {}", e)),
+            ConstantKind::Secret if constant_value.is_empty() => Err("Secret cannot be empty".to_string()),
+            ConstantKind::Secret => Ok(()),
+        }
+    }
+}
+
+impl ToSql<Text, Sqlite> for ConstantKind {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Sqlite>) -> serialize::Result {
+        out.set_value(self.as_str());
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<Text, Sqlite> for ConstantKind {
+    fn from_sql(bytes: <Sqlite as Backend>::RawValue<'_>) -> deserialize::Result<Self> {
+        match <String as FromSql<Text, Sqlite>>::from_sql(bytes)?.as_str() {
+            "string" => Ok(ConstantKind::String),
+            "number" => Ok(ConstantKind::Number),
+            "bool" => Ok(ConstantKind::Bool),
+            "json" => Ok(ConstantKind::Json),
+            "secret" => Ok(ConstantKind::Secret),
+            other => Err(format!("Unknown constant kind {}", other).into()),
+        }
+    }
 }
 
 impl Constant {
@@ -33,10 +132,75 @@ impl Constant {
         constants.filter(user_id.eq(uid)).load::<Constant>(conn)
     }
 
+    /// A page of a user's own constants and the number of constants matching the search
+    pub fn list_by_user_id(uid: i32, listing: &Listing, conn: &mut PooledConnection<ConnectionManager<SqliteConnection>>) -> Result<(Vec<Constant>, i64), diesel::result::Error> {
+        let pattern = listing.pattern();
+        let filtered = || {
+            let mut query = constants.filter(user_id.eq(uid)).into_boxed();
+            if let Some(ref pattern) = pattern {
+                query = query.filter(name.like(pattern).escape('\\'));
+            }
+            query
+        };
+
+        let total = filtered().count().get_result(conn)?;
+        let query = match (listing.sort.as_str(), listing.descending) {
+            ("name", false) => filtered().order(name.asc()),
+            ("name", true) => filtered().order(name.desc()),
+            (_, false) => filtered().order(id.asc()),
+            (_, true) => filtered().order(id.desc()),
+        };
+
+        let page = query.then_order_by(id.asc()).limit(listing.limit).offset(listing.offset).load::<Constant>(conn)?;
+        Ok((page, total))
+    }
+
     pub fn get_all_by_user_id_and_name(uid: i32, constant_name: &String, conn: &mut PooledConnection<ConnectionManager<SqliteConnection>>) -> Result<Vec<Constant>, diesel::result::Error> {
         constants.filter(user_id.eq(uid).and(name.eq(constant_name))).load::<Constant>(conn)
     }
 
+    pub fn get_by_user_id_and_name(uid: i32, constant_name: &str, conn: &mut PooledConnection<ConnectionManager<SqliteConnection>>) -> Result<Constant, diesel::result::Error> {
+        constants.filter(user_id.eq(uid).and(name.eq(constant_name))).first::<Constant>(conn)
+    }
+
+    pub fn get_all_global(conn: &mut PooledConnection<ConnectionManager<SqliteConnection>>) -> Result<Vec<Constant>, diesel::result::Error> {
+        constants.filter(user_id.is_null()).load::<Constant>(conn)
+    }
+
+    pub fn get_global_by_name(constant_name: &str, conn: &mut PooledConnection<ConnectionManager<SqliteConnection>>) -> Result<Constant, diesel::result::Error> {
+        constants.filter(user_id.is_null().and(name.eq(constant_name))).first::<Constant>(conn)
+    }
+
+    /// The constants a user sees: the global ones, with their own constants taking
+    /// precedence over global ones with the same name. Sorted by name.
+    pub fn get_resolved(uid: i32, conn: &mut PooledConnection<ConnectionManager<SqliteConnection>>) -> Result<Vec<Constant>, diesel::result::Error> {
+        let mut resolved = BTreeMap::new();
+
+        for constant in constants.filter(user_id.eq(uid).or(user_id.is_null())).load::<Constant>(conn)? {
+            if constant.user_id.is_some() || !resolved.contains_key(&constant.name) {
+                resolved.insert(constant.name.clone(), constant);
+            }
+        }
+
+        Ok(resolved.into_values().collect())
+    }
+
+    /// The value as entered, decrypting secrets
+    pub fn plain_value(&self, secrets: &SecretBox) -> Result<String, SecretError> {
+        match self.kind {
+            ConstantKind::Secret => secrets.decrypt(&self.value),
+            _ => Ok(self.value.clone()),
+        }
+    }
+
+    /// Hides the value of secrets, for list responses
+    pub fn masked(mut self) -> Constant {
+        if self.kind == ConstantKind::Secret {
+            self.value = SECRET_MASK.to_string();
+        }
+        self
+    }
+
     // Method to delete a constant by user_id and name
     pub fn delete_by_user_id_and_name(uid: i32, constant_name: &str, conn: &mut PooledConnection<ConnectionManager<SqliteConnection>>) -> Result<usize, diesel::result::Error> {
         diesel::delete(constants.filter(user_id.eq(uid).and(name.eq(constant_name))))
@@ -44,16 +208,33 @@ impl Constant {
     }
 
     // Method to create a new constant with user_id, name, and value
-    pub fn create(uid: i32, constant_name: &str, constant_value: &str, conn: &mut PooledConnection<ConnectionManager<SqliteConnection>>) -> Result<usize, diesel::result::Error> {
+    pub fn create(uid: i32, constant_name: &str, constant_value: &str, constant_kind: ConstantKind, conn: &mut PooledConnection<ConnectionManager<SqliteConnection>>) -> Result<usize, diesel::result::Error> {
+        diesel::insert_into(constants)
+            .values((user_id.eq(uid), name.eq(constant_name), value.eq(constant_value), kind.eq(constant_kind)))
+            .execute(conn)
+    }
+
+    pub fn create_global(constant_name: &str, constant_value: &str, constant_kind: ConstantKind, conn: &mut PooledConnection<ConnectionManager<SqliteConnection>>) -> Result<usize, diesel::result::Error> {
         diesel::insert_into(constants)
-            .values((user_id.eq(uid), name.eq(constant_name), value.eq(constant_value)))
+            .values((user_id.eq(None::<i32>), name.eq(constant_name), value.eq(constant_value), kind.eq(constant_kind)))
+            .execute(conn)
+    }
+
+    pub fn delete_global_by_name(constant_name: &str, conn: &mut PooledConnection<ConnectionManager<SqliteConnection>>) -> Result<usize, diesel::result::Error> {
+        diesel::delete(constants.filter(user_id.is_null().and(name.eq(constant_name))))
+            .execute(conn)
+    }
+
+    pub fn update_global_value(constant_name: &str, new_value: &str, new_kind: ConstantKind, conn: &mut PooledConnection<ConnectionManager<SqliteConnection>>) -> Result<usize, diesel::result::Error> {
+        diesel::update(constants.filter(user_id.is_null().and(name.eq(constant_name))))
+            .set((value.eq(new_value), kind.eq(new_kind)))
             .execute(conn)
     }
 
     // Method to update the value of a constant by user_id and name
-    pub fn update_value(uid: i32, constant_name: &str, new_value: &str, conn: &mut PooledConnection<ConnectionManager<SqliteConnection>>) -> Result<usize, diesel::result::Error> {
+    pub fn update_value(uid: i32, constant_name: &str, new_value: &str, new_kind: ConstantKind, conn: &mut PooledConnection<ConnectionManager<SqliteConnection>>) -> Result<usize, diesel::result::Error> {
         diesel::update(constants.filter(user_id.eq(uid).and(name.eq(constant_name))))
-            .set(value.eq(new_value))
+            .set((value.eq(new_value), kind.eq(new_kind)))
             .execute(conn)
     }
 }
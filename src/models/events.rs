@@ -0,0 +1,231 @@
+use serde_derive::Serialize;
+use serde_json::Value;
+use utoipa::openapi::{ArrayBuilder, ObjectBuilder, Ref, RefOr, Schema, SchemaType};
+use utoipa::ToSchema;
+
+use crate::handlers::bluetooth_handler::BluetoothDevice;
+use crate::handlers::request_handler::RequestResult;
+use crate::handlers::session_handler::ActiveSession;
+use crate::models::interface::{NetworkInterface, ScanResult};
+use crate::models::user::User;
+use crate::models::user_actions::UserAction;
+use crate::models::websocket::{Ack, CommandError, Hello, OpCode};
+
+/// A broadcast event type, published as a JSON Schema at `/openapi/events.json`
+pub struct EventType {
+    pub op: OpCode,
+    pub t: &'static str,
+    pub description: &'static str,
+    /// Schema of `d`
+    pub payload: fn() -> RefOr<Schema>,
+}
+
+/// Every event the backend sends. Add new events here, the schema endpoint is built from this list.
+pub const EVENTS: &[EventType] = &[
+    EventType { op: OpCode::System, t: "DISPLAY_STATUS", description: "The display was switched on or off", payload: of::<DisplayStatus> },
+    EventType { op: OpCode::System, t: "NETWORK_STATUS", description: "Wi-Fi connection status", payload: of::<NetworkStatus> },
+    EventType { op: OpCode::System, t: "NETWORK_INTERFACES", description: "Network interfaces and their addresses", payload: list_of::<NetworkInterface> },
+    EventType { op: OpCode::System, t: "SCAN_RESULTS", description: "Networks found by the last Wi-Fi scan", payload: list_of::<ScanResult> },
+    EventType { op: OpCode::System, t: "SESSION_CHANGED", description: "A user logged in or out", payload: of::<SessionChanged> },
+    EventType { op: OpCode::System, t: "DATABASE_RESTORED", description: "All data was replaced by a backup, clients should reload", payload: of::<DatabaseRestored> },
+    EventType { op: OpCode::System, t: "CONFIG_RELOADED", description: "The config file was reloaded", payload: of::<ConfigReloaded> },
+    EventType { op: OpCode::System, t: "PAIRING_REQUESTED", description: "A device wants to pair, the code should be shown on the kiosk", payload: of::<PairingRequested> },
+    EventType { op: OpCode::System, t: "PAIRING_COMPLETED", description: "A device was paired", payload: of::<PairingCompleted> },
+    EventType { op: OpCode::Rfid, t: "RFID_DETECT", description: "A tag was scanned, with its action if it has one", payload: of::<RfidDetected> },
+    EventType { op: OpCode::Bluetooth, t: "DEVICE_INFO", description: "A known device, sent for each device on `DEVICES`", payload: of::<BluetoothDevice> },
+    EventType { op: OpCode::Bluetooth, t: "DEVICE_FOUND", description: "Discovery found a new device", payload: of::<BluetoothDevice> },
+    EventType { op: OpCode::Bluetooth, t: "DEVICE_CONNECTED", description: "A device connected", payload: of::<BluetoothDevice> },
+    EventType { op: OpCode::Bluetooth, t: "DEVICE_DISCONNECTED", description: "A device disconnected", payload: of::<BluetoothDevice> },
+    EventType { op: OpCode::Bluetooth, t: "DEVICE_PAIRED", description: "A device was paired", payload: of::<BluetoothDevice> },
+    EventType { op: OpCode::Bluetooth, t: "DEVICE_UNPAIRED", description: "A device was unpaired", payload: of::<BluetoothDevice> },
+    EventType { op: OpCode::Bluetooth, t: "DEVICE_BONDED", description: "A device was bonded", payload: of::<BluetoothDevice> },
+    EventType { op: OpCode::Bluetooth, t: "DEVICE_UNBONDED", description: "A device was unbonded", payload: of::<BluetoothDevice> },
+    EventType { op: OpCode::Bluetooth, t: "DEVICE_TRUSTED", description: "A device was trusted", payload: of::<BluetoothDevice> },
+    EventType { op: OpCode::Bluetooth, t: "DEVICE_UNTRUSTED", description: "A device is no longer trusted", payload: of::<BluetoothDevice> },
+    EventType { op: OpCode::Bluetooth, t: "DISCOVERY_STARTED", description: "Discovery started, the payload is always `true`", payload: boolean },
+    EventType { op: OpCode::Bluetooth, t: "DISCOVERY_STOPPED", description: "Discovery stopped, the payload is always `false`", payload: boolean },
+    EventType { op: OpCode::Actions, t: "ACTION_STARTED", description: "An RFID action started running", payload: of::<ActionStarted> },
+    EventType { op: OpCode::Actions, t: "ACTION_SUCCEEDED", description: "An RFID action finished", payload: of::<ActionSucceeded> },
+    EventType { op: OpCode::Actions, t: "ACTION_FAILED", description: "An RFID action failed", payload: of::<ActionFailed> },
+    EventType { op: OpCode::Actions, t: "SWITCH_USER", description: "A `switch_user` action is about to log in this user", payload: of::<User> },
+    EventType { op: OpCode::Actions, t: "REQUEST_EXECUTED", description: "A saved request returned a response", payload: of::<RequestExecuted> },
+    EventType { op: OpCode::Actions, t: "REQUEST_FAILED", description: "A saved request could not be executed", payload: of::<RequestFailed> },
+    EventType { op: OpCode::Updates, t: "START_LISTING_UPDATE", description: "Looking for system updates", payload: of::<UpdateMessage> },
+    EventType { op: OpCode::Updates, t: "UPDATE_AVAILABLE", description: "An upgradable package, one event per package", payload: of::<UpdateMessage> },
+    EventType { op: OpCode::Updates, t: "FINISHED_LISTING_UPDATE", description: "All available updates were listed", payload: of::<UpdateMessage> },
+    EventType { op: OpCode::Updates, t: "START_UPDATE_PROCESS", description: "The system update started", payload: of::<UpdateMessage> },
+    EventType { op: OpCode::Updates, t: "UPDATE_SUCCESS", description: "The system update finished", payload: of::<UpdateMessage> },
+    EventType { op: OpCode::Updates, t: "UPDATE_FAILURE", description: "The system update failed", payload: of::<UpdateMessage> },
+    EventType { op: OpCode::Protocol, t: "HELLO", description: "First frame on every connection", payload: of::<Hello> },
+    EventType { op: OpCode::Protocol, t: "READY", description: "Current state, sent after `HELLO`", payload: of::<Ready> },
+    EventType { op: OpCode::Protocol, t: "ACK", description: "A command succeeded, only sent to the client that sent it", payload: of::<Ack> },
+    EventType { op: OpCode::Protocol, t: "ERROR", description: "A command failed, only sent to the client that sent it", payload: of::<CommandError> },
+];
+
+fn of<'s, T: ToSchema<'s>>() -> RefOr<Schema> {
+    Ref::from_schema_name(T::schema().0).into()
+}
+
+fn list_of<'s, T: ToSchema<'s>>() -> RefOr<Schema> {
+    ArrayBuilder::new().items(of::<T>()).into()
+}
+
+fn boolean() -> RefOr<Schema> {
+    ObjectBuilder::new().schema_type(SchemaType::Boolean).into()
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct DisplayStatus {
+    /// `on` or `off`
+    pub status: String,
+}
+
+impl DisplayStatus {
+    pub fn new(on: bool) -> Self {
+        DisplayStatus { status: if on { "on" } else { "off" }.to_string() }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct NetworkStatus {
+    /// Left out while the Wi-Fi is deactivated
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ssid: Option<String>,
+    /// `wpa_state` of wpa_supplicant, or `DEACTIVATED`
+    pub status: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ip_address: Option<String>,
+}
+
+impl NetworkStatus {
+    pub fn deactivated() -> Self {
+        NetworkStatus { ssid: None, status: "DEACTIVATED".to_string(), ip_address: None }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct SessionChanged {
+    /// `null` after a logout
+    pub session: Option<ActiveSession>,
+    /// `login`, `logout`, `display_sleep`, `user_deleted` or `restore`
+    pub reason: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct DatabaseRestored {}
+
+#[derive(Serialize, ToSchema)]
+pub struct ConfigReloaded {
+    /// Changed settings that are in effect now, like `display.timeout`
+    pub applied: Vec<String>,
+    /// Changed settings that only take effect after a restart
+    pub restart_required: Vec<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct PairingRequested {
+    pub pairing_id: String,
+    pub name: String,
+    /// Shown on the kiosk, entered on the pairing device
+    pub code: String,
+    pub expires_in: u64,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct PairingCompleted {
+    pub pairing_id: String,
+    pub name: String,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(untagged)]
+pub enum RfidDetected {
+    Action(UserAction),
+    /// The tag has no action
+    Unknown { rfid_uid: String },
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ActionStarted {
+    pub action_id: i32,
+    pub type_name: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ActionSucceeded {
+    pub action_id: i32,
+    pub type_name: String,
+    /// Depends on the action type
+    #[schema(value_type = Object)]
+    pub result: Value,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ActionFailed {
+    pub action_id: i32,
+    pub type_name: String,
+    pub error: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct RequestExecuted {
+    pub request_id: i32,
+    pub name: String,
+    pub result: RequestResult,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct RequestFailed {
+    pub request_id: i32,
+    pub name: String,
+    pub error: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct UpdateMessage {
+    pub message: String,
+}
+
+impl UpdateMessage {
+    pub fn new(message: impl Into<String>) -> Self {
+        UpdateMessage { message: message.into() }
+    }
+}
+
+/// Snapshot of the state store. Values are `null` until the backend has seen the matching event.
+#[derive(Serialize, ToSchema)]
+pub struct Ready {
+    #[schema(value_type = Option<DisplayStatus>)]
+    pub display: Option<Value>,
+    #[schema(value_type = Option<ActiveSession>)]
+    pub session: Option<Value>,
+    pub network: ReadyNetwork,
+    pub bluetooth: ReadyBluetooth,
+    pub updates: ReadyUpdates,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ReadyNetwork {
+    #[schema(value_type = Option<NetworkStatus>)]
+    pub status: Option<Value>,
+    #[schema(value_type = Option<Vec<NetworkInterface>>)]
+    pub interfaces: Option<Value>,
+    #[schema(value_type = Option<Vec<ScanResult>>)]
+    pub scan_results: Option<Value>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ReadyBluetooth {
+    pub discovering: Option<bool>,
+    #[schema(value_type = Vec<BluetoothDevice>)]
+    pub devices: Vec<Value>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ReadyUpdates {
+    /// `listing`, `listed`, `updating`, `succeeded` or `failed`
+    pub status: Option<&'static str>,
+    /// Messages of the `UPDATE_AVAILABLE` events
+    #[schema(value_type = Vec<String>)]
+    pub available: Vec<Value>,
+}
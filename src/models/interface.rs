@@ -2,9 +2,11 @@ use std::fmt::Debug;
 use serde::{Deserialize, Serialize};
 use std::net::{Ipv4Addr, Ipv6Addr};
 
+use utoipa::ToSchema;
+
 pub type Netmask<T> = Option<T>;
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize, ToSchema)]
 pub struct NetworkInterface {
     pub name: String,
     pub addr: Vec<Addr>,
@@ -12,26 +14,42 @@ pub struct NetworkInterface {
     pub index: u32,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize, ToSchema)]
 pub enum Addr {
     V4(V4IfAddr),
     V6(V6IfAddr),
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize, ToSchema)]
 pub struct V4IfAddr {
+    #[schema(value_type = String)]
     pub ip: Ipv4Addr,
+    #[schema(value_type = Option<String>)]
     pub broadcast: Option<Ipv4Addr>,
+    #[schema(value_type = Option<String>)]
     pub netmask: Netmask<Ipv4Addr>,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize, ToSchema)]
 pub struct V6IfAddr {
+    #[schema(value_type = String)]
     pub ip: Ipv6Addr,
+    #[schema(value_type = Option<String>)]
     pub broadcast: Option<Ipv6Addr>,
+    #[schema(value_type = Option<String>)]
     pub netmask: Netmask<Ipv6Addr>,
 }
 
+/// A network found by a Wi-Fi scan, as listed by `wpa_cli scan_results`
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ScanResult {
+    pub bssid: String,
+    pub frequency: String,
+    pub signal_level: String,
+    pub flags: String,
+    pub ssid: String,
+}
+
 impl NetworkInterface {
     pub fn new_afinet(
         name: &str,
@@ -0,0 +1,19 @@
+/// One page of a list, see `api::listing` for how it is read from the query string
+pub struct Listing {
+    pub limit: i64,
+    pub offset: i64,
+    /// Column to sort by, checked against the columns the endpoint allows
+    pub sort: String,
+    pub descending: bool,
+    pub search: Option<String>,
+}
+
+impl Listing {
+    /// `LIKE` pattern matching the search text anywhere, to be used with `escape('\\')`
+    pub fn pattern(&self) -> Option<String> {
+        self.search.as_ref().map(|search| {
+            let escaped = search.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+            format!("%{}%", escaped)
+        })
+    }
+}
@@ -4,3 +4,10 @@ pub mod interface;
 pub mod user_actions;
 pub mod user_requests;
 pub(crate) mod constants;
+pub mod action_runs;
+pub mod api_tokens;
+pub mod rfid_tags;
+pub mod user_bundle;
+pub mod listing;
+pub mod events;
+pub mod validation;
@@ -0,0 +1,50 @@
+use chrono::NaiveDateTime;
+use diesel::{RunQueryDsl, SqliteConnection};
+use diesel::prelude::*;
+use diesel::r2d2::{ConnectionManager, PooledConnection};
+use serde_derive::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::schema::rfid_tags::dsl::*;
+
+/// An RFID tag that logs its user in when scanned
+#[derive(Queryable, Identifiable, Serialize, Deserialize, Debug, ToSchema)]
+#[diesel(table_name = crate::schema::rfid_tags)]
+pub struct RfidTag {
+    pub id: i32,
+    pub user_id: i32,
+    pub rfid_uid: String,
+    pub created_on: NaiveDateTime,
+}
+
+#[derive(Insertable, Deserialize, Debug)]
+#[diesel(table_name = crate::schema::rfid_tags)]
+pub struct NewRfidTag {
+    pub user_id: i32,
+    pub rfid_uid: String,
+}
+
+impl RfidTag {
+    pub fn get_by_uid(uid: &str, conn: &mut PooledConnection<ConnectionManager<SqliteConnection>>) -> Result<RfidTag, diesel::result::Error> {
+        rfid_tags
+            .filter(rfid_uid.eq(uid))
+            .first::<RfidTag>(conn)
+    }
+
+    pub fn get_all_by_user_id(uid: i32, conn: &mut PooledConnection<ConnectionManager<SqliteConnection>>) -> Result<Vec<RfidTag>, diesel::result::Error> {
+        rfid_tags
+            .filter(user_id.eq(uid))
+            .load::<RfidTag>(conn)
+    }
+
+    pub fn create(new_tag: NewRfidTag, conn: &mut PooledConnection<ConnectionManager<SqliteConnection>>) -> Result<usize, diesel::result::Error> {
+        diesel::insert_into(rfid_tags)
+            .values(&new_tag)
+            .execute(conn)
+    }
+
+    pub fn delete_by_user_id_and_uid(uid: i32, tag_uid: &str, conn: &mut PooledConnection<ConnectionManager<SqliteConnection>>) -> Result<usize, diesel::result::Error> {
+        diesel::delete(rfid_tags.filter(user_id.eq(uid)).filter(rfid_uid.eq(tag_uid)))
+            .execute(conn)
+    }
+}
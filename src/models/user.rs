@@ -1,12 +1,22 @@
 use chrono::{NaiveDate, NaiveDateTime};
-use diesel::{RunQueryDsl, SqliteConnection};
+use diesel::{AsExpression, FromSqlRow, RunQueryDsl, SqliteConnection};
+use diesel::backend::Backend;
+use diesel::deserialize::{self, FromSql};
 use diesel::prelude::*;
 use diesel::r2d2::{ConnectionManager, PooledConnection};
+use diesel::serialize::{self, IsNull, Output, ToSql};
+use diesel::sql_types::Text;
+use diesel::sqlite::Sqlite;
+use serde::Serializer;
 use serde_derive::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use validator::Validate;
 
-use crate::schema::user_users::dsl::*;
+use crate::models::listing::Listing;
+use crate::models::validation::{keyboard_layout, language_tag, MAX_THEME, not_blank, not_in_future};
+use crate::schema::user_users::dsl::{self, user_users};
 
-#[derive(Queryable, Identifiable, Serialize, Deserialize, Debug)]
+#[derive(Queryable, Identifiable, Serialize, Deserialize, Clone, Debug, ToSchema)]
 #[diesel(table_name = crate::schema::user_users)]
 pub struct User {
     pub id: i32,
@@ -16,26 +26,56 @@ pub struct User {
     pub language: String,
     pub keyboard: String,
     pub created_on: NaiveDateTime,
+    /// Only exposed as `has_pin`
+    #[schema(value_type = bool)]
+    #[serde(rename = "has_pin", serialize_with = "serialize_has_pin", skip_deserializing)]
+    pub pin_hash: Option<String>,
+    pub role: Role,
 }
 
-#[derive(Insertable, AsChangeset, Deserialize, Serialize, Debug)]
+/// What a profile is allowed to change, see `api::permissions`
+#[derive(AsExpression, FromSqlRow, Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, ToSchema)]
+#[diesel(sql_type = Text)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    Admin,
+    #[default]
+    Member,
+    Child,
+    Guest,
+}
+
+#[derive(Insertable, AsChangeset, Deserialize, Serialize, Debug, ToSchema, Validate)]
 #[diesel(table_name = crate::schema::user_users)]
 pub struct NewUser {
+    #[validate(length(max = 64), custom(function = "not_blank"))]
     pub username: String,
+    #[validate(custom(function = "not_in_future"))]
     pub birthday: chrono::NaiveDate,
+    #[validate(range(min = 1, max = MAX_THEME))]
     pub theme: i32,
+    #[validate(custom(function = "language_tag"))]
     pub language: String,
+    #[validate(custom(function = "keyboard_layout"))]
     pub keyboard: String,
+    #[serde(default)]
+    pub role: Role,
 }
 
-#[derive(AsChangeset, Deserialize, Serialize)]
+#[derive(AsChangeset, Deserialize, Serialize, ToSchema, Validate)]
 #[diesel(table_name = crate::schema::user_users)]
 pub struct UserChangeset {
+    #[validate(length(max = 64), custom(function = "not_blank"))]
     pub username: Option<String>,
+    #[validate(custom(function = "not_in_future"))]
     pub birthday: Option<chrono::NaiveDate>,
+    #[validate(range(min = 1, max = MAX_THEME))]
     pub theme: Option<i32>,
+    #[validate(custom(function = "language_tag"))]
     pub language: Option<String>,
+    #[validate(custom(function = "keyboard_layout"))]
     pub keyboard: String,
+    pub role: Option<Role>,
 }
 
 impl User {
@@ -43,18 +83,65 @@ impl User {
         user_users.load::<User>(conn)
     }
 
+    /// A page of users and the number of users matching the search
+    pub fn list(listing: &Listing, conn: &mut PooledConnection<ConnectionManager<SqliteConnection>>) -> Result<(Vec<User>, i64), diesel::result::Error> {
+        let pattern = listing.pattern();
+        let filtered = || {
+            let mut query = user_users.into_boxed();
+            if let Some(ref pattern) = pattern {
+                query = query.filter(dsl::username.like(pattern).escape('\\'));
+            }
+            query
+        };
+
+        let total = filtered().count().get_result(conn)?;
+        let query = match (listing.sort.as_str(), listing.descending) {
+            ("username", false) => filtered().order(dsl::username.asc()),
+            ("username", true) => filtered().order(dsl::username.desc()),
+            ("created_on", false) => filtered().order(dsl::created_on.asc()),
+            ("created_on", true) => filtered().order(dsl::created_on.desc()),
+            (_, false) => filtered().order(dsl::id.asc()),
+            (_, true) => filtered().order(dsl::id.desc()),
+        };
+
+        let users = query.then_order_by(dsl::id.asc()).limit(listing.limit).offset(listing.offset).load::<User>(conn)?;
+        Ok((users, total))
+    }
+
     pub fn get_by_id(user_id: i32, conn: &mut PooledConnection<ConnectionManager<SqliteConnection>>) -> Result<User, diesel::result::Error> {
         user_users
-            .filter(id.eq(user_id))
+            .filter(dsl::id.eq(user_id))
             .first::<User>(conn)
     }
 
     pub fn get_by_username(user_username: &str, conn: &mut PooledConnection<ConnectionManager<SqliteConnection>>) -> Result<User, diesel::result::Error> {
         user_users
-            .filter(username.eq(user_username))
+            .filter(dsl::username.eq(user_username))
             .first::<User>(conn)
     }
 
+    pub fn count(conn: &mut PooledConnection<ConnectionManager<SqliteConnection>>) -> Result<i64, diesel::result::Error> {
+        user_users.count().get_result(conn)
+    }
+
+    pub fn count_by_role(user_role: Role, conn: &mut PooledConnection<ConnectionManager<SqliteConnection>>) -> Result<i64, diesel::result::Error> {
+        user_users
+            .filter(dsl::role.eq(user_role))
+            .count()
+            .get_result(conn)
+    }
+
+    /// False until the hub is set up, e.g. right after upgrading from a version without PINs
+    pub fn admin_has_pin(conn: &mut PooledConnection<ConnectionManager<SqliteConnection>>) -> Result<bool, diesel::result::Error> {
+        let admins: i64 = user_users
+            .filter(dsl::role.eq(Role::Admin))
+            .filter(dsl::pin_hash.is_not_null())
+            .count()
+            .get_result(conn)?;
+        Ok(admins > 0)
+    }
+
+    #[allow(clippy::new_ret_no_self)]
     pub fn new(
         new_user_data: NewUser,
         conn: &mut PooledConnection<ConnectionManager<SqliteConnection>>
@@ -65,27 +152,67 @@ impl User {
     }
 
     pub fn delete(user_id: i32, conn: &mut PooledConnection<ConnectionManager<SqliteConnection>>) -> Result<usize, diesel::result::Error> {
-        diesel::delete(user_users.filter(id.eq(user_id)))
+        diesel::delete(user_users.filter(dsl::id.eq(user_id)))
             .execute(conn)
     }
 
     pub fn update(user_id: i32,
                   changes: UserChangeset,
                   conn: &mut PooledConnection<ConnectionManager<SqliteConnection>>) -> Result<usize, diesel::result::Error> {
-        diesel::update(user_users.filter(id.eq(user_id)))
+        diesel::update(user_users.filter(dsl::id.eq(user_id)))
             .set(changes)
             .execute(conn)
     }
 
     pub fn set_theme(user_id: i32, user_theme: i32, conn: &mut PooledConnection<ConnectionManager<SqliteConnection>>) -> Result<usize, diesel::result::Error> {
-        diesel::update(user_users.filter(id.eq(user_id)))
-            .set(theme.eq(user_theme))
+        diesel::update(user_users.filter(dsl::id.eq(user_id)))
+            .set(dsl::theme.eq(user_theme))
+            .execute(conn)
+    }
+
+    pub fn set_pin_hash(user_id: i32, user_pin_hash: Option<String>, conn: &mut PooledConnection<ConnectionManager<SqliteConnection>>) -> Result<usize, diesel::result::Error> {
+        diesel::update(user_users.filter(dsl::id.eq(user_id)))
+            .set(dsl::pin_hash.eq(user_pin_hash))
             .execute(conn)
     }
 
     pub fn set_language(user_id: i32, user_language: &str, conn: &mut PooledConnection<ConnectionManager<SqliteConnection>>) -> Result<usize, diesel::result::Error> {
-        diesel::update(user_users.filter(id.eq(user_id)))
-            .set(language.eq(user_language))
+        diesel::update(user_users.filter(dsl::id.eq(user_id)))
+            .set(dsl::language.eq(user_language))
             .execute(conn)
     }
-}
\ No newline at end of file
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Admin => "admin",
+            Role::Member => "member",
+            Role::Child => "child",
+            Role::Guest => "guest",
+        }
+    }
+}
+
+impl ToSql<Text, Sqlite> for Role {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Sqlite>) -> serialize::Result {
+        out.set_value(self.as_str());
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<Text, Sqlite> for Role {
+    fn from_sql(bytes: <Sqlite as Backend>::RawValue<'_>) -> deserialize::Result<Self> {
+        match <String as FromSql<Text, Sqlite>>::from_sql(bytes)?.as_str() {
+            "admin" => Ok(Role::Admin),
+            "member" => Ok(Role::Member),
+            "child" => Ok(Role::Child),
+            "guest" => Ok(Role::Guest),
+            other => Err(format!("Unknown role {}", other).into()),
+        }
+    }
+}
+
+fn serialize_has_pin<S: Serializer>(user_pin_hash: &Option<String>, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_bool(user_pin_hash.is_some())
+}
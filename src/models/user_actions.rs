@@ -3,10 +3,14 @@ use diesel::{RunQueryDsl, SqliteConnection};
 use diesel::prelude::*;
 use diesel::r2d2::{ConnectionManager, PooledConnection};
 use serde_derive::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use validator::Validate;
 
+use crate::models::listing::Listing;
+use crate::models::validation::{not_blank, type_name as action_type};
 use crate::schema::user_actions::dsl::*;
 
-#[derive(Queryable, Insertable, AsChangeset, Identifiable, Deserialize, Serialize)]
+#[derive(Queryable, Insertable, AsChangeset, Identifiable, Deserialize, Serialize, Clone, Debug, ToSchema)]
 #[diesel(table_name = crate::schema::user_actions)]
 pub struct UserAction {
     pub id: i32,
@@ -17,21 +21,27 @@ pub struct UserAction {
     pub created_on: NaiveDateTime,
 }
 
-#[derive(Insertable, Serialize, Deserialize)]
+#[derive(Insertable, Serialize, Deserialize, ToSchema, Validate)]
 #[diesel(table_name = crate::schema::user_actions)]
 pub struct NewUserAction {
     pub user_id: i32,
+    #[validate(length(max = 64), custom(function = "not_blank"))]
     pub rfid_uid: String,
+    #[validate(length(max = 64), custom(function = "action_type"))]
     pub type_name: String,
+    #[validate(length(max = 65536))]
     pub details: String,
 }
 
-#[derive(AsChangeset, Deserialize, Serialize)]
+#[derive(AsChangeset, Deserialize, Serialize, ToSchema, Validate)]
 #[diesel(table_name = crate::schema::user_actions)]
 pub struct UserActionChangeset {
     pub user_id: i32,
+    #[validate(length(max = 64), custom(function = "not_blank"))]
     pub rfid_uid: String,
+    #[validate(length(max = 64), custom(function = "action_type"))]
     pub type_name: String,
+    #[validate(length(max = 65536))]
     pub details: String,
 }
 
@@ -41,6 +51,37 @@ impl UserAction {
         user_actions.filter(user_id.eq(uid)).load::<UserAction>(conn)
     }
 
+    /// A page of a user's actions and the number of actions matching the search
+    pub fn list_by_user_id(uid: i32, listing: &Listing, conn: &mut PooledConnection<ConnectionManager<SqliteConnection>>) -> Result<(Vec<UserAction>, i64), diesel::result::Error> {
+        let pattern = listing.pattern();
+        let filtered = || {
+            let mut query = user_actions.filter(user_id.eq(uid)).into_boxed();
+            if let Some(ref pattern) = pattern {
+                query = query.filter(rfid_uid.like(pattern).escape('\\').or(type_name.like(pattern).escape('\\')));
+            }
+            query
+        };
+
+        let total = filtered().count().get_result(conn)?;
+        let query = match (listing.sort.as_str(), listing.descending) {
+            ("rfid_uid", false) => filtered().order(rfid_uid.asc()),
+            ("rfid_uid", true) => filtered().order(rfid_uid.desc()),
+            ("type_name", false) => filtered().order(type_name.asc()),
+            ("type_name", true) => filtered().order(type_name.desc()),
+            ("created_on", false) => filtered().order(created_on.asc()),
+            ("created_on", true) => filtered().order(created_on.desc()),
+            (_, false) => filtered().order(id.asc()),
+            (_, true) => filtered().order(id.desc()),
+        };
+
+        let actions = query.then_order_by(id.asc()).limit(listing.limit).offset(listing.offset).load::<UserAction>(conn)?;
+        Ok((actions, total))
+    }
+
+    pub fn get_by_id(action_id: i32, conn: &mut PooledConnection<ConnectionManager<SqliteConnection>>) -> Result<UserAction, diesel::result::Error> {
+        user_actions.filter(id.eq(action_id)).first(conn)
+    }
+
     pub fn get_all_by_rfid_id(rfid: &String, conn: &mut PooledConnection<ConnectionManager<SqliteConnection>>) -> Result<Vec<UserAction>, diesel::result::Error> {
         user_actions.filter(rfid_uid.eq(rfid)).load::<UserAction>(conn)
     }
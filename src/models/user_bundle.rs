@@ -0,0 +1,114 @@
+use std::collections::HashMap;
+
+use chrono::{NaiveDate, NaiveDateTime};
+use serde_derive::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use serde_json::Value;
+use validator::Validate;
+
+use crate::models::constants::ConstantKind;
+use crate::models::user::Role;
+use crate::models::validation::{constant_name, endpoint, keyboard_layout, language_tag, MAX_THEME, not_blank, not_in_future, request_parameters, type_name};
+
+/// Bumped whenever the bundle layout changes in a way older backends can't read
+pub const BUNDLE_VERSION: u32 = 1;
+
+/// A user with everything needed to recreate them on another hub
+#[derive(Serialize, Deserialize, ToSchema, Validate)]
+pub struct UserBundle {
+    pub version: u32,
+    pub exported_on: NaiveDateTime,
+    #[validate(nested)]
+    pub user: BundleUser,
+    #[serde(default)]
+    #[validate(nested)]
+    pub constants: Vec<BundleConstant>,
+    #[serde(default)]
+    #[validate(nested)]
+    pub requests: Vec<BundleRequest>,
+    #[serde(default)]
+    #[validate(nested)]
+    pub actions: Vec<BundleAction>,
+    /// RFID tags the user logs in with
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Validate)]
+pub struct BundleUser {
+    /// Id on the exporting hub, only used to fix up references in action details
+    pub id: i32,
+    #[validate(length(max = 64), custom(function = "not_blank"))]
+    pub username: String,
+    #[validate(range(min = 1, max = MAX_THEME))]
+    pub theme: i32,
+    #[validate(custom(function = "not_in_future"))]
+    pub birthday: NaiveDate,
+    #[validate(custom(function = "language_tag"))]
+    pub language: String,
+    #[validate(custom(function = "keyboard_layout"))]
+    pub keyboard: String,
+    #[serde(default)]
+    pub role: Role,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Validate)]
+pub struct BundleConstant {
+    #[validate(length(max = 128), custom(function = "constant_name"))]
+    pub name: String,
+    /// Plain text, secrets are decrypted for the export and encrypted again on import
+    #[validate(length(max = 65536))]
+    pub value: String,
+    #[serde(default)]
+    pub kind: ConstantKind,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Validate)]
+pub struct BundleRequest {
+    /// Id on the exporting hub, referenced by `http_request` actions
+    pub id: i32,
+    #[validate(length(max = 128), custom(function = "not_blank"))]
+    pub name: String,
+    #[validate(length(max = 2048), custom(function = "endpoint"))]
+    pub endpoint: String,
+    #[validate(length(max = 65536), custom(function = "request_parameters"))]
+    pub parameters: String,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Validate)]
+pub struct BundleAction {
+    #[validate(length(max = 64), custom(function = "not_blank"))]
+    pub rfid_uid: String,
+    #[validate(length(max = 64), custom(function = "type_name"))]
+    pub type_name: String,
+    #[validate(length(max = 65536))]
+    pub details: String,
+}
+
+/// Points `request_id` and `user_id` references in action details, including scene steps,
+/// at the rows created by the import. References to anything outside the bundle are kept.
+pub fn remap_details(details: &mut Value, requests: &HashMap<i32, i32>, user: (i32, i32)) {
+    match details {
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                let id = value.as_i64().and_then(|id| i32::try_from(id).ok());
+
+                match (key.as_str(), id) {
+                    ("request_id", Some(id)) => {
+                        if let Some(new_id) = requests.get(&id) {
+                            *value = Value::from(*new_id);
+                        }
+                    }
+                    ("user_id", Some(id)) if id == user.0 => *value = Value::from(user.1),
+                    _ => remap_details(value, requests, user),
+                }
+            }
+        }
+        Value::Array(values) => {
+            for value in values {
+                remap_details(value, requests, user);
+            }
+        }
+        _ => {}
+    }
+}
@@ -1,11 +1,30 @@
+use std::collections::HashMap;
+
 use diesel::{RunQueryDsl, SqliteConnection};
 use diesel::prelude::*;
 use diesel::r2d2::{ConnectionManager, PooledConnection};
+use http::Method;
 use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
+use utoipa::ToSchema;
+use validator::Validate;
 
+use crate::models::listing::Listing;
+use crate::models::validation::{endpoint as request_endpoint, not_blank, request_parameters};
 use crate::schema::user_requests::dsl::*;
 
-#[derive(Queryable, Insertable, AsChangeset, Identifiable, Deserialize, Serialize)]
+/// Options stored as JSON in the `parameters` column of a user request.
+#[derive(Deserialize, Default)]
+pub struct RequestParameters {
+    pub method: Option<String>,
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    pub body: Option<Value>,
+    /// Timeout in seconds, capped by the request handler
+    pub timeout: Option<u64>,
+}
+
+#[derive(Queryable, Insertable, AsChangeset, Identifiable, Deserialize, Serialize, ToSchema)]
 #[diesel(table_name = crate::schema::user_requests)]
 pub struct UserRequest {
     pub id: i32,
@@ -16,20 +35,26 @@ pub struct UserRequest {
     pub created_on: chrono::NaiveDateTime,
 }
 
-#[derive(Insertable, Serialize, Deserialize)]
+#[derive(Insertable, Serialize, Deserialize, ToSchema, Validate)]
 #[diesel(table_name = crate::schema::user_requests)]
 pub struct NewUserRequest {
     pub user_id: i32,
+    #[validate(length(max = 128), custom(function = "not_blank"))]
     pub name: String,
+    #[validate(length(max = 2048), custom(function = "request_endpoint"))]
     pub endpoint: String,
+    #[validate(length(max = 65536), custom(function = "request_parameters"))]
     pub parameters: String,
 }
 
-#[derive(AsChangeset, Deserialize, Serialize)]
+#[derive(AsChangeset, Deserialize, Serialize, ToSchema, Validate)]
 #[diesel(table_name = crate::schema::user_requests)]
 pub struct UserRequestChangeset {
+    #[validate(length(max = 128), custom(function = "not_blank"))]
     pub name: String,
+    #[validate(length(max = 2048), custom(function = "request_endpoint"))]
     pub endpoint: String,
+    #[validate(length(max = 65536), custom(function = "request_parameters"))]
     pub parameters: String,
 }
 
@@ -38,6 +63,35 @@ impl UserRequest {
         user_requests.filter(user_id.eq(uid)).load::<UserRequest>(conn)
     }
 
+    pub fn get_by_id(request_id: i32, conn: &mut PooledConnection<ConnectionManager<SqliteConnection>>) -> Result<UserRequest, diesel::result::Error> {
+        user_requests.filter(id.eq(request_id)).first::<UserRequest>(conn)
+    }
+
+    /// A page of a user's requests and the number of requests matching the search
+    pub fn list_by_user_id(uid: i32, listing: &Listing, conn: &mut PooledConnection<ConnectionManager<SqliteConnection>>) -> Result<(Vec<UserRequest>, i64), diesel::result::Error> {
+        let pattern = listing.pattern();
+        let filtered = || {
+            let mut query = user_requests.filter(user_id.eq(uid)).into_boxed();
+            if let Some(ref pattern) = pattern {
+                query = query.filter(name.like(pattern).escape('\\').or(endpoint.like(pattern).escape('\\')));
+            }
+            query
+        };
+
+        let total = filtered().count().get_result(conn)?;
+        let query = match (listing.sort.as_str(), listing.descending) {
+            ("name", false) => filtered().order(name.asc()),
+            ("name", true) => filtered().order(name.desc()),
+            ("created_on", false) => filtered().order(created_on.asc()),
+            ("created_on", true) => filtered().order(created_on.desc()),
+            (_, false) => filtered().order(id.asc()),
+            (_, true) => filtered().order(id.desc()),
+        };
+
+        let requests = query.then_order_by(id.asc()).limit(listing.limit).offset(listing.offset).load::<UserRequest>(conn)?;
+        Ok((requests, total))
+    }
+
     pub fn get_all_by_user_id_and_name(uid: i32, constant_name: &String, conn: &mut PooledConnection<ConnectionManager<SqliteConnection>>) -> Result<Vec<UserRequest>, diesel::result::Error> {
         user_requests.filter(user_id.eq(uid).and(name.eq(constant_name))).load::<UserRequest>(conn)
     }
@@ -62,4 +116,26 @@ impl UserRequest {
             .set(changes)
             .execute(conn)
     }
-}
\ No newline at end of file
+}
+
+pub fn parse_parameters(json: &str) -> Result<RequestParameters, String> {
+    if json.trim().is_empty() {
+        return Ok(RequestParameters::default());
+    }
+    serde_json::from_str(json).map_err(|e| e.to_string())
+}
+
+pub fn parse_method(options: &RequestParameters) -> Result<Method, String> {
+    match &options.method {
+        Some(method) => Method::from_bytes(method.to_uppercase().as_bytes())
+            .map_err(|_| format!("Invalid HTTP method: {}", method)),
+        None => Ok(Method::GET),
+    }
+}
+
+/// Checks the `parameters` of a user request before it is saved
+pub fn validate_parameters(json: &str) -> Result<(), String> {
+    let options = parse_parameters(json)
+        .map_err(|e| format!("Must be a JSON object with method, headers, body and timeout: {}", e))?;
+    parse_method(&options).map(|_| ())
+}
@@ -0,0 +1,258 @@
+use std::borrow::Cow;
+
+use chrono::{NaiveDate, Utc};
+use url::Url;
+use validator::{ValidationError, ValidationErrors, ValidationErrorsKind};
+
+use crate::models::user_requests::validate_parameters;
+
+/// Themes offered by the frontend, numbered from 1
+pub const MAX_THEME: i32 = 16;
+
+/// Keyboard layouts the on-screen keyboard ships with
+pub const KEYBOARD_LAYOUTS: &[&str] = &[
+    "english", "german", "french", "spanish", "italian", "portuguese", "dutch",
+    "swedish", "norwegian", "danish", "finnish", "polish", "czech", "turkish", "russian",
+];
+
+fn invalid(code: &'static str, message: impl Into<Cow<'static, str>>) -> ValidationError {
+    ValidationError::new(code).with_message(message.into())
+}
+
+pub fn not_blank(value: &str) -> Result<(), ValidationError> {
+    match value.trim().is_empty() {
+        true => Err(invalid("blank", "Cannot be empty")),
+        false => Ok(()),
+    }
+}
+
+/// PINs are 4 to 8 digits
+pub fn pin(value: &str) -> Result<(), ValidationError> {
+    match (4..=8).contains(&value.len()) && value.chars().all(|c| c.is_ascii_digit()) {
+        true => Ok(()),
+        false => Err(invalid("pin", "PIN must be 4 to 8 digits")),
+    }
+}
+
+/// A BCP 47 language tag like `en`, `en-US` or `zh-Hant-TW`. Only the shape is checked,
+/// not whether the subtags are registered.
+pub fn language_tag(value: &str) -> Result<(), ValidationError> {
+    let mut subtags = value.split('-');
+    let language = subtags.next().unwrap_or_default();
+
+    let valid = (2..=3).contains(&language.len())
+        && language.chars().all(|c| c.is_ascii_alphabetic())
+        && subtags.all(|subtag| (1..=8).contains(&subtag.len()) && subtag.chars().all(|c| c.is_ascii_alphanumeric()));
+
+    match valid {
+        true => Ok(()),
+        false => Err(invalid("language", "Must be a language tag like en-US")),
+    }
+}
+
+pub fn keyboard_layout(value: &str) -> Result<(), ValidationError> {
+    match KEYBOARD_LAYOUTS.contains(&value) {
+        true => Ok(()),
+        false => Err(invalid("keyboard", format!("Must be one of {}", KEYBOARD_LAYOUTS.join(", ")))),
+    }
+}
+
+pub fn not_in_future(value: &NaiveDate) -> Result<(), ValidationError> {
+    match *value > Utc::now().date_naive() {
+        true => Err(invalid("future", "Cannot be in the future")),
+        false => Ok(()),
+    }
+}
+
+/// An http(s) URL, which may contain `{{constant}}` placeholders. A placeholder at the start
+/// may hold the whole base URL.
+pub fn endpoint(value: &str) -> Result<(), ValidationError> {
+    let mut example = String::with_capacity(value.len());
+    let mut rest = value;
+
+    while let Some(start) = rest.find("{{") {
+        let Some(end) = rest[start..].find("}}") else {
+            return Err(invalid("placeholder", "Placeholder is missing its closing }}"));
+        };
+        example.push_str(&rest[..start]);
+        example.push_str(if example.is_empty() { "http://placeholder" } else { "placeholder" });
+        rest = &rest[start + end + 2..];
+    }
+    example.push_str(rest);
+
+    match Url::parse(&example) {
+        Ok(url) if matches!(url.scheme(), "http" | "https") => Ok(()),
+        Ok(_) => Err(invalid("url", "Must be an http or https URL")),
+        Err(e) => Err(invalid("url", format!("Invalid URL: {}", e))),
+    }
+}
+
+pub fn request_parameters(value: &str) -> Result<(), ValidationError> {
+    validate_parameters(value).map_err(|e| invalid("parameters", e))
+}
+
+/// Constant names are used as `{{name}}` placeholders
+pub fn constant_name(value: &str) -> Result<(), ValidationError> {
+    not_blank(value)?;
+    match value.contains('{') || value.contains('}') {
+        true => Err(invalid("constant_name", "Cannot contain { or }")),
+        false => Ok(()),
+    }
+}
+
+/// Action types run by the backend or the frontend, like `http_request`
+pub fn type_name(value: &str) -> Result<(), ValidationError> {
+    match !value.is_empty() && value.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_') {
+        true => Ok(()),
+        false => Err(invalid("type_name", "Must be lowercase letters, digits and underscores")),
+    }
+}
+
+/// Field paths like `requests[0].endpoint` with their messages, sorted by path
+pub fn flatten_errors(errors: &ValidationErrors) -> Vec<(String, String)> {
+    let mut fields = Vec::new();
+    collect_errors("", errors, &mut fields);
+    fields.sort();
+    fields
+}
+
+fn collect_errors(prefix: &str, errors: &ValidationErrors, fields: &mut Vec<(String, String)>) {
+    for (field, kind) in errors.errors() {
+        // Checks on a whole struct are reported on the struct
+        let path = match (prefix, *field) {
+            (prefix, "__all__") => prefix.to_string(),
+            ("", field) => field.to_string(),
+            (prefix, field) => format!("{}.{}", prefix, field),
+        };
+
+        match kind {
+            ValidationErrorsKind::Field(errors) => fields.extend(errors.iter().map(|error| (path.clone(), message(error)))),
+            ValidationErrorsKind::Struct(errors) => collect_errors(&path, errors, fields),
+            ValidationErrorsKind::List(items) => {
+                for (index, errors) in items {
+                    collect_errors(&format!("{}[{}]", path, index), errors, fields);
+                }
+            }
+        }
+    }
+}
+
+/// The message of a custom check, or one built from the parameters of a built-in one
+fn message(error: &ValidationError) -> String {
+    if let Some(message) = &error.message {
+        return message.to_string();
+    }
+
+    let param = |name: &str| error.params.get(name).map(|value| value.to_string());
+    match (error.code.as_ref(), param("min"), param("max")) {
+        ("length", Some(min), Some(max)) => format!("Must be {} to {} characters", min, max),
+        ("length", None, Some(max)) => format!("Must be at most {} characters", max),
+        ("length", Some(min), None) => format!("Must be at least {} characters", min),
+        ("range", Some(min), Some(max)) => format!("Must be between {} and {}", min, max),
+        ("range", None, Some(max)) => format!("Must be at most {}", max),
+        ("range", Some(min), None) => format!("Must be at least {}", min),
+        (code, _, _) => format!("Invalid ({})", code),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use validator::Validate;
+
+    use super::*;
+
+    #[test]
+    fn accepts_language_tags() {
+        for tag in ["en", "deu", "en-US", "zh-Hant-TW", "es-419"] {
+            assert!(language_tag(tag).is_ok(), "{}", tag);
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_language_tags() {
+        for tag in ["", "e", "english", "en_US", "en-", "en--US", "en-toolongsubtag", "1n-US"] {
+            assert!(language_tag(tag).is_err(), "{}", tag);
+        }
+    }
+
+    #[test]
+    fn accepts_endpoints() {
+        for url in [
+            "http://hub.local/api",
+            "https://example.com/lights/{{light}}?on=true",
+            "{{base_url}}/state",
+            "{{base_url}}",
+        ] {
+            assert!(endpoint(url).is_ok(), "{}", url);
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_endpoints() {
+        assert_eq!(endpoint("ftp://example.com").unwrap_err().code, "url");
+        assert_eq!(endpoint("example.com/api").unwrap_err().code, "url");
+        assert_eq!(endpoint("").unwrap_err().code, "url");
+        assert_eq!(endpoint("http://example.com/{{light").unwrap_err().code, "placeholder");
+    }
+
+    #[test]
+    fn checks_request_parameters() {
+        assert!(request_parameters("").is_ok());
+        assert!(request_parameters(r#"{"method": "post", "headers": {"X-Key": "{{key}}"}, "timeout": 5}"#).is_ok());
+        assert!(request_parameters(r#"{"method": "NOT A METHOD"}"#).is_err());
+        assert!(request_parameters(r#"{"headers": []}"#).is_err());
+        assert!(request_parameters("not json").is_err());
+    }
+
+    #[derive(Validate)]
+    struct Item {
+        #[validate(length(min = 1, max = 4))]
+        name: String,
+        #[validate(range(min = 1))]
+        count: i32,
+    }
+
+    #[derive(Validate)]
+    #[validate(schema(function = "valid_range"))]
+    struct Range {
+        from: i32,
+        to: i32,
+    }
+
+    fn valid_range(range: &Range) -> Result<(), ValidationError> {
+        match range.from <= range.to {
+            true => Ok(()),
+            false => Err(invalid("range_order", "From must not be after to")),
+        }
+    }
+
+    #[derive(Validate)]
+    struct Order {
+        #[validate(custom(function = "not_blank"))]
+        title: String,
+        #[validate(nested)]
+        items: Vec<Item>,
+        #[validate(nested)]
+        delivery: Range,
+    }
+
+    #[test]
+    fn flattens_nested_errors_into_paths() {
+        let order = Order {
+            title: " ".to_string(),
+            items: vec![
+                Item { name: "ok".to_string(), count: 1 },
+                Item { name: "too long".to_string(), count: 0 },
+            ],
+            delivery: Range { from: 5, to: 1 },
+        };
+
+        // Checks of a whole struct are reported on its own path
+        assert_eq!(flatten_errors(&order.validate().unwrap_err()), vec![
+            ("delivery".to_string(), "From must not be after to".to_string()),
+            ("items[1].count".to_string(), "Must be at least 1".to_string()),
+            ("items[1].name".to_string(), "Must be 1 to 4 characters".to_string()),
+            ("title".to_string(), "Cannot be empty".to_string()),
+        ]);
+    }
+}
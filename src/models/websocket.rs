@@ -1,10 +1,299 @@
+use std::collections::HashSet;
+
 use serde_derive::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use utoipa::openapi::{ObjectBuilder, RefOr, Schema, SchemaType};
+use utoipa::ToSchema;
+
+/// Version of the WebSocket protocol announced in the `HELLO` frame
+pub const PROTOCOL_VERSION: u8 = 1;
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct WebSocketMessage {
     pub t: Option<String>,
     // Event type
-    pub op: u8,
+    pub op: OpCode,
     // Operation code
-    pub d: Option<serde_json::Value>, // Data
-}
\ No newline at end of file
+    pub d: Option<Value>, // Data
+}
+
+/// Groups events and commands by the part of the system they belong to.
+/// Sent as a number on the wire.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[serde(into = "u8", try_from = "u8")]
+pub enum OpCode {
+    System = 0,
+    Rfid = 1,
+    Bluetooth = 2,
+    Actions = 3,
+    Updates = 4,
+    Protocol = 5,
+}
+
+impl From<OpCode> for u8 {
+    fn from(op: OpCode) -> Self {
+        op as u8
+    }
+}
+
+impl<'s> ToSchema<'s> for OpCode {
+    fn schema() -> (&'s str, RefOr<Schema>) {
+        let schema = ObjectBuilder::new()
+            .schema_type(SchemaType::Integer)
+            .description(Some("0 system, 1 RFID, 2 Bluetooth, 3 actions, 4 updates, 5 protocol"))
+            .enum_values(Some(0..=5))
+            .build();
+
+        ("OpCode", schema.into())
+    }
+}
+
+impl TryFrom<u8> for OpCode {
+    type Error = String;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(OpCode::System),
+            1 => Ok(OpCode::Rfid),
+            2 => Ok(OpCode::Bluetooth),
+            3 => Ok(OpCode::Actions),
+            4 => Ok(OpCode::Updates),
+            5 => Ok(OpCode::Protocol),
+            _ => Err(format!("Unknown op code: {}", value)),
+        }
+    }
+}
+
+/// A frame sent by a client. `op` is kept raw so unknown op codes can be reported back.
+#[derive(Deserialize, Debug)]
+pub struct ClientFrame {
+    pub op: u8,
+    pub t: Option<String>,
+    pub d: Option<Value>,
+    pub nonce: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(tag = "t", content = "d", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ClientCommand {
+    // System
+    Display,
+    ListingUpdate,
+    Update,
+    SimulateRfid { uid: String },
+    SimulateTouch,
+    // Actions and requests
+    ExecuteRequest { id: i32 },
+    // Bluetooth
+    Devices,
+    StartDiscovering,
+    StopDiscovering,
+    Connect { address: String },
+    Disconnect { address: String },
+    Pair { address: String },
+    Unpair { address: String },
+    Trust { address: String },
+    Untrust { address: String },
+    // Protocol
+    Subscribe {
+        #[serde(default)]
+        ops: Vec<OpCode>,
+        #[serde(default)]
+        events: Vec<String>,
+    },
+    Unsubscribe {
+        #[serde(default)]
+        ops: Vec<OpCode>,
+        #[serde(default)]
+        events: Vec<String>,
+    },
+}
+
+/// Every command a client can send, announced in the `HELLO` frame
+pub const CLIENT_COMMANDS: &[(OpCode, &str)] = &[
+    (OpCode::System, "DISPLAY"),
+    (OpCode::System, "LISTING_UPDATE"),
+    (OpCode::System, "UPDATE"),
+    (OpCode::System, "SIMULATE_RFID"),
+    (OpCode::System, "SIMULATE_TOUCH"),
+    (OpCode::Actions, "EXECUTE_REQUEST"),
+    (OpCode::Bluetooth, "DEVICES"),
+    (OpCode::Bluetooth, "START_DISCOVERING"),
+    (OpCode::Bluetooth, "STOP_DISCOVERING"),
+    (OpCode::Bluetooth, "CONNECT"),
+    (OpCode::Bluetooth, "DISCONNECT"),
+    (OpCode::Bluetooth, "PAIR"),
+    (OpCode::Bluetooth, "UNPAIR"),
+    (OpCode::Bluetooth, "TRUST"),
+    (OpCode::Bluetooth, "UNTRUST"),
+    (OpCode::Protocol, "SUBSCRIBE"),
+    (OpCode::Protocol, "UNSUBSCRIBE"),
+];
+
+/// Op codes a client can subscribe to. Protocol frames are always delivered.
+const EVENT_OPS: &[OpCode] = &[OpCode::System, OpCode::Rfid, OpCode::Bluetooth, OpCode::Actions, OpCode::Updates];
+
+/// Which broadcast events a client receives. Until the first `SUBSCRIBE` or `UNSUBSCRIBE`
+/// a client receives everything.
+#[derive(Serialize, Clone, Debug, Default, ToSchema)]
+pub struct Subscriptions {
+    filtered: bool,
+    ops: HashSet<OpCode>,
+    events: HashSet<String>,
+    muted_events: HashSet<String>,
+}
+
+impl Subscriptions {
+    pub fn wants(&self, message: &WebSocketMessage) -> bool {
+        if message.op == OpCode::Protocol {
+            return true;
+        }
+
+        let event = message.t.as_deref().unwrap_or_default();
+        if self.muted_events.contains(event) {
+            return false;
+        }
+
+        !self.filtered || self.ops.contains(&message.op) || self.events.contains(event)
+    }
+
+    /// Adds op codes and event types. The first subscription replaces the default of receiving everything.
+    pub fn subscribe(&mut self, ops: Vec<OpCode>, events: Vec<String>) {
+        if !self.filtered {
+            self.filtered = true;
+            self.ops.clear();
+            self.events.clear();
+        }
+
+        for event in &events {
+            self.muted_events.remove(event);
+        }
+        self.ops.extend(ops);
+        self.events.extend(events);
+    }
+
+    /// Removes op codes and event types. Event types stay muted even if their op code is subscribed.
+    pub fn unsubscribe(&mut self, ops: Vec<OpCode>, events: Vec<String>) {
+        if !self.filtered {
+            self.filtered = true;
+            self.ops = EVENT_OPS.iter().copied().collect();
+        }
+
+        for op in &ops {
+            self.ops.remove(op);
+        }
+        for event in events {
+            self.events.remove(&event);
+            self.muted_events.insert(event);
+        }
+    }
+}
+
+/// Why a client frame was rejected
+#[derive(Debug)]
+pub enum FrameError {
+    InvalidJson(String),
+    UnknownOp(u8),
+    UnknownCommand(String),
+    InvalidPayload(String),
+}
+
+impl FrameError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            FrameError::InvalidJson(_) => "INVALID_JSON",
+            FrameError::UnknownOp(_) => "UNKNOWN_OP",
+            FrameError::UnknownCommand(_) => "UNKNOWN_COMMAND",
+            FrameError::InvalidPayload(_) => "INVALID_PAYLOAD",
+        }
+    }
+
+    pub fn message(&self) -> String {
+        match self {
+            FrameError::InvalidJson(e) => format!("Malformed JSON: {}", e),
+            FrameError::UnknownOp(op) => format!("Unknown op code: {}", op),
+            FrameError::UnknownCommand(t) => format!("Unknown command: {}", t),
+            FrameError::InvalidPayload(e) => format!("Invalid payload: {}", e),
+        }
+    }
+}
+
+impl ClientFrame {
+    pub fn parse(text: &str) -> Result<Self, FrameError> {
+        serde_json::from_str::<ClientFrame>(text).map_err(|e| FrameError::InvalidJson(e.to_string()))
+    }
+
+    /// Resolves the frame into a command, checking that `t` exists for the given op code
+    pub fn command(&self) -> Result<ClientCommand, FrameError> {
+        let op = OpCode::try_from(self.op).map_err(|_| FrameError::UnknownOp(self.op))?;
+        let t = self.t.as_deref().unwrap_or_default();
+
+        if !CLIENT_COMMANDS.contains(&(op, t)) {
+            return Err(FrameError::UnknownCommand(t.to_string()));
+        }
+
+        serde_json::from_value::<ClientCommand>(json!({ "t": t, "d": self.d }))
+            .map_err(|e| FrameError::InvalidPayload(e.to_string()))
+    }
+}
+
+/// Frames describing the protocol itself, sent on op code 5
+#[derive(Serialize, Debug)]
+#[serde(tag = "t", content = "d", rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ServerEvent {
+    Hello(Hello),
+    Ack(Ack),
+    Error(CommandError),
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+pub struct Hello {
+    pub version: u8,
+    pub commands: Vec<CommandInfo>,
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+pub struct Ack {
+    /// Copied from the command
+    pub nonce: Option<String>,
+    pub command: String,
+    /// Depends on the command
+    #[schema(value_type = Option<Object>)]
+    pub result: Option<Value>,
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+pub struct CommandError {
+    pub nonce: Option<String>,
+    /// `null` if the frame couldn't be read as a command
+    pub command: Option<String>,
+    pub code: String,
+    pub message: String,
+}
+
+#[derive(Serialize, Debug, ToSchema)]
+pub struct CommandInfo {
+    pub op: OpCode,
+    pub t: &'static str,
+}
+
+impl ServerEvent {
+    pub fn hello() -> Self {
+        ServerEvent::Hello(Hello {
+            version: PROTOCOL_VERSION,
+            commands: CLIENT_COMMANDS.iter().map(|&(op, t)| CommandInfo { op, t }).collect(),
+        })
+    }
+}
+
+impl From<ServerEvent> for WebSocketMessage {
+    fn from(event: ServerEvent) -> Self {
+        let mut value = serde_json::to_value(event).unwrap_or(Value::Null);
+
+        WebSocketMessage {
+            t: value.get("t").and_then(|t| t.as_str()).map(str::to_string),
+            op: OpCode::Protocol,
+            d: value.get_mut("d").map(Value::take),
+        }
+    }
+}
@@ -1,3 +1,4 @@
+#[allow(dead_code)]
 pub mod wifi_scan;
 pub mod interfaces;
 pub mod getifaddrs;
\ No newline at end of file
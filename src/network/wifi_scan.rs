@@ -13,7 +13,7 @@ pub struct Wifi {
 }
 
 pub async fn scan() -> Result<Vec<Wifi>, Error> {
-    const PATH_ENV: &'static str = "PATH";
+    const PATH_ENV: &str = "PATH";
     let path_system = "/usr/sbin:/sbin";
     let path = env::var_os(PATH_ENV).map_or(path_system.to_string(), |v| {
         format!("{}:{}", v.to_string_lossy().into_owned(), path_system)
@@ -53,7 +53,7 @@ fn parse_iw_dev(interfaces: &str) -> Result<String, Error> {
         .last()
         .ok_or(Error::NoValue)?
         .split("\n")
-        .nth(0)
+        .next()
         .ok_or(Error::NoValue)
         .map(|text| text.to_string())
 }
@@ -1,11 +1,48 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    action_runs (id) {
+        id -> Integer,
+        user_id -> Integer,
+        action_id -> Nullable<Integer>,
+        request_id -> Nullable<Integer>,
+        trigger_source -> Text,
+        trigger_detail -> Nullable<Text>,
+        started_on -> Timestamp,
+        finished_on -> Timestamp,
+        outcome -> Text,
+        status_code -> Nullable<Integer>,
+        response -> Nullable<Text>,
+        error -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    api_tokens (id) {
+        id -> Integer,
+        name -> Text,
+        token_hash -> Text,
+        created_on -> Timestamp,
+        last_used_on -> Nullable<Timestamp>,
+    }
+}
+
 diesel::table! {
     constants (id) {
         id -> Integer,
         name -> Text,
-        user_id -> Integer,
+        user_id -> Nullable<Integer>,
         value -> Text,
+        kind -> Text,
+    }
+}
+
+diesel::table! {
+    rfid_tags (id) {
+        id -> Integer,
+        user_id -> Integer,
+        rfid_uid -> Text,
+        created_on -> Timestamp,
     }
 }
 
@@ -40,15 +77,22 @@ diesel::table! {
         language -> Text,
         keyboard -> Text,
         created_on -> Timestamp,
+        pin_hash -> Nullable<Text>,
+        role -> Text,
     }
 }
 
+diesel::joinable!(action_runs -> user_users (user_id));
 diesel::joinable!(constants -> user_users (user_id));
+diesel::joinable!(rfid_tags -> user_users (user_id));
 diesel::joinable!(user_actions -> user_users (user_id));
 diesel::joinable!(user_requests -> user_users (user_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
+    action_runs,
+    api_tokens,
     constants,
+    rfid_tags,
     user_actions,
     user_requests,
     user_users,